@@ -1,37 +1,28 @@
 // Coinbase transaction validation module
-// Validates block rewards according to the ATMN halving schedule
+// Validates block rewards against the ATMN emission schedule
 
 use anyhow::{Result, anyhow};
+use atmn_core::chain_params::{ChainParams, REWARD_SCHEDULE, SATOSHI_PER_ATMN};
+use atmn_core::types::Amount;
 
-/// Block reward schedule for ATMN
-/// - Blocks 1-525,600 (Year 1): 50 ATMN per block
-/// - Blocks 525,601-1,051,200 (Year 2): 25 ATMN per block
-/// - Blocks 1,051,201-2,628,000 (Year 2-3): 12.5 ATMN per block
-/// - Blocks 2,628,001+ (Year 4+): 6.25 ATMN per block
+// Block rewards come from ChainParams; this module only converts between
+// satoshis and the f64 ATMN amounts the API works with
 
-const INITIAL_REWARD: f64 = 50.0;
-const HALVING_1_HEIGHT: u64 = 525_600;   // After Year 1
-const HALVING_2_HEIGHT: u64 = 1_051_200; // After Year 2
-const HALVING_3_HEIGHT: u64 = 2_628_000; // After Year 3
+fn params() -> ChainParams {
+    ChainParams::mainnet()
+}
+
+fn to_atmn(amount: Amount) -> f64 {
+    amount as f64 / SATOSHI_PER_ATMN as f64
+}
+
+fn to_satoshis(atmn: f64) -> Amount {
+    (atmn * SATOSHI_PER_ATMN as f64).round() as Amount
+}
 
 /// Calculate the correct block reward for a given block height
 pub fn calculate_block_reward(height: u64) -> f64 {
-    if height == 0 {
-        // Genesis block has no reward
-        return 0.0;
-    } else if height <= HALVING_1_HEIGHT {
-        // Year 1: 50 ATMN
-        INITIAL_REWARD
-    } else if height <= HALVING_2_HEIGHT {
-        // Year 2: 25 ATMN (first halving)
-        INITIAL_REWARD / 2.0
-    } else if height <= HALVING_3_HEIGHT {
-        // Year 2-3: 12.5 ATMN (second halving)
-        INITIAL_REWARD / 4.0
-    } else {
-        // Year 4+: 6.25 ATMN (third halving, permanent)
-        INITIAL_REWARD / 8.0
-    }
+    to_atmn(params().get_block_reward(height))
 }
 
 /// Validate that a coinbase transaction has the correct reward amount
@@ -49,18 +40,16 @@ pub fn validate_coinbase_reward(
     coinbase_amount: f64,
     total_fees: f64,
 ) -> Result<()> {
-    let expected_reward = calculate_block_reward(height);
-    let max_allowed = expected_reward + total_fees;
-    
-    // Allow small floating point tolerance (0.00001 ATMN)
-    let tolerance = 0.00001;
+    let expected_reward = params().get_block_reward(height);
+    let max_allowed = expected_reward.saturating_add(to_satoshis(total_fees));
     
-    if coinbase_amount > max_allowed + tolerance {
+    // Compare in satoshis so float rounding can't let extra coins through
+    if to_satoshis(coinbase_amount) > max_allowed {
         return Err(anyhow!(
             "Coinbase amount {} exceeds maximum allowed {} (reward: {}, fees: {})",
             coinbase_amount,
-            max_allowed,
-            expected_reward,
+            to_atmn(max_allowed),
+            to_atmn(expected_reward),
             total_fees
         ));
     }
@@ -73,24 +62,24 @@ pub fn validate_coinbase_reward(
 
 /// Get the total emission for a block range
 pub fn calculate_total_emission(start_height: u64, end_height: u64) -> f64 {
-    let mut total = 0.0;
-    for height in start_height..=end_height {
-        total += calculate_block_reward(height);
+    if start_height > end_height {
+        return 0.0;
     }
-    total
+    let params = params();
+    let before = match start_height {
+        0 => 0,
+        h => params.issued_supply(h - 1),
+    };
+    to_atmn(params.issued_supply(end_height) - before)
 }
 
 /// Get reward era information for a given height
 pub fn get_reward_era(height: u64) -> (u64, &'static str, f64) {
-    if height <= HALVING_1_HEIGHT {
-        (1, "Year 1", 50.0)
-    } else if height <= HALVING_2_HEIGHT {
-        (2, "Year 2", 25.0)
-    } else if height <= HALVING_3_HEIGHT {
-        (3, "Year 2-3", 12.5)
-    } else {
-        (4, "Year 4+", 6.25)
-    }
+    const LABELS: [&str; 4] = ["Year 1", "Year 2", "Years 3-5", "Year 6+"];
+    let era = REWARD_SCHEDULE.iter()
+        .position(|&(last, _)| height <= last)
+        .unwrap_or(REWARD_SCHEDULE.len() - 1);
+    (era as u64 + 1, LABELS[era], to_atmn(REWARD_SCHEDULE[era].1))
 }
 
 #[cfg(test)]
@@ -100,7 +89,7 @@ mod tests {
     #[test]
     fn test_block_rewards() {
         // Genesis block
-        assert_eq!(calculate_block_reward(0), 0.0);
+        assert_eq!(calculate_block_reward(0), 50_000_000.0);
         
         // Year 1
         assert_eq!(calculate_block_reward(1), 50.0);
@@ -148,6 +137,9 @@ mod tests {
         // Across halving boundary
         let emission = calculate_total_emission(525_600, 525_601);
         assert_eq!(emission, 75.0); // 50 + 25
+        
+        // Genesis subsidy
+        assert_eq!(calculate_total_emission(0, 0), 50_000_000.0);
     }
 
     #[test]
@@ -3,6 +3,8 @@ use crate::models::{Wallet, Transaction, MiningWorker, MasterWallet};
 use uuid::Uuid;
 use chrono::Utc;
 use anyhow::Result;
+use atmn_core::difficulty::{self, DifficultySample};
+use atmn_core::ChainParams;
 
 pub async fn init_db(_pool: &SqlitePool) -> Result<()> {
     Ok(())
@@ -368,6 +370,32 @@ pub async fn get_current_height(pool: &SqlitePool) -> Result<u64> {
     Ok(result as u64)
 }
 
+/// Timestamp and bits of blocks from `start_height` up to the tip, oldest first
+pub async fn get_difficulty_samples(pool: &SqlitePool, start_height: u64) -> Result<Vec<DifficultySample>> {
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT timestamp, difficulty FROM blocks WHERE height >= ? ORDER BY height ASC"
+    )
+    .bind(start_height as i64)
+    .fetch_all(pool)
+    .await?;
+    
+    Ok(rows
+        .into_iter()
+        .map(|(timestamp, bits)| DifficultySample {
+            timestamp: timestamp as u32,
+            bits: bits as u32,
+        })
+        .collect())
+}
+
+/// Difficulty bits the block at `height` must use, from the stored chain
+pub async fn next_block_bits(pool: &SqlitePool, height: u64) -> Result<u32> {
+    let params = ChainParams::mainnet();
+    let start = difficulty::window_start(&params, height);
+    let samples = get_difficulty_samples(pool, start).await?;
+    Ok(difficulty::next_work_required(&params, height, &samples))
+}
+
 /// Get block by height
 pub async fn get_block_by_height(pool: &SqlitePool, height: u64) -> Result<serde_json::Value> {
     let result: (String, i64, i64, i64, i64, Option<String>, Option<String>) = sqlx::query_as(
@@ -437,35 +465,6 @@ pub async fn get_coinbase_amount(pool: &SqlitePool, block_hash: &str) -> Result<
     Ok(amount.unwrap_or(0.0))
 }
 
-/// Get block timestamp by height for difficulty adjustment
-pub async fn get_block_timestamp(pool: &SqlitePool, height: u64) -> Result<u32> {
-    let timestamp: Option<i64> = sqlx::query_scalar(
-        "SELECT timestamp FROM blocks WHERE height = ?"
-    )
-    .bind(height as i64)
-    .fetch_optional(pool)
-    .await?;
-    
-    Ok(timestamp.unwrap_or(0) as u32)
-}
-
-/// Get blocks for difficulty calculation (last 2016 blocks)
-pub async fn get_blocks_for_difficulty(pool: &SqlitePool, end_height: u64) -> Result<Vec<(u64, u32)>> {
-    let start_height = end_height.saturating_sub(2015);
-    
-    let blocks: Vec<(i64, i64)> = sqlx::query_as(
-        "SELECT height, timestamp FROM blocks 
-         WHERE height >= ? AND height <= ?
-         ORDER BY height ASC"
-    )
-    .bind(start_height as i64)
-    .bind(end_height as i64)
-    .fetch_all(pool)
-    .await?;
-    
-    Ok(blocks.iter().map(|(h, t)| (*h as u64, *t as u32)).collect())
-}
-
 // Batch operations for performance
 pub async fn batch_create_utxos(
     pool: &SqlitePool,
@@ -5,6 +5,8 @@ use crate::models::ErrorResponse;
 use crate::mining_manager::MiningManager;
 use crate::db;
 use crate::coinbase;
+use atmn_core::{encoding, script, Block, ChainParams};
+use atmn_core::fee_estimator::DEFAULT_CONFIRMATION_TARGET;
 use std::sync::Arc;
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +22,11 @@ pub struct SubmitBlockRequest {
     pub hash: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct FeeEstimateQuery {
+    pub target_blocks: Option<usize>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct MiningStatusResponse {
     pub is_mining: bool,
@@ -47,10 +54,10 @@ pub async fn start_mining(
     req: web::Json<StartMiningRequest>,
 ) -> HttpResponse {
     // Validate miner address
-    if !req.miner_address.starts_with("ATMN_") {
+    if script::address_to_script_pubkey(&req.miner_address, &ChainParams::mainnet()).is_none() {
         return HttpResponse::BadRequest().json(ErrorResponse {
             error: "INVALID_ADDRESS".to_string(),
-            message: "Miner address must start with ATMN_".to_string(),
+            message: "Miner address must be a mainnet address".to_string(),
         });
     }
 
@@ -80,12 +87,10 @@ pub async fn start_mining(
 
     // Start mining
     let threads = req.threads.unwrap_or(1);
-    let difficulty_bits = 0x1d00ffff; // Default difficulty
 
     match mining_manager.start_mining(
         req.miner_address.clone(),
         threads,
-        difficulty_bits,
         prev_hash,
         height + 1,
     ) {
@@ -174,17 +179,22 @@ pub async fn get_block_template(
         }
     };
 
+    let bits = match db::next_block_bits(pool.get_ref(), height + 1).await {
+        Ok(bits) => bits,
+        Err(e) => {
+            log::error!("Failed to compute difficulty: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "DATABASE_ERROR".to_string(),
+                message: "Failed to compute difficulty".to_string(),
+            });
+        }
+    };
+
     // Get pending transactions
     let transactions = mining_manager.get_pending_transactions(100);
     let tx_ids: Vec<String> = transactions
         .iter()
-        .map(|tx| {
-            // Generate transaction ID (hash of serialized tx)
-            use sha2::{Sha256, Digest};
-            let serialized = bincode::serialize(tx).unwrap_or_default();
-            let hash = Sha256::digest(&serialized);
-            hex::encode(hash)
-        })
+        .map(|tx| tx.hash().to_string())
         .collect();
 
     // Calculate merkle root
@@ -202,7 +212,7 @@ pub async fn get_block_template(
         prev_block_hash: prev_hash,
         merkle_root,
         timestamp: chrono::Utc::now().timestamp() as u32,
-        bits: 0x1d00ffff,
+        bits,
         height: height + 1,
         transactions: tx_ids,
     };
@@ -241,6 +251,17 @@ pub async fn submit_block(
 
     log::info!("Validating block at height {}", block_height);
 
+    // The block itself, hex of its consensus encoding
+    let block: Block = match hex::decode(&req.block_data).ok().and_then(|bytes| encoding::deserialize(&bytes).ok()) {
+        Some(block) => block,
+        None => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "INVALID_BLOCK".to_string(),
+                message: "Block data must be a hex-encoded block".to_string(),
+            });
+        }
+    };
+
     // TODO: Parse block_data to extract transactions
     // For now, we'll implement the validation framework
     
@@ -327,6 +348,14 @@ pub async fn submit_block(
             let expected_reward = coinbase::calculate_block_reward(block_height);
             log::info!("Block accepted and processed! Hash: {}, Height: {}, Reward: {}", 
                       req.hash, block_height, expected_reward);
+
+            // Mined transactions leave the pool and feed fee estimation
+            mining_manager.connect_block(&block);
+
+            // Background mining moves on to the new tip
+            if let Ok(tip_hash) = <[u8; 32]>::try_from(hex::decode(&req.hash).unwrap_or_default()) {
+                mining_manager.notify_new_tip(tip_hash);
+            }
             
             HttpResponse::Ok().json(serde_json::json!({
                 "success": true,
@@ -363,6 +392,34 @@ pub async fn get_mempool_stats(
     }))
 }
 
+/// Estimate the fee rate needed to confirm within `target_blocks`
+///
+/// Falls back to the mempool's minimum fee until enough blocks have been seen.
+pub async fn get_fee_estimate(
+    mining_manager: web::Data<Arc<MiningManager>>,
+    query: web::Query<FeeEstimateQuery>,
+) -> HttpResponse {
+    let target_blocks = query.target_blocks.unwrap_or(DEFAULT_CONFIRMATION_TARGET);
+
+    match mining_manager.estimate_fee(target_blocks) {
+        Some(estimate) => HttpResponse::Ok().json(serde_json::json!({
+            "fee_per_kb": estimate.fee_per_kb,
+            "target_blocks": estimate.target_blocks,
+            "confidence": estimate.confidence,
+            "estimated": true
+        })),
+        None => {
+            let (_, _, _, min_fee_per_byte) = mining_manager.get_mempool_stats();
+            HttpResponse::Ok().json(serde_json::json!({
+                "fee_per_kb": min_fee_per_byte * 1000,
+                "target_blocks": target_blocks,
+                "confidence": null,
+                "estimated": false
+            }))
+        }
+    }
+}
+
 // Legacy pool endpoints (keep for backward compatibility)
 
 pub async fn register_worker(
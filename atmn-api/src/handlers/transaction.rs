@@ -1,5 +1,7 @@
 use actix_web::{web, HttpResponse};
 use sqlx::SqlitePool;
+use atmn_core::types::Address;
+use atmn_core::ChainParams;
 use crate::models::{CreateTransactionRequest, CreateTransactionResponse, ErrorResponse};
 use crate::db;
 
@@ -7,6 +9,16 @@ pub async fn create_transaction(
     pool: web::Data<SqlitePool>,
     req: web::Json<CreateTransactionRequest>,
 ) -> HttpResponse {
+    // Reject mistyped recipients; legacy addresses are accepted for existing wallets
+    if let Err(e) = Address::parse(&req.to_address, &ChainParams::mainnet()) {
+        if db::get_wallet(pool.get_ref(), &req.to_address).await.is_err() {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "INVALID_ADDRESS".to_string(),
+                message: format!("{}: {}", req.to_address, e),
+            });
+        }
+    }
+    
     // Check if sender has sufficient balance
     match db::get_wallet(pool.get_ref(), &req.from_address).await {
         Ok(wallet) => {
@@ -2,6 +2,9 @@ use actix_web::{web, HttpResponse};
 use sqlx::SqlitePool;
 use sha2::{Sha256, Digest};
 use hex;
+use atmn_core::tx_builder::parse_secret_key;
+use atmn_core::types::Address;
+use atmn_core::ChainParams;
 use crate::models::{CreateWalletRequest, CreateWalletResponse, ErrorResponse};
 use crate::db;
 
@@ -9,14 +12,23 @@ pub async fn create_wallet(
     pool: web::Data<SqlitePool>,
     req: web::Json<CreateWalletRequest>,
 ) -> HttpResponse {
+    // The address pays to the key's public key hash
+    let secret_key = match parse_secret_key(&req.private_key) {
+        Ok(secret_key) => secret_key,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "INVALID_PRIVATE_KEY".to_string(),
+                message: "private_key must be a 32-byte hex secp256k1 key".to_string(),
+            })
+        }
+    };
+    let address = Address::from_secret_key(&secret_key).encode(&ChainParams::mainnet());
+    
     // Hash the private key
     let mut hasher = Sha256::new();
     hasher.update(req.private_key.as_bytes());
     let key_hash = hex::encode(hasher.finalize());
     
-    // Generate address from hash
-    let address = format!("ATMN_{}", &key_hash[..40]);
-    
     match db::create_wallet(pool.get_ref(), &address, &key_hash).await {
         Ok(wallet) => HttpResponse::Ok().json(CreateWalletResponse {
             address: wallet.wallet_address,
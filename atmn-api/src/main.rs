@@ -12,9 +12,14 @@ use actix_cors::Cors;
 use dotenv::dotenv;
 use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
 use std::env;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use mining_manager::MiningManager;
 
+/// How often the mempool and fee estimates are saved while the server runs
+const MEMPOOL_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
@@ -29,14 +34,42 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to create pool");
 
-    // Create mining manager
-    let mining_manager = Arc::new(MiningManager::new(database_url.clone()));
+    let data_dir = PathBuf::from(env::var("ATMN_DATA_DIR").unwrap_or_else(|_| "./data".to_string()));
+    std::fs::create_dir_all(&data_dir)?;
+
+    // Create mining manager and restore the mempool from the last run
+    let mining_manager = Arc::new(MiningManager::new(database_url.clone(), &data_dir));
+    match mining_manager.load_mempool() {
+        Ok(stats) => log::info!(
+            "Mempool restored: {} transactions ({} invalid, {} expired dropped)",
+            stats.accepted, stats.rejected, stats.expired
+        ),
+        Err(e) => log::warn!("Starting with an empty mempool: {}", e),
+    }
+    if let Err(e) = mining_manager.load_fee_estimates() {
+        log::warn!("Starting without fee estimation history: {}", e);
+    }
+
+    let saver = mining_manager.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MEMPOOL_SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = saver.save_mempool() {
+                log::warn!("{}", e);
+            }
+            if let Err(e) = saver.save_fee_estimates() {
+                log::warn!("{}", e);
+            }
+        }
+    });
 
     log::info!("Starting ATMN API server...");
     log::info!("Database: {}", database_url);
     log::info!("Mining manager initialized");
 
-    HttpServer::new(move || {
+    let app_mining_manager = mining_manager.clone();
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -45,7 +78,7 @@ async fn main() -> std::io::Result<()> {
 
         App::new()
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(mining_manager.clone()))
+            .app_data(web::Data::new(app_mining_manager.clone()))
             .wrap(Logger::default())
             .wrap(cors)
             .route("/health", web::get().to(handlers::health::health_check))
@@ -81,6 +114,7 @@ async fn main() -> std::io::Result<()> {
             .route("/api/mining/template", web::get().to(handlers::mining::get_block_template))
             .route("/api/mining/submit", web::post().to(handlers::mining::submit_block))
             .route("/api/mempool/stats", web::get().to(handlers::mining::get_mempool_stats))
+            .route("/api/mempool/fee-estimate", web::get().to(handlers::mining::get_fee_estimate))
             // Blockchain query endpoints (NEW)
             .route("/api/blocks/latest", web::get().to(handlers::blockchain::get_latest_blocks))
             .route("/api/blocks/range", web::get().to(handlers::blockchain::get_block_range))
@@ -108,5 +142,16 @@ async fn main() -> std::io::Result<()> {
     })
     .bind("127.0.0.1:8000")?
     .run()
-    .await
+    .await;
+
+    // Save once more on shutdown
+    match mining_manager.save_mempool() {
+        Ok(count) => log::info!("Saved {} mempool transactions", count),
+        Err(e) => log::warn!("{}", e),
+    }
+    if let Err(e) = mining_manager.save_fee_estimates() {
+        log::warn!("{}", e);
+    }
+
+    server
 }
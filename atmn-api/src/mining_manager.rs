@@ -1,13 +1,29 @@
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::task::JoinHandle;
 use atmn_core::{
-    Miner, MinerConfig, BlockTemplate,
-    Transaction,
+    MinerConfig, BlockAssembler, JobControl, JobOutcome, MiningJob,
+    Block, Transaction,
     Mempool, MempoolConfig,
+    ChainParams, Consensus, Storage,
+    FeeEstimate, FeeEstimator,
 };
+use atmn_core::fee_estimator::FEE_ESTIMATES_FILE_NAME;
+use atmn_core::mempool::{MempoolLoadStats, MEMPOOL_FILE_NAME};
+use atmn_core::consensus::ValidationContext;
 use atmn_core::types::{BlockHash, TxHash};
 use log::{info, error};
+use crate::db;
+
+/// File name of the core chain database inside the data directory
+const CORE_DB_NAME: &str = "atmn-miner.db";
+
+/// How often a running job's progress is collected
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often the database is checked for blocks that replace the job's tip
+const TIP_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Mining state shared across threads
 #[derive(Debug, Clone)]
@@ -39,16 +55,27 @@ pub struct MiningManager {
     mempool: Arc<Mutex<Mempool>>,
     mining_task: Arc<Mutex<Option<JoinHandle<()>>>>,
     should_stop: Arc<Mutex<bool>>,
+    /// Job the mining task is working on, if any
+    current_job: Arc<Mutex<Option<JobControl>>>,
     database_url: String,
+    /// Core chain database the mempool and block templates are checked against
+    core_db: PathBuf,
+    /// Where the mempool is saved between restarts
+    mempool_path: PathBuf,
+    /// Where the fee estimator's history is saved between restarts
+    fee_estimates_path: PathBuf,
+    /// Builds the templates the mining task works on
+    assembler: BlockAssembler,
 }
 
 impl MiningManager {
-    pub fn new(database_url: String) -> Self {
+    pub fn new(database_url: String, data_dir: &Path) -> Self {
         let mempool_config = MempoolConfig {
             max_size: 50_000,
             max_tx_size: 100_000,
             min_fee_per_byte: 1,
             tx_expiration: 3600,
+            ..MempoolConfig::default()
         };
 
         MiningManager {
@@ -56,7 +83,12 @@ impl MiningManager {
             mempool: Arc::new(Mutex::new(Mempool::with_config(mempool_config))),
             mining_task: Arc::new(Mutex::new(None)),
             should_stop: Arc::new(Mutex::new(false)),
+            current_job: Arc::new(Mutex::new(None)),
             database_url,
+            core_db: data_dir.join(CORE_DB_NAME),
+            mempool_path: data_dir.join(MEMPOOL_FILE_NAME),
+            fee_estimates_path: data_dir.join(FEE_ESTIMATES_FILE_NAME),
+            assembler: BlockAssembler::new(Consensus::new(ChainParams::mainnet())),
         }
     }
 
@@ -65,7 +97,6 @@ impl MiningManager {
         &self,
         miner_address: String,
         thread_count: usize,
-        difficulty_bits: u32,
         prev_block_hash: [u8; 32],
         height: u64,
     ) -> Result<(), String> {
@@ -88,17 +119,22 @@ impl MiningManager {
         let state_clone = Arc::clone(&self.state);
         let mempool_clone = Arc::clone(&self.mempool);
         let should_stop = Arc::clone(&self.should_stop);
+        let current_job = Arc::clone(&self.current_job);
 
         let database_url = self.database_url.clone();
+        let core_db = self.core_db.clone();
+        let assembler = self.assembler.clone();
         let handle = tokio::spawn(async move {
             mining_task(
                 state_clone,
                 mempool_clone,
+                assembler,
                 should_stop,
+                current_job,
                 database_url,
+                core_db,
                 miner_address,
                 thread_count,
-                difficulty_bits,
                 prev_block_hash,
                 height,
             )
@@ -118,8 +154,11 @@ impl MiningManager {
             return Err("Mining not active".to_string());
         }
 
-        // Signal stop
+        // Signal stop and abort the job in progress
         *self.should_stop.lock().unwrap() = true;
+        if let Some(job) = self.current_job.lock().unwrap().as_ref() {
+            job.cancel();
+        }
 
         let blocks_found = state.blocks_found;
         let miner_address = state.miner_address.clone();
@@ -133,20 +172,40 @@ impl MiningManager {
         Ok((blocks_found, miner_address))
     }
 
+    /// Tell the mining task about a new chain tip, so a job still mining
+    /// on the old one starts over
+    pub fn notify_new_tip(&self, tip_hash: [u8; 32]) {
+        if let Some(job) = self.current_job.lock().unwrap().as_ref() {
+            if job.tip_changed(&BlockHash::from_bytes(tip_hash)) {
+                info!("New tip {}, restarting mining", hex::encode(tip_hash));
+            }
+        }
+    }
+
     /// Get current mining state
     pub fn get_state(&self) -> MiningState {
         self.state.lock().unwrap().clone()
     }
 
-    /// Add transaction to mempool
-    pub fn add_transaction(&self, tx: Transaction) -> Result<(), String> {
+    /// Add transaction to mempool if it could be mined on top of the chain in
+    /// `storage`, spending confirmed outputs or those of pooled transactions
+    pub fn add_transaction(&self, tx: Transaction, storage: &Storage) -> Result<(), String> {
+        let consensus = Consensus::new(ChainParams::mainnet());
+        let ctx = consensus.context_for_next_block(storage)
+            .map_err(|e| format!("Failed to read chain tip: {:?}", e))?;
         self.mempool
             .lock()
             .unwrap()
-            .add_transaction(tx)
+            .accept_transaction(tx, &consensus, &ctx, storage)
             .map_err(|e| format!("Failed to add transaction: {:?}", e))
     }
 
+    /// Update the mempool for a block committed to the chain: its
+    /// transactions leave the pool and feed fee estimation
+    pub fn connect_block(&self, block: &Block) {
+        self.mempool.lock().unwrap().connect_block(block);
+    }
+
     /// Get mempool statistics
     pub fn get_mempool_stats(&self) -> (usize, usize, usize, u64) {
         let mempool = self.mempool.lock().unwrap();
@@ -154,11 +213,60 @@ impl MiningManager {
         (
             stats.transaction_count,
             stats.total_size_bytes,
-            50_000,
-            1,
+            stats.max_size,
+            stats.min_fee_per_kb.div_ceil(1000),
         )
     }
 
+    /// Reload the mempool saved by `save_mempool`, revalidating each
+    /// transaction against the core chain database
+    pub fn load_mempool(&self) -> Result<MempoolLoadStats, String> {
+        let storage = Storage::new(&self.core_db)
+            .map_err(|e| format!("Failed to open chain database: {:?}", e))?;
+        let consensus = Consensus::new(ChainParams::mainnet());
+        let ctx = consensus.context_for_next_block(&storage)
+            .map_err(|e| format!("Failed to read chain tip: {:?}", e))?;
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+
+        self.mempool
+            .lock()
+            .unwrap()
+            .load(&self.mempool_path, &consensus, &ctx, &storage, now)
+            .map_err(|e| format!("Failed to load mempool: {:?}", e))
+    }
+
+    /// Save the mempool so pending transactions survive a restart
+    pub fn save_mempool(&self) -> Result<usize, String> {
+        self.mempool
+            .lock()
+            .unwrap()
+            .dump(&self.mempool_path)
+            .map_err(|e| format!("Failed to save mempool: {:?}", e))
+    }
+
+    /// Restore the fee estimator's confirmation history saved by `save_fee_estimates`
+    pub fn load_fee_estimates(&self) -> Result<(), String> {
+        let estimator = FeeEstimator::load(&self.fee_estimates_path)
+            .map_err(|e| format!("Failed to load fee estimates: {:?}", e))?;
+        *self.mempool.lock().unwrap().fee_estimator_mut() = estimator;
+        Ok(())
+    }
+
+    /// Save the fee estimator's confirmation history
+    pub fn save_fee_estimates(&self) -> Result<(), String> {
+        self.mempool
+            .lock()
+            .unwrap()
+            .fee_estimator()
+            .save(&self.fee_estimates_path)
+            .map_err(|e| format!("Failed to save fee estimates: {:?}", e))
+    }
+
+    /// Fee rate expected to confirm within `target_blocks`, if there is enough history
+    pub fn estimate_fee(&self, target_blocks: usize) -> Option<FeeEstimate> {
+        self.mempool.lock().unwrap().estimate_fee(target_blocks)
+    }
+
     /// Get pending transactions for block template
     pub fn get_pending_transactions(&self, limit: usize) -> Vec<Transaction> {
         self.mempool
@@ -211,32 +319,49 @@ async fn get_latest_block_hash(database_url: &str) -> Result<([u8; 32], u64), St
     }
 }
 
+/// Difficulty bits the block at `height` must use, from the API database
+async fn get_next_block_bits(database_url: &str, height: u64) -> Result<u32, String> {
+    use sqlx::SqlitePool;
+    
+    let pool = SqlitePool::connect(database_url)
+        .await
+        .map_err(|e| format!("Database connection failed: {}", e))?;
+    
+    let bits = db::next_block_bits(&pool, height)
+        .await
+        .map_err(|e| format!("Difficulty query failed: {}", e));
+    
+    pool.close().await;
+    bits
+}
+
 /// Background mining task
 async fn mining_task(
     state: Arc<Mutex<MiningState>>,
     mempool: Arc<Mutex<Mempool>>,
+    assembler: BlockAssembler,
     should_stop: Arc<Mutex<bool>>,
+    current_job: Arc<Mutex<Option<JobControl>>>,
     database_url: String,
+    core_db: PathBuf,
     miner_address: String,
     thread_count: usize,
-    difficulty_bits: u32,
     mut prev_block_hash: [u8; 32],
     mut height: u64,
 ) {
     info!(
-        "Mining task started: address={}, threads={}, difficulty={}, height={}",
-        miner_address, thread_count, difficulty_bits, height
+        "Mining task started: address={}, threads={}, height={}",
+        miner_address, thread_count, height
     );
 
-    // Create miner instance
+    // Jobs run until a block is found, mining is stopped or the tip moves
     let config = MinerConfig {
-        max_nonce: 100_000,
         thread_count,
-        update_interval_ms: 5000,
+        update_interval_ms: 1000,
+        ..MinerConfig::default()
     };
-    let mut miner = Miner::with_config(config);
 
-    let start_time = std::time::Instant::now();
+    let start_time = Instant::now();
 
     loop {
         // Check stop signal
@@ -245,109 +370,115 @@ async fn mining_task(
             break;
         }
 
-        // Get pending transactions
-        let transactions = mempool
-            .lock()
-            .unwrap()
-            .get_ordered_transactions(1000);
-
-        // Calculate total fees
-        let total_fees: u64 = transactions
-            .iter()
-            .map(|tx| {
-                let size = tx.inputs.len() * 148 + tx.outputs.len() * 34 + 10;
-                size as u64
-            })
-            .sum();
-
-        // Create coinbase transaction
-        let miner_pubkey = hex::decode(&miner_address.replace("ATMN_", ""))
-            .unwrap_or_else(|_| miner_address.as_bytes().to_vec());
-        
-        let coinbase = atmn_core::miner::create_coinbase_transaction(miner_pubkey, height, total_fees);
-
-        // Build transaction list
-        let mut all_txs = vec![coinbase];
-        all_txs.extend(transactions);
-
-        // Calculate merkle root
-        let merkle_root = match atmn_core::miner::calculate_merkle_root(&all_txs) {
-            Ok(root) => root,
+        // Difficulty follows the chain, so it is recomputed for every template
+        let difficulty_bits = match get_next_block_bits(&database_url, height).await {
+            Ok(bits) => bits,
             Err(e) => {
-                error!("Failed to calculate merkle root: {:?}", e);
+                error!("Failed to compute difficulty: {}", e);
                 break;
             }
         };
 
-        // Create block template
-        let template = BlockTemplate {
-            version: 1,
-            prev_block_hash: BlockHash::from_bytes(prev_block_hash),
-            merkle_root,
+        // Template on the latest known block from the pool's best packages,
+        // rechecked against the core chain's UTXO set. The SQLite chain has
+        // no median-time-past, so the clock is used as is.
+        let ctx = ValidationContext {
+            prev_hash: BlockHash::from_bytes(prev_block_hash),
             height,
-            transactions: all_txs.clone(),
-            difficulty_bits,
-            template_time: chrono::Utc::now().timestamp() as u32,
+            expected_bits: difficulty_bits,
+            median_time_past: 0,
+            now: chrono::Utc::now().timestamp() as u32,
         };
+        let template = match Storage::new(&core_db)
+            .and_then(|storage| assembler.create_template_for(&ctx, &mempool.lock().unwrap(), &storage, &miner_address))
+        {
+            Ok(template) => template,
+            Err(e) => {
+                error!("Failed to build block template: {:?}", e);
+                break;
+            }
+        };
+
+        let job = MiningJob::start(template, config.clone());
+        *current_job.lock().unwrap() = Some(job.control());
+        // A stop request may have come in before the job was registered
+        if *should_stop.lock().unwrap() {
+            job.cancel();
+        }
 
-        // Mine block
-        match miner.mine_block(template) {
-            Ok(result) => {
-                if result.success {
-                    if let Some(block) = result.block {
-                        info!(
-                            "✨ Block found! Height: {}, Hashes: {}",
-                            height, result.hashes_attempted
-                        );
-
-                        // Update state
-                        {
-                            let mut state = state.lock().unwrap();
-                            state.blocks_found += 1;
-                        }
-                        
-                        // Get new prev_hash from database for continuous mining
-                        match get_latest_block_hash(&database_url).await {
-                            Ok((new_hash, new_height)) => {
-                                prev_block_hash = new_hash;
-                                height = new_height + 1;
-                                info!("🔄 Continuing mining on height {}", height);
-                                
-                                // Update state height
-                                {
-                                    let mut state = state.lock().unwrap();
-                                    state.current_height = height;
-                                }
-                                
-                                // Small delay before next block
-                                tokio::time::sleep(Duration::from_millis(500)).await;
-                                continue;
-                            }
-                            Err(e) => {
-                                error!("Failed to get latest block for continuous mining: {}", e);
-                                break;
-                            }
-                        }
-                    }
-                } else {
-                    // No block found, continue
+        let mut last_tip_check = Instant::now();
+        while !job.is_finished() {
+            tokio::time::sleep(JOB_POLL_INTERVAL).await;
+
+            if let Some(progress) = job.progress_updates().try_iter().last() {
+                state.lock().unwrap().hash_rate = progress.hash_rate;
+            }
+
+            // Blocks can also reach the database without going through the API
+            if last_tip_check.elapsed() >= TIP_CHECK_INTERVAL {
+                last_tip_check = Instant::now();
+                if let Ok((tip_hash, _)) = get_latest_block_hash(&database_url).await {
+                    job.tip_changed(&BlockHash::from_bytes(tip_hash));
+                }
+            }
+        }
+        *current_job.lock().unwrap() = None;
+        let hashes = job.progress().hashes;
+
+        match job.wait() {
+            JobOutcome::Found(block) => {
+                info!("✨ Block found! Height: {}, Hashes: {}", height, hashes);
+
+                // Commit the block to the core chain before its transactions
+                // leave the pool
+                if let Err(e) = Storage::new(&core_db).and_then(|storage| storage.put_block(block.height, &block)) {
+                    error!("Failed to store found block: {:?}", e);
+                    break;
                 }
 
-                // Update hash rate
-                let elapsed = start_time.elapsed().as_secs_f64();
-                if elapsed > 0.0 {
-                    let hash_rate = result.hashes_attempted as f64 / elapsed;
-                    state.lock().unwrap().hash_rate = hash_rate;
+                // Update state
+                {
+                    let mut state = state.lock().unwrap();
+                    state.blocks_found += 1;
                 }
+
+                // Mined transactions leave the pool and feed fee estimation
+                mempool.lock().unwrap().connect_block(&block);
             }
-            Err(e) => {
-                error!("Mining error: {:?}", e);
+            JobOutcome::TipChanged => {
+                info!("Chain tip moved, rebuilding the block template");
+            }
+            JobOutcome::Cancelled => {
+                info!("Mining task stopped by request");
                 break;
             }
+            JobOutcome::Exhausted => {
+                // Fresh template with a new timestamp and the latest transactions
+                continue;
+            }
         }
 
-        // Small delay between attempts
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        // Get new prev_hash from database for continuous mining
+        match get_latest_block_hash(&database_url).await {
+            Ok((new_hash, new_height)) => {
+                prev_block_hash = new_hash;
+                height = new_height + 1;
+                info!("🔄 Continuing mining on height {}", height);
+
+                // Update state height
+                {
+                    let mut state = state.lock().unwrap();
+                    state.current_height = height;
+                }
+
+                // Small delay before next block
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Err(e) => {
+                error!("Failed to get latest block for continuous mining: {}", e);
+                break;
+            }
+        }
     }
 
     // Mark mining as stopped
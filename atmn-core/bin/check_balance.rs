@@ -1,7 +1,7 @@
 // atmn-core/bin/check_balance.rs
 // Check wallet balance from UTXO set
 
-use atmn_core::Storage;
+use atmn_core::{ChainParams, Storage};
 use std::env;
 
 fn main() -> anyhow::Result<()> {
@@ -25,7 +25,7 @@ fn main() -> anyhow::Result<()> {
     let storage = Storage::new(&db_path)?;
     
     // Get UTXOs for address
-    let utxos = storage.get_utxos_for_address(address)?;
+    let utxos = storage.get_utxos_for_address(address, &ChainParams::mainnet())?;
     
     if utxos.is_empty() {
         println!("❌ No UTXOs found for this address");
@@ -1,8 +1,8 @@
 // atmn-core/bin/create_transaction.rs
 // Create and display a transaction
 
-use atmn_core::{Storage, Transaction};
-use atmn_core::tx_builder::TransactionBuilder;
+use atmn_core::{ChainParams, Storage, Transaction};
+use atmn_core::tx_builder::{self, TransactionBuilder};
 use std::env;
 
 fn main() -> anyhow::Result<()> {
@@ -11,10 +11,12 @@ fn main() -> anyhow::Result<()> {
     if args.len() < 4 {
         println!("Usage: create-transaction <from_address> <to_address> <amount_atmn> [fee_atmn]");
         println!();
+        println!("The sender's hex secret key is read from ATMN_SECRET_KEY.");
+        println!();
         println!("Example:");
         println!("  create-transaction \\");
         println!("    ATMN_1e6df34f5f50ff6b581b827c5e9dc5b5b787e178 \\");
-        println!("    ATMN_recipient123 \\");
+        println!("    ATMN_5d0e9a1bc5f7f5c2b35e1d2e6a8e9f4b7c3d2a10 \\");
         println!("    25.5 \\");
         println!("    0.001");
         std::process::exit(1);
@@ -43,9 +45,10 @@ fn main() -> anyhow::Result<()> {
     
     let db_path = env::var("DB_PATH").unwrap_or_else(|_| "./data/atmn-miner.db".to_string());
     let storage = Storage::new(&db_path)?;
+    let params = ChainParams::mainnet();
     
     // Check sender balance
-    let balance = storage.get_balance(from_address)?;
+    let balance = storage.get_balance(from_address, &params)?;
     let balance_atmn = balance as f64 / 100_000_000.0;
     println!("💰 Sender Balance: {} ATMN ({} satoshis)", balance_atmn, balance);
     
@@ -61,10 +64,18 @@ fn main() -> anyhow::Result<()> {
     println!();
     println!("📝 Creating transaction...");
     
+    let secret_key = tx_builder::parse_secret_key(&env::var("ATMN_SECRET_KEY")
+        .map_err(|_| anyhow::anyhow!("ATMN_SECRET_KEY is not set"))?)?;
+    if tx_builder::secret_key_address(&secret_key) != *from_address {
+        anyhow::bail!("ATMN_SECRET_KEY does not control {}", from_address);
+    }
+    
     let builder = TransactionBuilder::new(storage);
-    let tx = builder.create_payment(from_address, to_address, amount, fee)?;
+    let mut tx = builder.create_payment(from_address, to_address, amount, fee)?;
+    builder.sign_all(&mut tx, &[secret_key])?;
+    builder.validate_transaction(&tx)?;
     
-    println!("✅ Transaction created successfully!");
+    println!("✅ Transaction created and signed successfully!");
     println!();
     println!("Transaction Details:");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
@@ -86,7 +97,8 @@ fn main() -> anyhow::Result<()> {
     println!("Outputs:");
     for (i, output) in tx.outputs.iter().enumerate() {
         let output_atmn = output.amount as f64 / 100_000_000.0;
-        let recipient = String::from_utf8_lossy(&output.script_pubkey);
+        let recipient = atmn_core::script::script_pubkey_to_address(&output.script_pubkey, &params)
+            .unwrap_or_else(|| hex::encode(&output.script_pubkey));
         println!("  Output #{}:", i + 1);
         println!("    ├─ Amount: {} ATMN ({} satoshis)", output_atmn, output.amount);
         println!("    └─ Recipient: {}", recipient);
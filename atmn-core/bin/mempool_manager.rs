@@ -1,9 +1,11 @@
 // atmn-core/bin/mempool_manager.rs
 // Persistent mempool manager for testing transaction batching
 
-use atmn_core::{Storage, Transaction, Mempool};
-use atmn_core::tx_builder::TransactionBuilder;
+use atmn_core::{ChainParams, Consensus, Storage, Transaction, Mempool};
+use atmn_core::mempool::MEMPOOL_FILE_NAME;
+use atmn_core::tx_builder::{self, TransactionBuilder};
 use std::env;
+use std::path::Path;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
@@ -15,8 +17,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     let command = &args[1];
     let db_path = "./data/atmn-miner.db";
+    let mempool_path = Path::new("./data").join(MEMPOOL_FILE_NAME);
     let storage = Storage::new(db_path)?;
+    let consensus = Consensus::new(ChainParams::mainnet());
+    let ctx = consensus.context_for_next_block(&storage)?;
+    
+    // Pick up pending transactions from earlier runs
     let mut mempool = Mempool::new();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let loaded = mempool.load(&mempool_path, &consensus, &ctx, &storage, now)?;
+    if loaded.rejected + loaded.expired > 0 {
+        println!("ℹ️  Dropped {} invalid and {} expired saved transactions", loaded.rejected, loaded.expired);
+    }
     
     match command.as_str() {
         "add" => {
@@ -31,7 +45,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let fee: u64 = (args[5].parse::<f64>()? * 100_000_000.0) as u64;
             
             // Check balance
-            let balance = storage.get_balance(from)?;
+            let balance = storage.get_balance(from, &consensus.chain_params)?;
             if balance < amount + fee {
                 eprintln!("❌ Insufficient funds!");
                 eprintln!("   Need: {} ATMN", (amount + fee) as f64 / 100_000_000.0);
@@ -39,12 +53,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Ok(());
             }
             
-            // Create transaction
+            // Create and sign transaction
+            let secret_key = match env::var("ATMN_SECRET_KEY") {
+                Ok(hex_key) => tx_builder::parse_secret_key(&hex_key)?,
+                Err(_) => {
+                    eprintln!("❌ Set ATMN_SECRET_KEY to the sender's hex secret key");
+                    return Ok(());
+                }
+            };
             let builder = TransactionBuilder::new(storage.clone());
-            let tx = builder.create_payment(from, to, amount, fee)?;
+            let mut tx = builder.create_payment(from, to, amount, fee)?;
+            builder.sign_all(&mut tx, &[secret_key])?;
+            builder.validate_transaction(&tx)?;
             
-            // Add to mempool
-            mempool.add_transaction(tx.clone())?;
+            // Add to mempool if it can be mined in the next block
+            mempool.accept_transaction(tx.clone(), &consensus, &ctx, &storage)?;
+            mempool.dump(&mempool_path)?;
             
             println!("✅ Transaction added to mempool");
             println!("   From: {}", from);
@@ -96,6 +120,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         
         "clear" => {
             mempool.clear();
+            mempool.dump(&mempool_path)?;
             println!("✅ Mempool cleared");
         }
         
@@ -117,8 +142,11 @@ fn print_usage() {
     println!("  stats                            - Show mempool statistics");
     println!("  clear                            - Clear all transactions");
     println!();
+    println!("Environment:");
+    println!("  ATMN_SECRET_KEY                  - Sender's hex secret key (for add)");
+    println!();
     println!("Examples:");
-    println!("  mempool-manager add ATMN_sender ATMN_recipient 10.0 0.01");
+    println!("  ATMN_SECRET_KEY=<hex> mempool-manager add ATMN_<sender> ATMN_<recipient> 10.0 0.01");
     println!("  mempool-manager list 20");
     println!("  mempool-manager stats");
 }
@@ -1,8 +1,9 @@
 // atmn-core/bin/mine_production.rs
 // Production miner that connects to database and mines real blocks
 
-use atmn_core::{MultiThreadedMiner, BlockTemplate, Storage};
-use atmn_core::types::BlockHash;
+use atmn_core::{BlockAssembler, ChainParams, Consensus, JobOutcome, Mempool, MinerConfig, MiningJob, Storage};
+use atmn_core::mempool::MEMPOOL_FILE_NAME;
+use std::path::Path;
 use std::time::Instant;
 use std::env;
 
@@ -54,87 +55,81 @@ fn main() -> anyhow::Result<()> {
     // Get current blockchain height
     let current_height = storage.get_best_height()?.unwrap_or(0);
     println!("   Current blockchain height: {}", current_height);
+
+    // Regtest rules keep the fixed easy difficulty suited to CPU mining
+    let consensus = Consensus::new(ChainParams::regtest());
+    let assembler = BlockAssembler::new(consensus.clone());
+
+    // Mine the transactions waiting in the saved mempool
+    let mempool_path = Path::new(&db_path).with_file_name(MEMPOOL_FILE_NAME);
+    let mut mempool = Mempool::new();
+    let ctx = consensus.context_for_next_block(&storage)?;
+    let loaded = mempool.load(&mempool_path, &consensus, &ctx, &storage, ctx.now as u64)?;
+    println!("   Pending transactions: {}", loaded.accepted);
     println!();
 
-    // Create multi-threaded miner
-    let mut miner = MultiThreadedMiner::new(Some(thread_count));
+    // Progress every few seconds while a block is being mined
+    let config = MinerConfig {
+        thread_count,
+        update_interval_ms: 5000,
+        ..MinerConfig::default()
+    };
     
     let mut blocks_mined: u64 = 0;
     let total_start = Instant::now();
 
     loop {
-        // Get the latest block hash
-        let prev_block_hash = if current_height + blocks_mined == 0 {
-            BlockHash::zero()
-        } else {
-            // Get the last block from storage
-            let prev_height = current_height + blocks_mined;
-            match storage.get_block(prev_height) {
-                Ok(Some(prev_block)) => prev_block.hash(),
-                _ => BlockHash::zero(), // Fallback to genesis
-            }
-        };
-
-        // Create block template
-        let next_height = current_height + blocks_mined + 1;
-        
-        // Create coinbase transaction (50 ATMN = 5,000,000,000 satoshis)
-        let block_reward = 5_000_000_000u64; // 50 ATMN
-        let coinbase_tx = atmn_core::Block::create_coinbase_tx(next_height, &miner_address, block_reward);
-        
-        let template = BlockTemplate {
-            prev_block_hash,
-            merkle_root: BlockHash::zero(), // TODO: Calculate from transactions
-            height: next_height,
-            transactions: vec![coinbase_tx],
-            difficulty_bits: 0x207fffff, // Much easier difficulty for testing (was 0x1d00ffff)
-            version: 1,
-            template_time: current_timestamp(),
-        };
+        // Reward plus fees of the best-paying pending transactions
+        let template = assembler.create_template(&storage, &mempool, &miner_address)?;
+        let next_height = template.height;
+        let template_fees = template.total_fees;
 
         println!("⛏️  Mining block #{} ...", next_height);
         let block_start = Instant::now();
 
-        // Mine the block
-        match miner.mine_block(template) {
-            Ok(result) => {
-                if result.success && result.block.is_some() {
-                    let block = result.block.unwrap();
-                    let block_time = block_start.elapsed();
-                    
-                    // Store the block
-                    match storage.put_block(next_height, &block) {
-                        Ok(_) => {
-                            blocks_mined += 1;
-                            
-                            println!("✅ Block #{} mined!", next_height);
-                            println!("   ├─ Hash: {:?}", block.hash());
-                            println!("   ├─ Nonce: {}", block.header.nonce);
-                            println!("   ├─ Time: {:.2}s", block_time.as_secs_f64());
-                            println!("   ├─ Hashes: {}", result.hashes_attempted);
-                            println!("   └─ Hash Rate: {:.2} MH/s", result.hashes_attempted as f64 / block_time.as_secs_f64() / 1_000_000.0);
-                            println!();
-
-                            // Check if we've reached target
-                            if let Some(target) = target_blocks {
-                                if blocks_mined >= target {
-                                    break;
-                                }
+        // Mine the block in the background, reporting as it goes
+        let job = MiningJob::start(template, config.clone());
+        for progress in job.progress_updates() {
+            println!("   … {} hashes, {:.2} MH/s", progress.hashes, progress.hash_rate / 1_000_000.0);
+        }
+        let hashes = job.progress().hashes;
+
+        match job.wait() {
+            JobOutcome::Found(block) => {
+                let block_time = block_start.elapsed();
+
+                // Store the block
+                match storage.put_block(next_height, &block) {
+                    Ok(_) => {
+                        blocks_mined += 1;
+                        mempool.connect_block(&block);
+                        mempool.dump(&mempool_path)?;
+
+                        println!("✅ Block #{} mined!", next_height);
+                        println!("   ├─ Hash: {:?}", block.hash());
+                        println!("   ├─ Nonce: {}", block.header.nonce);
+                        println!("   ├─ Transactions: {} ({} sats in fees)", block.transactions.len(), template_fees);
+                        println!("   ├─ Time: {:.2}s", block_time.as_secs_f64());
+                        println!("   ├─ Hashes: {}", hashes);
+                        println!("   └─ Hash Rate: {:.2} MH/s", hashes as f64 / block_time.as_secs_f64() / 1_000_000.0);
+                        println!();
+
+                        // Check if we've reached target
+                        if let Some(target) = target_blocks {
+                            if blocks_mined >= target {
+                                break;
                             }
                         }
-                        Err(e) => {
-                            eprintln!("❌ Error storing block: {:?}", e);
-                            break;
-                        }
                     }
-                } else {
-                    println!("❌ Mining failed - no valid block found");
-                    break;
+                    Err(e) => {
+                        eprintln!("❌ Error storing block: {:?}", e);
+                        break;
+                    }
                 }
             }
-            Err(e) => {
-                eprintln!("❌ Mining error: {:?}", e);
-                break;
+            outcome => {
+                // Nothing else stops the job, but a fresh template is the answer either way
+                println!("⚠️  No block found ({:?}), rebuilding the template", outcome);
             }
         }
     }
@@ -151,11 +146,3 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
-
-fn current_timestamp() -> u32 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as u32
-}
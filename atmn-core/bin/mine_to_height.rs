@@ -1,9 +1,8 @@
 // atmn-core/bin/mine_to_height.rs
 // Continuous miner with difficulty adjustment monitoring
 
-use atmn_core::{Block, Storage, MultiThreadedMiner, BlockTemplate};
-use atmn_core::types::BlockHash;
-use std::time::{SystemTime, UNIX_EPOCH, Instant};
+use atmn_core::{BlockAssembler, ChainParams, Consensus, Mempool, Storage, MultiThreadedMiner};
+use std::time::Instant;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let target_height: u64 = std::env::args()
@@ -18,6 +17,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     
     let storage = Storage::new(db_path)?;
+    // Regtest limits, with retargeting on so adjustments show up
+    let params = ChainParams { no_retargeting: false, ..ChainParams::regtest() };
+    let consensus = Consensus::new(params.clone());
+    let assembler = BlockAssembler::new(consensus.clone());
+    let mempool = Mempool::new();
     let mut miner = MultiThreadedMiner::new(Some(6));
     
     let start_height = storage.get_best_height()?.unwrap_or(0);
@@ -25,11 +29,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("📊 Start height: {}", start_height);
     println!("📊 Blocks to mine: {}", target_height - start_height);
-    println!("📊 Difficulty: 0x207fffff (testing)");
+    println!("📊 Difficulty: LWMA over {} blocks (regtest limits)", params.difficulty_window);
     println!();
     
     let mut blocks_mined = 0;
-    let mut last_difficulty_bits = 0x207fffff;
+    let mut last_difficulty_bits = params.genesis_bits;
     
     loop {
         let current_height = storage.get_best_height()?.unwrap_or(0);
@@ -38,74 +42,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             break;
         }
         
-        let next_height = current_height + 1;
-        
-        // Get previous block hash
-        let prev_block_hash = if current_height == 0 {
-            BlockHash::zero()
-        } else {
-            match storage.get_block(current_height) {
-                Ok(Some(prev_block)) => prev_block.hash(),
-                _ => BlockHash::zero(),
-            }
-        };
-        
-        // Create coinbase transaction
-        let block_reward = 5_000_000_000u64; // 50 ATMN
-        let coinbase_tx = Block::create_coinbase_tx(next_height, miner_address, block_reward);
+        // Coinbase-only blocks on the stored tip
+        let template = assembler.create_template(&storage, &mempool, miner_address)?;
+        let next_height = template.height;
         
         // Check for difficulty adjustment
-        let difficulty_bits = if next_height % 2016 == 0 && next_height > 0 {
-            // Calculate new difficulty
-            let adjustment_start = next_height.saturating_sub(2016);
-            let start_block = storage.get_block(adjustment_start)?;
-            let end_block = storage.get_block(current_height)?;
-            
-            if let (Some(start), Some(end)) = (start_block, end_block) {
-                let actual_time = end.header.timestamp.saturating_sub(start.header.timestamp);
-                let target_time = 2016 * 12; // 2016 blocks * 12 seconds
-                
-                // Calculate adjustment ratio (with 4x bounds)
-                let ratio = (actual_time as f64) / (target_time as f64);
-                let bounded_ratio = ratio.max(0.25).min(4.0);
-                
-                // Adjust difficulty (higher bits = easier, lower bits = harder)
-                let old_difficulty = last_difficulty_bits as f64;
-                let new_difficulty = (old_difficulty * bounded_ratio) as u32;
-                
-                println!();
-                println!("🎉 DIFFICULTY ADJUSTMENT at block {}", next_height);
-                println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-                println!("   Actual time:  {}s ({:.1}m)", actual_time, actual_time as f64 / 60.0);
-                println!("   Target time:  {}s ({:.1}m)", target_time, target_time as f64 / 60.0);
-                println!("   Ratio:        {:.4}x", ratio);
-                println!("   Bounded:      {:.4}x", bounded_ratio);
-                println!("   Old bits:     0x{:08x}", last_difficulty_bits);
-                println!("   New bits:     0x{:08x}", new_difficulty);
-                println!();
-                
-                last_difficulty_bits = new_difficulty;
-                new_difficulty
-            } else {
-                last_difficulty_bits
-            }
-        } else {
-            last_difficulty_bits
-        };
-        
-        // Create block template
-        let template = BlockTemplate {
-            prev_block_hash,
-            merkle_root: BlockHash::zero(),
-            height: next_height,
-            transactions: vec![coinbase_tx],
-            difficulty_bits,
-            version: 1,
-            template_time: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32,
-        };
+        let difficulty_bits = template.difficulty_bits;
+        if difficulty_bits != last_difficulty_bits {
+            println!();
+            println!("🎉 DIFFICULTY ADJUSTMENT at block {}", next_height);
+            println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+            println!("   Old bits:     0x{:08x}", last_difficulty_bits);
+            println!("   New bits:     0x{:08x}", difficulty_bits);
+            println!();
+            last_difficulty_bits = difficulty_bits;
+        }
         
         // Mine the block
         match miner.mine_block(template)? {
@@ -17,6 +17,7 @@ fn main() {
         max_nonce: u32::MAX,
         thread_count: num_cpus::get(),
         update_interval_ms: 1000,
+        ..MinerConfig::default()
     };
 
     let mut miner = Miner::with_config(config);
@@ -49,6 +50,7 @@ fn main() {
             difficulty_bits: 0x207fffff, // Very very easy difficulty for testing
             version: 1,
             template_time: current_timestamp(),
+            total_fees: 0,
         };
 
         print!("⛏️  Mining block #{}... ", block_count);
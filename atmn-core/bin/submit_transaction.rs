@@ -1,11 +1,15 @@
 // atmn-core/bin/submit_transaction.rs
 // Submit a transaction to the mempool and optionally mine it
 
-use atmn_core::{Storage, Transaction, Block, MultiThreadedMiner, BlockTemplate};
-use atmn_core::tx_builder::TransactionBuilder;
-use atmn_core::mempool::Mempool;
-use atmn_core::types::BlockHash;
+use atmn_core::{BlockAssembler, ChainParams, Consensus, Storage, MultiThreadedMiner};
+use atmn_core::tx_builder::{self, TransactionBuilder};
+use atmn_core::mempool::{Mempool, MempoolConfig};
+use atmn_core::fee_estimator::{FeeEstimator, DEFAULT_CONFIRMATION_TARGET, FEE_ESTIMATES_FILE_NAME};
 use std::env;
+use std::path::Path;
+
+/// Size assumed when estimating a fee before the transaction is built, in bytes
+const ESTIMATED_TX_SIZE: usize = 250;
 
 fn main() -> anyhow::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -16,10 +20,14 @@ fn main() -> anyhow::Result<()> {
         println!("Options:");
         println!("  --mine    Mine a block immediately with this transaction");
         println!();
+        println!("Without fee_atmn, the fee is estimated to confirm within {} blocks.", DEFAULT_CONFIRMATION_TARGET);
+        println!();
+        println!("The sender's hex secret key is read from ATMN_SECRET_KEY.");
+        println!();
         println!("Example:");
         println!("  submit-transaction \\");
         println!("    ATMN_1e6df34f5f50ff6b581b827c5e9dc5b5b787e178 \\");
-        println!("    ATMN_recipient123 \\");
+        println!("    ATMN_5d0e9a1bc5f7f5c2b35e1d2e6a8e9f4b7c3d2a10 \\");
         println!("    25.0 \\");
         println!("    0.001 \\");
         println!("    --mine");
@@ -29,35 +37,54 @@ fn main() -> anyhow::Result<()> {
     let from_address = &args[1];
     let to_address = &args[2];
     let amount_atmn: f64 = args[3].parse()?;
-    let fee_atmn: f64 = if args.len() > 4 && !args[4].starts_with("--") {
-        args[4].parse()?
+    let fee_atmn: Option<f64> = if args.len() > 4 && !args[4].starts_with("--") {
+        Some(args[4].parse()?)
     } else {
-        0.001 // Default fee
+        None
     };
     
     let should_mine = args.contains(&"--mine".to_string());
     
+    let db_path = env::var("DB_PATH").unwrap_or_else(|_| "./data/atmn-miner.db".to_string());
+    let storage = Storage::new(&db_path)?;
+    // The miner database holds the regtest chain `mine-production` builds;
+    // signing, mempool acceptance and the template all use its parameters
+    let params = ChainParams::regtest();
+    
+    // Without an explicit fee, pay the rate recent blocks needed for the
+    // default target, or the relay minimum until there is enough history
+    let fee_for_size = |fee_per_kb: u64, size: usize| (fee_per_kb as u128 * size as u128).div_ceil(1000) as u64;
+    let (estimated_fee_per_kb, mut fee) = match fee_atmn {
+        Some(fee_atmn) => (None, (fee_atmn * 100_000_000.0) as u64),
+        None => {
+            let estimator = FeeEstimator::load(&Path::new(&db_path).with_file_name(FEE_ESTIMATES_FILE_NAME))?;
+            let fee_per_kb = estimator
+                .estimate_fee(DEFAULT_CONFIRMATION_TARGET)
+                .map_or(MempoolConfig::default().min_fee_per_byte * 1000, |estimate| estimate.fee_per_kb);
+            (Some(fee_per_kb), fee_for_size(fee_per_kb, ESTIMATED_TX_SIZE))
+        }
+    };
+    
     // Convert to satoshis
     let amount = (amount_atmn * 100_000_000.0) as u64;
-    let fee = (fee_atmn * 100_000_000.0) as u64;
     
     println!("💸 ATMN Transaction Submission");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!("From:   {}", from_address);
     println!("To:     {}", to_address);
     println!("Amount: {} ATMN", amount_atmn);
-    println!("Fee:    {} ATMN", fee_atmn);
+    match estimated_fee_per_kb {
+        Some(fee_per_kb) => println!("Fee:    {} ATMN/kB (estimated)", fee_per_kb as f64 / 100_000_000.0),
+        None => println!("Fee:    {} ATMN", fee as f64 / 100_000_000.0),
+    }
     if should_mine {
         println!("Mode:   Mine immediately");
     }
     println!();
     
-    let db_path = env::var("DB_PATH").unwrap_or_else(|_| "./data/atmn-miner.db".to_string());
-    let storage = Storage::new(&db_path)?;
-    
     // Check sender balance
     println!("📊 Checking sender balance...");
-    let balance = storage.get_balance(from_address)?;
+    let balance = storage.get_balance(from_address, &params)?;
     let balance_atmn = balance as f64 / 100_000_000.0;
     println!("   Balance: {} ATMN", balance_atmn);
     
@@ -73,18 +100,36 @@ fn main() -> anyhow::Result<()> {
     // Create transaction
     println!();
     println!("📝 Creating transaction...");
-    let builder = TransactionBuilder::new(storage.clone());
-    let tx = builder.create_payment(from_address, to_address, amount, fee)?;
+    let secret_key = tx_builder::parse_secret_key(&env::var("ATMN_SECRET_KEY")
+        .map_err(|_| anyhow::anyhow!("ATMN_SECRET_KEY is not set"))?)?;
+    
+    let builder = TransactionBuilder::new(storage.clone()).with_chain_params(params.clone());
+    let mut tx = builder.create_payment(from_address, to_address, amount, fee)?;
+    builder.sign_all(&mut tx, &[secret_key])?;
+    
+    // Rebuild once the real size is known if the estimated fee falls short
+    if let Some(fee_per_kb) = estimated_fee_per_kb {
+        let needed = fee_for_size(fee_per_kb, tx.size());
+        if needed > fee {
+            fee = needed;
+            tx = builder.create_payment(from_address, to_address, amount, fee)?;
+            builder.sign_all(&mut tx, &[secret_key])?;
+        }
+        println!("   Fee: {} ATMN for {} bytes", fee as f64 / 100_000_000.0, tx.size());
+    }
+    builder.validate_transaction(&tx)?;
     
     let tx_hash = tx.hash();
-    println!("✅ Transaction created!");
+    println!("✅ Transaction created and signed!");
     println!("   TX Hash: {:?}", tx_hash);
     
     // Add to mempool
     println!();
     println!("📤 Submitting to mempool...");
+    let consensus = Consensus::new(params.clone());
+    let ctx = consensus.context_for_next_block(&storage)?;
     let mut mempool = Mempool::new();
-    mempool.add_transaction(tx.clone())?;
+    mempool.accept_transaction(tx.clone(), &consensus, &ctx, &storage)?;
     
     let mempool_size = mempool.size();
     println!("✅ Transaction added to mempool!");
@@ -94,40 +139,15 @@ fn main() -> anyhow::Result<()> {
         println!();
         println!("⛏️  Mining block with transaction...");
         
-        // Get current height
-        let current_height = storage.get_best_height()?.unwrap_or(0);
-        let next_height = current_height + 1;
-        
-        // Get previous block hash
-        let prev_block_hash = match storage.get_block(current_height) {
-            Ok(Some(prev_block)) => prev_block.hash(),
-            _ => BlockHash::zero(),
-        };
-        
-        // Create coinbase transaction
-        let block_reward = 5_000_000_000u64; // 50 ATMN
-        let coinbase_tx = Block::create_coinbase_tx(next_height, from_address, block_reward);
-        
-        // Get transactions from mempool
-        let pending_txs = mempool.get_transactions(10)?; // Get up to 10 transactions
-        
-        // Build block with coinbase + pending transactions
-        let mut block_txs = vec![coinbase_tx];
-        block_txs.extend(pending_txs);
-        
-        // Create block template
-        let template = BlockTemplate {
-            prev_block_hash,
-            merkle_root: BlockHash::zero(),
-            height: next_height,
-            transactions: block_txs,
-            difficulty_bits: 0x207fffff,
-            version: 1,
-            template_time: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32,
-        };
+        // Regtest difficulty keeps immediate mining quick; the coinbase
+        // pays the sender the reward plus the fees
+        let assembler = BlockAssembler::new(consensus);
+        let template = assembler.create_template(&storage, &mempool, from_address)?;
+        if !template.transactions.iter().any(|included| included.hash() == tx_hash) {
+            anyhow::bail!("transaction {:?} was left out of the block template", tx_hash);
+        }
+        let next_height = template.height;
+        let total_fees = template.total_fees;
         
         // Mine the block
         let mut miner = MultiThreadedMiner::new(Some(6));
@@ -143,15 +163,16 @@ fn main() -> anyhow::Result<()> {
                 println!("   Transactions: {}", block.transactions.len());
                 println!("   - 1 coinbase");
                 println!("   - {} transfer(s)", block.transactions.len() - 1);
+                println!("   Fees:  {} ATMN", total_fees as f64 / 100_000_000.0);
                 
                 // Check updated balances
                 println!();
                 println!("💰 Updated Balances:");
                 
-                let sender_balance = storage.get_balance(from_address)?;
+                let sender_balance = storage.get_balance(from_address, &params)?;
                 println!("   Sender:    {} ATMN", sender_balance as f64 / 100_000_000.0);
                 
-                let recipient_balance = storage.get_balance(to_address)?;
+                let recipient_balance = storage.get_balance(to_address, &params)?;
                 println!("   Recipient: {} ATMN", recipient_balance as f64 / 100_000_000.0);
             }
             _ => {
@@ -0,0 +1,304 @@
+// atmn-core/src/address.rs
+// Checksummed bech32m addresses for the 20-byte Address type
+//
+// An address is `<network prefix>1<version><payload><checksum>` in the
+// bech32 alphabet. The prefix comes from `ChainParams::address_hrp`, the
+// version is 0 for pay-to-pubkey-hash and the payload is the public key
+// hash. The BIP350 checksum detects any error in up to four characters,
+// so a mistyped address fails to parse instead of paying a key nobody has.
+
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
+use crate::chain_params::ChainParams;
+use crate::error::{AddressError, Result};
+use crate::script;
+use crate::types::Address;
+
+/// Separator between the network prefix and the data
+pub const SEPARATOR: char = '1';
+
+/// Address version of pay-to-pubkey-hash outputs
+pub const P2PKH_VERSION: u8 = 0;
+
+/// Longest string bech32 allows
+const MAX_LENGTH: usize = 90;
+
+/// Checksum length in characters
+const CHECKSUM_LENGTH: usize = 6;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Checksum constant distinguishing bech32m from bech32
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+impl Address {
+    /// Address of a serialized public key: its HASH160
+    pub fn from_public_key(pubkey: &[u8]) -> Self {
+        Address::from_bytes(script::hash160(pubkey))
+    }
+
+    /// Address controlled by a secret key, using the compressed public key
+    pub fn from_secret_key(secret_key: &SecretKey) -> Self {
+        Self::from_public_key(&PublicKey::from_secret_key(SECP256K1, secret_key).serialize())
+    }
+
+    /// Address a script pays to, if it is a standard template
+    pub fn from_script_pubkey(script_pubkey: &[u8]) -> Option<Self> {
+        script::extract_p2pkh(script_pubkey).map(Address::from_bytes)
+    }
+
+    /// Locking script paying to this address
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        script::p2pkh_script_pubkey(self.as_bytes())
+    }
+
+    /// Legacy `ATMN_<40 hex>` form, which keys the storage address index
+    pub fn to_legacy(&self) -> String {
+        script::pubkey_hash_to_address(self.as_bytes())
+    }
+
+    /// Checksummed string form on `params`' network
+    pub fn encode(&self, params: &ChainParams) -> String {
+        let mut data = vec![P2PKH_VERSION];
+        data.extend(convert_bits(self.as_bytes(), 8, 5, true).expect("padding allowed"));
+
+        let mut encoded = String::with_capacity(params.address_hrp.len() + 1 + data.len() + CHECKSUM_LENGTH);
+        encoded.push_str(&params.address_hrp);
+        encoded.push(SEPARATOR);
+        for symbol in data.iter().chain(&create_checksum(&params.address_hrp, &data)) {
+            encoded.push(CHARSET[*symbol as usize] as char);
+        }
+        encoded
+    }
+
+    /// Parse a checksummed address for `params`' network
+    ///
+    /// All upper or all lower case is accepted.
+    pub fn parse(address: &str, params: &ChainParams) -> Result<Self> {
+        let (hrp, data) = decode(address)?;
+        if hrp != params.address_hrp {
+            return Err(AddressError::WrongNetwork { expected: params.address_hrp.clone(), found: hrp }.into());
+        }
+
+        let (&version, program) = data.split_first().ok_or(AddressError::InvalidLength(address.len()))?;
+        if version != P2PKH_VERSION {
+            return Err(AddressError::UnknownVersion(version).into());
+        }
+        let payload = convert_bits(program, 5, 8, false).ok_or(AddressError::InvalidPadding)?;
+        let len = payload.len();
+        let bytes: [u8; 20] = payload.try_into().map_err(|_| AddressError::InvalidPayloadLength(len))?;
+        Ok(Address::from_bytes(bytes))
+    }
+}
+
+/// Split a bech32m string into its prefix and 5-bit data, checking the checksum
+fn decode(address: &str) -> std::result::Result<(String, Vec<u8>), AddressError> {
+    if address.len() > MAX_LENGTH {
+        return Err(AddressError::InvalidLength(address.len()));
+    }
+    if address.chars().any(|c| c.is_ascii_lowercase()) && address.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(AddressError::MixedCase);
+    }
+    let address = address.to_ascii_lowercase();
+
+    let separator = address.rfind(SEPARATOR).ok_or(AddressError::MissingSeparator)?;
+    if separator == 0 {
+        return Err(AddressError::MissingSeparator);
+    }
+    if address.len() < separator + 1 + CHECKSUM_LENGTH {
+        return Err(AddressError::InvalidLength(address.len()));
+    }
+
+    let (hrp, rest) = address.split_at(separator);
+    if let Some((position, character)) = hrp.chars().enumerate().find(|(_, c)| !('!'..='~').contains(c)) {
+        return Err(AddressError::InvalidCharacter { position, character });
+    }
+    let mut data = Vec::with_capacity(rest.len() - 1);
+    for (offset, character) in rest.chars().enumerate().skip(1) {
+        let symbol = CHARSET
+            .iter()
+            .position(|&c| c as char == character)
+            .ok_or(AddressError::InvalidCharacter { position: separator + offset, character })?;
+        data.push(symbol as u8);
+    }
+
+    if polymod(&checksum_input(hrp, &data)) != BECH32M_CONST {
+        return Err(AddressError::InvalidChecksum);
+    }
+    data.truncate(data.len() - CHECKSUM_LENGTH);
+    Ok((hrp.to_string(), data))
+}
+
+/// BCH checksum over 5-bit values
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for &value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+/// The prefix expanded to 5-bit values, followed by `data`
+fn checksum_input(hrp: &str, data: &[u8]) -> Vec<u8> {
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 0x1f));
+    values.extend_from_slice(data);
+    values
+}
+
+fn create_checksum(hrp: &str, data: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let mut values = checksum_input(hrp, data);
+    values.extend_from_slice(&[0; CHECKSUM_LENGTH]);
+    let checksum = polymod(&values) ^ BECH32M_CONST;
+
+    let mut symbols = [0u8; CHECKSUM_LENGTH];
+    for (i, symbol) in symbols.iter_mut().enumerate() {
+        *symbol = ((checksum >> (5 * (CHECKSUM_LENGTH - 1 - i))) & 0x1f) as u8;
+    }
+    symbols
+}
+
+/// Regroup bits, e.g. bytes into 5-bit symbols
+///
+/// Without `pad`, leftover bits must be fewer than `from` and all zero.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let max = (1u32 << to) - 1;
+    let mut out = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    for &value in data {
+        if (value as u32) >> from != 0 {
+            return None;
+        }
+        acc = (acc << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    fn parse_error(address: &str, params: &ChainParams) -> AddressError {
+        match Address::parse(address, params) {
+            Err(Error::Address(e)) => e,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bip350_checksum_vectors() {
+        for valid in [
+            "A1LQFN3A",
+            "a1lqfn3a",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+            "?1v759aa",
+        ] {
+            assert!(decode(valid).is_ok(), "{}", valid);
+        }
+
+        // Plain bech32 checksums are not bech32m
+        assert_eq!(decode("a12uel5l"), Err(AddressError::InvalidChecksum));
+        assert_eq!(decode("1xj0phk"), Err(AddressError::MissingSeparator));
+        assert_eq!(decode("M1VUXWEZ"), Err(AddressError::InvalidChecksum));
+        assert_eq!(decode("aBcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx"), Err(AddressError::MixedCase));
+    }
+
+    #[test]
+    fn test_address_round_trip() {
+        let params = ChainParams::mainnet();
+        let address = Address::from_bytes([0x11; 20]);
+
+        let encoded = address.encode(&params);
+        assert!(encoded.starts_with("atmn1q"));
+        assert_eq!(encoded.len(), 4 + 1 + 1 + 32 + CHECKSUM_LENGTH);
+        assert_eq!(Address::parse(&encoded, &params).unwrap(), address);
+        assert_eq!(Address::parse(&encoded.to_uppercase(), &params).unwrap(), address);
+
+        // Known vector, also checked by atmn-node's InputValidator
+        let counting = Address::from_bytes(std::array::from_fn(|i| i as u8 + 1));
+        assert_eq!(counting.encode(&params), "atmn1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5knek8x");
+
+        let script = address.script_pubkey();
+        assert_eq!(Address::from_script_pubkey(&script), Some(address));
+        assert_eq!(script::address_to_script_pubkey(&address.to_legacy(), &params), Some(script));
+        assert_eq!(Address::from_script_pubkey(&[script::OP_RETURN]), None);
+    }
+
+    #[test]
+    fn test_secret_key_address_matches_legacy_form() {
+        let secret = crate::tx_builder::parse_secret_key(&"01".repeat(32)).unwrap();
+        let address = Address::from_secret_key(&secret);
+        assert_eq!(address.to_legacy(), crate::tx_builder::secret_key_address(&secret));
+    }
+
+    #[test]
+    fn test_typos_rejected() {
+        let params = ChainParams::mainnet();
+        let encoded = Address::from_bytes([0xab; 20]).encode(&params);
+
+        // Every single-character substitution breaks the checksum
+        for position in params.address_hrp.len() + 1..encoded.len() {
+            for &replacement in CHARSET {
+                let mut typo = encoded.clone().into_bytes();
+                if typo[position] == replacement {
+                    continue;
+                }
+                typo[position] = replacement;
+                let typo = String::from_utf8(typo).unwrap();
+                assert_eq!(parse_error(&typo, &params), AddressError::InvalidChecksum, "{}", typo);
+            }
+        }
+
+        // Adjacent characters swapped
+        let mut swapped = encoded.clone().into_bytes();
+        swapped.swap(10, 11);
+        if swapped != encoded.as_bytes() {
+            let swapped = String::from_utf8(swapped).unwrap();
+            assert_eq!(parse_error(&swapped, &params), AddressError::InvalidChecksum);
+        }
+
+        let mut truncated = encoded.clone();
+        truncated.pop();
+        assert_eq!(parse_error(&truncated, &params), AddressError::InvalidChecksum);
+
+        let mut invalid = encoded.clone();
+        invalid.replace_range(10..11, "b");
+        assert_eq!(parse_error(&invalid, &params), AddressError::InvalidCharacter { position: 10, character: 'b' });
+        assert!(matches!(parse_error(&"ATMN_".repeat(9), &params), AddressError::MissingSeparator));
+    }
+
+    #[test]
+    fn test_network_prefix_checked() {
+        let address = Address::from_bytes([0x42; 20]);
+        let testnet = address.encode(&ChainParams::testnet());
+        assert!(testnet.starts_with("tatmn1"));
+
+        assert_eq!(
+            parse_error(&testnet, &ChainParams::mainnet()),
+            AddressError::WrongNetwork { expected: "atmn".to_string(), found: "tatmn".to_string() }
+        );
+        assert_eq!(Address::parse(&testnet, &ChainParams::testnet()).unwrap(), address);
+    }
+}
@@ -2,13 +2,17 @@
 // Block structure and validation
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use crate::types::{BlockHash, BlockHeight, Amount, Timestamp, Nonce};
+use crate::chain_params::ChainParams;
 use crate::transaction::Transaction;
 use crate::consensus::sha256d;
+use crate::merkle;
+use crate::encoding::{self, Encodable, BLOCK_HEADER_SIZE};
 use crate::error::Result;
 
 /// Block Header
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BlockHeader {
     pub version: u32,
     pub prev_block_hash: BlockHash,
@@ -19,16 +23,9 @@ pub struct BlockHeader {
 }
 
 impl BlockHeader {
-    /// Serialize header for hashing
+    /// Serialize header for hashing (80 bytes, see `encoding`)
     pub fn serialize(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(84);
-        bytes.extend_from_slice(&self.version.to_le_bytes());
-        bytes.extend_from_slice(&self.prev_block_hash.0);
-        bytes.extend_from_slice(&self.merkle_root.0);
-        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
-        bytes.extend_from_slice(&self.bits.to_le_bytes());
-        bytes.extend_from_slice(&self.nonce.to_le_bytes());
-        bytes
+        encoding::serialize(self)
     }
 
     /// Hash the block header
@@ -36,6 +33,56 @@ impl BlockHeader {
         let bytes = self.serialize();
         sha256d(&bytes)
     }
+
+    /// Hashing state for trying nonces on this header
+    pub fn midstate(&self) -> HeaderMidstate {
+        HeaderMidstate::new(self)
+    }
+}
+
+/// Header bytes covered by the SHA-256 midstate: one compression block
+const MIDSTATE_SIZE: usize = 64;
+
+/// Offsets into the header bytes that follow the midstate
+const TAIL_TIMESTAMP: usize = 4;
+const TAIL_NONCE: usize = 12;
+
+/// SHA-256 state after the first 64 header bytes, for fast nonce search
+///
+/// Those bytes (version, previous hash and most of the merkle root) stay
+/// fixed while mining, so each nonce costs the last chunk of the first hash
+/// plus the second hash, with no allocation. Changing the merkle root needs
+/// a new midstate; the timestamp can move on the same one.
+#[derive(Clone)]
+pub struct HeaderMidstate {
+    state: Sha256,
+    tail: [u8; BLOCK_HEADER_SIZE - MIDSTATE_SIZE],
+}
+
+impl HeaderMidstate {
+    pub fn new(header: &BlockHeader) -> Self {
+        let bytes = header.serialize();
+        let mut state = Sha256::new();
+        state.update(&bytes[..MIDSTATE_SIZE]);
+        let mut tail = [0u8; BLOCK_HEADER_SIZE - MIDSTATE_SIZE];
+        tail.copy_from_slice(&bytes[MIDSTATE_SIZE..]);
+        HeaderMidstate { state, tail }
+    }
+
+    /// Move the header to `timestamp`
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.tail[TAIL_TIMESTAMP..TAIL_TIMESTAMP + 4].copy_from_slice(&timestamp.to_le_bytes());
+    }
+
+    /// Hash of the header with `nonce`, equal to `BlockHeader::hash`
+    pub fn hash(&self, nonce: Nonce) -> BlockHash {
+        let mut tail = self.tail;
+        tail[TAIL_NONCE..].copy_from_slice(&nonce.to_le_bytes());
+
+        let mut first = self.state.clone();
+        first.update(tail);
+        BlockHash(Sha256::digest(first.finalize()).into())
+    }
 }
 
 /// Block - Header + Transactions
@@ -77,56 +124,23 @@ impl Block {
         self.header.hash()
     }
 
-    /// Calculate merkle root of transactions
-    pub fn calculate_merkle_root(transactions: &[Transaction]) -> BlockHash {
-        if transactions.is_empty() {
-            return BlockHash::zero();
-        }
-
-        // Hash all transactions
-        let mut hashes: Vec<BlockHash> = transactions
-            .iter()
-            .map(|tx| {
-                // Hash transaction
-                let tx_bytes = bincode::serialize(tx).unwrap_or_default();
-                sha256d(&tx_bytes)
-            })
-            .collect();
-
-        // If only one transaction, return its hash
-        if hashes.len() == 1 {
-            return hashes[0].clone();
-        }
-
-        // Build merkle tree bottom-up
-        while hashes.len() > 1 {
-            let mut next_level = Vec::new();
-
-            // Process pairs
-            for i in (0..hashes.len()).step_by(2) {
-                if i + 1 < hashes.len() {
-                    // Hash pair
-                    let mut combined = Vec::with_capacity(64);
-                    combined.extend_from_slice(&hashes[i].0);
-                    combined.extend_from_slice(&hashes[i + 1].0);
-                    next_level.push(sha256d(&combined));
-                } else {
-                    // Odd number: duplicate last hash
-                    let mut combined = Vec::with_capacity(64);
-                    combined.extend_from_slice(&hashes[i].0);
-                    combined.extend_from_slice(&hashes[i].0);
-                    next_level.push(sha256d(&combined));
-                }
-            }
+    /// Serialize the full block (header, height, transactions)
+    pub fn serialize(&self) -> Vec<u8> {
+        encoding::serialize(self)
+    }
 
-            hashes = next_level;
-        }
+    /// Serialized block size in bytes
+    pub fn size(&self) -> usize {
+        self.encoded_len()
+    }
 
-        hashes[0].clone()
+    /// Calculate merkle root of transactions
+    pub fn calculate_merkle_root(transactions: &[Transaction]) -> BlockHash {
+        merkle::merkle_root(&merkle::transaction_leaves(transactions))
     }
 
     /// Validate block structure and transactions
-    pub fn is_valid(&self) -> Result<()> {
+    pub fn is_valid(&self, params: &ChainParams) -> Result<()> {
         // 1. Check block hash matches header
         let computed_hash = self.header.hash();
         
@@ -159,27 +173,19 @@ impl Block {
 
         // 5. Validate each transaction
         for tx in &self.transactions {
-            tx.is_valid()?;
+            tx.is_valid(params)?;
         }
 
         Ok(())
     }
 
-    /// Get block reward for this height
-    pub fn get_block_reward(&self) -> Amount {
-        // Block reward halves every 210,000 blocks (like Bitcoin)
-        let halvings = self.height / 210_000;
-        
-        if halvings >= 64 {
-            return 0; // All coins mined
-        }
-
-        let initial_reward: Amount = 50 * 100_000_000; // 50 ATMN
-        initial_reward >> halvings // Divide by 2^halvings
-    }
-
-    /// Create coinbase transaction for this block
-    pub fn create_coinbase_tx(height: BlockHeight, miner_address: &str, block_reward: Amount) -> Transaction {
+    /// Create coinbase transaction for this block, paying `miner_address` on `params`' network
+    pub fn create_coinbase_tx(
+        height: BlockHeight,
+        miner_address: &str,
+        block_reward: Amount,
+        params: &ChainParams,
+    ) -> Result<Transaction> {
         use crate::transaction::{TxInput, TxOutput};
         use crate::types::TxHash;
         
@@ -191,21 +197,33 @@ impl Block {
             sequence: 0xFFFFFFFF,
         };
         
-        // Output to miner
+        // Output to miner (P2PKH)
+        let script_pubkey = crate::script::address_to_script_pubkey(miner_address, params)
+            .ok_or(crate::error::Error::InvalidAddress)?;
         let coinbase_output = TxOutput {
             amount: block_reward,
-            script_pubkey: miner_address.as_bytes().to_vec(), // Simple address encoding
+            script_pubkey,
         };
         
-        Transaction {
+        Ok(Transaction {
             version: 1,
             inputs: vec![coinbase_input],
             outputs: vec![coinbase_output],
             locktime: 0,
-        }
+        })
     }
 }
 
+/// Address paid by coinbases in tests that never spend them
+#[cfg(test)]
+pub(crate) const TEST_MINER_ADDRESS: &str = "ATMN_1e6df34f5f50ff6b581b827c5e9dc5b5b787e178";
+
+/// Coinbase paying `TEST_MINER_ADDRESS`
+#[cfg(test)]
+pub(crate) fn test_coinbase(height: BlockHeight, block_reward: Amount) -> Transaction {
+    Block::create_coinbase_tx(height, TEST_MINER_ADDRESS, block_reward, &ChainParams::mainnet()).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,15 +261,6 @@ mod tests {
         assert_eq!(merkle, BlockHash::zero());
     }
 
-    #[test]
-    fn test_block_reward() {
-        let block = Block::new(1, BlockHash::zero(), vec![], 1704067200, 0x1d00ffff, 0);
-        assert_eq!(block.get_block_reward(), 50 * 100_000_000);
-        
-        let block2 = Block::new(1, BlockHash::zero(), vec![], 1704067200, 0x1d00ffff, 210_000);
-        assert_eq!(block2.get_block_reward(), 25 * 100_000_000);
-    }
-
     #[test]
     fn test_header_serialization() {
         let header = BlockHeader {
@@ -266,4 +275,45 @@ mod tests {
         // BlockHeader size: version(4) + prev_hash(32) + merkle(32) + timestamp(4) + bits(4) + nonce(4) = 80 bytes
         assert_eq!(bytes.len(), 80);
     }
+
+    #[test]
+    fn test_midstate_matches_sha256d() {
+        let genesis = crate::genesis::create_genesis_block().header;
+        let mut header = BlockHeader {
+            version: 2,
+            prev_block_hash: BlockHash::from_bytes([0x11; 32]),
+            merkle_root: BlockHash::from_bytes([0xEE; 32]),
+            timestamp: 1704067200,
+            bits: 0x207fffff,
+            nonce: 0,
+        };
+
+        for header in [genesis, header.clone()] {
+            let midstate = header.midstate();
+            for nonce in [0, 1, header.nonce, 0xDEADBEEF, u32::MAX] {
+                let expected = sha256d(&BlockHeader { nonce, ..header.clone() }.serialize());
+                assert_eq!(midstate.hash(nonce), expected);
+            }
+        }
+
+        // A rolled timestamp reuses the midstate
+        let mut midstate = header.midstate();
+        midstate.set_timestamp(1704067260);
+        header.timestamp = 1704067260;
+        header.nonce = 42;
+        assert_eq!(midstate.hash(42), header.hash());
+    }
+
+    #[test]
+    fn test_block_size() {
+        let params = ChainParams::mainnet();
+        let miner = crate::types::Address::from_bytes([0x11; 20]).encode(&params);
+        let coinbase = Block::create_coinbase_tx(1, &miner, 50, &params).unwrap();
+        assert!(Block::create_coinbase_tx(1, "miner", 50, &params).is_err());
+        let tx_size = coinbase.size();
+        let block = Block::new(1, BlockHash::zero(), vec![coinbase], 1704067200, 0x1d00ffff, 1);
+        // header(80) + height(8) + tx count(1) + tx
+        assert_eq!(block.size(), 80 + 8 + 1 + tx_size);
+        assert_eq!(block.size(), block.serialize().len());
+    }
 }
@@ -0,0 +1,282 @@
+// atmn-core/src/block_assembler.rs
+// Block template assembly from the chain tip and mempool
+
+use crate::block::Block;
+use crate::consensus::{BlockSpends, Consensus, ValidationContext};
+use crate::encoding::{Encodable, BLOCK_HEADER_SIZE};
+use crate::mempool::Mempool;
+use crate::miner::{self, BlockTemplate};
+use crate::storage::{Storage, UtxoView};
+use crate::transaction::Transaction;
+use crate::types::{Amount, BlockHeight};
+use crate::error::Result;
+
+/// Version of assembled blocks
+pub const BLOCK_VERSION: u32 = 1;
+
+/// Block bytes besides its transactions: header, height and the largest
+/// transaction count varint
+const BLOCK_OVERHEAD: usize = BLOCK_HEADER_SIZE + 8 + 9;
+
+/// Builds the block templates every miner works on
+///
+/// A template extends the chain tip with the best-paying mempool packages
+/// that fit the block size limit, after a coinbase paying the block reward
+/// plus their fees to the miner. Each pooled transaction is checked again
+/// against the chain before it goes in.
+#[derive(Debug, Clone)]
+pub struct BlockAssembler {
+    consensus: Consensus,
+    /// Largest block to build, at most the consensus limit
+    max_block_size: usize,
+}
+
+impl BlockAssembler {
+    /// Assembler filling blocks up to the consensus size limit
+    pub fn new(consensus: Consensus) -> Self {
+        let max_block_size = consensus.chain_params.max_block_size as usize;
+        BlockAssembler { consensus, max_block_size }
+    }
+
+    /// Build smaller blocks than the consensus limit allows
+    pub fn with_max_block_size(mut self, max_block_size: usize) -> Self {
+        self.max_block_size = max_block_size.min(self.consensus.chain_params.max_block_size as usize);
+        self
+    }
+
+    /// Template for the block after the tip stored in `storage`
+    pub fn create_template(&self, storage: &Storage, mempool: &Mempool, miner_address: &str) -> Result<BlockTemplate> {
+        let ctx = self.consensus.context_for_next_block(storage)?;
+        self.create_template_for(&ctx, mempool, storage, miner_address)
+    }
+
+    /// Template for the block described by `ctx`, on the chain whose UTXO
+    /// set is `utxos`
+    ///
+    /// Pooled transactions failing the block's input checks, such as bad
+    /// scripts, spent inputs or unmet locks, are left out along with their
+    /// descendants. The timestamp is the current time from `ctx`, raised
+    /// past the median-time-past if the clock is behind. Fails if
+    /// `miner_address` is not an address on the chain's network.
+    pub fn create_template_for(
+        &self,
+        ctx: &ValidationContext,
+        mempool: &Mempool,
+        utxos: &dyn UtxoView,
+        miner_address: &str,
+    ) -> Result<BlockTemplate> {
+        let reward = self.consensus.get_block_reward(ctx.height);
+
+        // The coinbase's size doesn't depend on its value
+        let coinbase_size = self.coinbase(ctx.height, miner_address, reward)?.encoded_len();
+        let space = self.max_block_size.saturating_sub(BLOCK_OVERHEAD + coinbase_size);
+        let selected = mempool.get_block_transactions(space);
+
+        let mut spends = BlockSpends::default();
+        let mut total_fees: Amount = 0;
+        let mut included = Vec::with_capacity(selected.len());
+        for tx in selected {
+            // After the coinbase; a skipped parent leaves its descendants with
+            // missing inputs, so they are skipped too
+            let index = included.len() + 1;
+            if let Ok(fee) = self.consensus.check_block_transaction(&tx, index, ctx, utxos, &mut spends) {
+                total_fees += fee;
+                included.push(tx);
+            }
+        }
+        let coinbase = self.coinbase(ctx.height, miner_address, reward + total_fees)?;
+
+        let mut transactions = Vec::with_capacity(included.len() + 1);
+        transactions.push(coinbase);
+        transactions.extend(included);
+
+        Ok(BlockTemplate {
+            prev_block_hash: ctx.prev_hash,
+            merkle_root: Block::calculate_merkle_root(&transactions),
+            height: ctx.height,
+            transactions,
+            difficulty_bits: ctx.expected_bits,
+            version: BLOCK_VERSION,
+            template_time: ctx.now.max(ctx.median_time_past + 1),
+            total_fees,
+        })
+    }
+
+    /// Coinbase with room for the miner's extra nonce, starting at zero
+    fn coinbase(&self, height: BlockHeight, miner_address: &str, value: Amount) -> Result<Transaction> {
+        let mut coinbase = Block::create_coinbase_tx(height, miner_address, value, &self.consensus.chain_params)?;
+        miner::set_coinbase_extra_nonce(&mut coinbase, 0);
+        Ok(coinbase)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::block::TEST_MINER_ADDRESS;
+    use std::collections::HashMap;
+    use crate::chain_params::ChainParams;
+    use crate::consensus::{bits_to_target, verify_hash_difficulty};
+    use crate::script::{self, OP_1};
+    use crate::storage::{UtxoEntry, UtxoView};
+    use crate::transaction::{TxInput, TxOutput};
+    use crate::types::{BlockHash, OutPoint, Timestamp, TxHash};
+
+    /// Chain whose UTXO set is the map; empty for coinbase-only templates
+    #[derive(Default)]
+    pub(crate) struct TestUtxos(HashMap<OutPoint, UtxoEntry>);
+
+    impl UtxoView for TestUtxos {
+        fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
+            Ok(self.0.get(outpoint).cloned())
+        }
+
+        fn median_time_past(&self, _height: BlockHeight) -> Result<Timestamp> {
+            Ok(0)
+        }
+    }
+
+    /// Coinbase-only template for height 1 at `bits`
+    pub(crate) fn coinbase_only_template(bits: u32) -> BlockTemplate {
+        let ctx = ValidationContext {
+            prev_hash: BlockHash::zero(),
+            height: 1,
+            expected_bits: bits,
+            median_time_past: 0,
+            now: 1_000,
+        };
+        BlockAssembler::new(Consensus::new(ChainParams::regtest()))
+            .create_template_for(&ctx, &Mempool::new(), &TestUtxos::default(), TEST_MINER_ADDRESS)
+            .unwrap()
+    }
+
+    /// Pool holding one 1_000-sat-fee spend of each of `count` coins, and the
+    /// chain they were accepted on
+    fn pool_with_spends(count: u32) -> (Mempool, TestUtxos) {
+        let coin_hash = TxHash::from_bytes([1u8; 32]);
+        let utxos = TestUtxos((0..count).map(|index| {
+            let outpoint = OutPoint::new(coin_hash, index);
+            (outpoint, UtxoEntry {
+                tx_hash: coin_hash,
+                output_index: index,
+                amount: 100_000,
+                script_pubkey: vec![OP_1],
+                block_height: 1,
+                is_coinbase: false,
+            })
+        }).collect());
+
+        let consensus = Consensus::new(ChainParams::regtest());
+        let mut mempool = Mempool::new();
+        for index in 0..count {
+            let tx = Transaction {
+                version: 1,
+                inputs: vec![TxInput { prev_tx_hash: coin_hash, prev_tx_index: index, script: vec![], sequence: 0xFFFFFFFF }],
+                outputs: vec![TxOutput { amount: 99_000, script_pubkey: vec![OP_1] }],
+                locktime: 0,
+            };
+            mempool.accept_transaction(tx, &consensus, &context(), &utxos).unwrap();
+        }
+        (mempool, utxos)
+    }
+
+    fn context() -> ValidationContext {
+        ValidationContext {
+            prev_hash: BlockHash::from_bytes([7u8; 32]),
+            height: 5,
+            expected_bits: 0x207fffff,
+            median_time_past: 1_000,
+            now: 2_000,
+        }
+    }
+
+    #[test]
+    fn test_template_pays_reward_and_fees() {
+        let consensus = Consensus::new(ChainParams::regtest());
+        let (mempool, utxos) = pool_with_spends(3);
+        let ctx = context();
+
+        let assembler = BlockAssembler::new(consensus.clone());
+        assert!(assembler.create_template_for(&ctx, &mempool, &utxos, "miner").is_err());
+        let template = assembler.create_template_for(&ctx, &mempool, &utxos, TEST_MINER_ADDRESS).unwrap();
+        assert_eq!(template.transactions.len(), 4);
+        assert_eq!(template.total_fees, 3_000);
+        let coinbase = &template.transactions[0];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.total_output_amount(), consensus.get_block_reward(5) + 3_000);
+        assert_eq!(coinbase.inputs[0].script, [5u64.to_le_bytes(), [0u8; 8]].concat());
+
+        assert_eq!(template.prev_block_hash, ctx.prev_hash);
+        assert_eq!(template.height, 5);
+        assert_eq!(template.difficulty_bits, ctx.expected_bits);
+        assert_eq!(template.template_time, 2_000);
+        assert_eq!(template.merkle_root, Block::calculate_merkle_root(&template.transactions));
+    }
+
+    #[test]
+    fn test_template_fills_to_size_limit() {
+        let (mempool, utxos) = pool_with_spends(10);
+        let assembler = BlockAssembler::new(Consensus::new(ChainParams::regtest()));
+        let coinbase_size = assembler.coinbase(5, TEST_MINER_ADDRESS, 0).unwrap().encoded_len();
+        let tx_size = mempool.get_ordered_transactions(1)[0].size();
+
+        let max_block_size = BLOCK_OVERHEAD + coinbase_size + 4 * tx_size;
+        let template = assembler
+            .with_max_block_size(max_block_size)
+            .create_template_for(&context(), &mempool, &utxos, TEST_MINER_ADDRESS)
+            .unwrap();
+        assert_eq!(template.transactions.len(), 5);
+        assert_eq!(template.total_fees, 4_000);
+    }
+
+    #[test]
+    fn test_template_rechecks_pooled_transactions() {
+        let consensus = Consensus::new(ChainParams::regtest());
+        let (mut mempool, mut utxos) = pool_with_spends(3);
+        let coin_hash = TxHash::from_bytes([1u8; 32]);
+        let unsigned = mempool.get_spender(&OutPoint::new(coin_hash, 0)).unwrap();
+        let child = Transaction {
+            version: 1,
+            inputs: vec![TxInput { prev_tx_hash: unsigned, prev_tx_index: 0, script: vec![], sequence: 0xFFFFFFFF }],
+            outputs: vec![TxOutput { amount: 98_000, script_pubkey: vec![OP_1] }],
+            locktime: 0,
+        };
+        mempool.accept_transaction(child, &consensus, &context(), &utxos).unwrap();
+
+        // The pool checked them against an older chain: coin 0 now needs a
+        // signature its unsigned spend lacks, and coin 1 is already spent
+        let locked = script::p2pkh_script_pubkey(&[7u8; 20]);
+        utxos.0.get_mut(&OutPoint::new(coin_hash, 0)).unwrap().script_pubkey = locked;
+        utxos.0.remove(&OutPoint::new(coin_hash, 1));
+
+        let template = BlockAssembler::new(consensus).create_template_for(&context(), &mempool, &utxos, TEST_MINER_ADDRESS).unwrap();
+        assert_eq!(template.transactions.len(), 2);
+        assert_eq!(template.transactions[1].inputs[0].outpoint(), OutPoint::new(coin_hash, 2));
+        assert_eq!(template.total_fees, 1_000);
+    }
+
+    #[test]
+    fn test_template_mines_to_valid_block() {
+        let consensus = Consensus::new(ChainParams::regtest());
+        // Clock behind the median-time-past: the timestamp must still move forward
+        let ctx = ValidationContext { now: 500, ..context() };
+        let utxos = TestUtxos::default();
+        let template = BlockAssembler::new(consensus.clone()).create_template_for(&ctx, &Mempool::new(), &utxos, TEST_MINER_ADDRESS).unwrap();
+        assert_eq!(template.template_time, 1_001);
+
+        let mut block = Block::new(
+            template.version,
+            template.prev_block_hash,
+            template.transactions,
+            template.template_time,
+            template.difficulty_bits,
+            template.height,
+        );
+        let target = bits_to_target(block.header.bits);
+        while !verify_hash_difficulty(&block.hash(), &target) {
+            block.header.nonce += 1;
+        }
+        let ctx = ValidationContext { now: 2_000, ..ctx };
+        consensus.verify_block(&block, &ctx, &utxos).unwrap();
+    }
+}
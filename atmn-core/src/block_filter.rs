@@ -0,0 +1,349 @@
+// atmn-core/src/block_filter.rs
+// BIP158 compact block filters: Golomb-coded sets over block scripts
+
+use std::collections::BTreeSet;
+use crate::block::Block;
+use crate::consensus::sha256d;
+use crate::encoding::{self, Reader};
+use crate::types::BlockHash;
+use crate::error::{Error, Result};
+
+/// Golomb-Rice parameter of basic filters
+pub const FILTER_P: u8 = 19;
+
+/// Inverse false-positive rate of basic filters
+pub const FILTER_M: u64 = 784_931;
+
+/// Most filters returned by one range request
+pub const MAX_FILTER_RANGE: u64 = 1_000;
+
+/// Most filter headers returned by one range request
+pub const MAX_FILTER_HEADER_RANGE: u64 = 2_000;
+
+/// Leading byte of provably unspendable outputs, left out of filters
+const OP_RETURN: u8 = 0x6a;
+
+/// BIP158 basic filter of one block
+///
+/// Commits to every output script the block creates and every script it
+/// spends, so a light client can test its own scripts against the filter
+/// and fetch only the blocks that match. False positives occur about once
+/// in `FILTER_M` queries; there are no false negatives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockFilter {
+    /// Block the filter describes; its hash keys the set's hashing
+    pub block_hash: BlockHash,
+    /// Element count followed by the Golomb-Rice coded deltas
+    pub content: Vec<u8>,
+}
+
+impl BlockFilter {
+    /// Filter over `block` and the scripts of the outputs it spends, in any order
+    pub fn build<'a>(block: &'a Block, spent_scripts: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let created = block
+            .transactions
+            .iter()
+            .flat_map(|tx| tx.outputs.iter())
+            .map(|output| output.script_pubkey.as_slice())
+            .filter(|script| script.first() != Some(&OP_RETURN));
+        let elements: BTreeSet<&[u8]> = created
+            .chain(spent_scripts)
+            .filter(|script| !script.is_empty())
+            .collect();
+
+        Self::from_elements(block.hash(), elements)
+    }
+
+    /// Filter over exactly `elements`, which must be distinct
+    pub fn from_elements<'a>(block_hash: BlockHash, elements: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let elements: Vec<&[u8]> = elements.into_iter().collect();
+        let key = SipKey::from_block_hash(&block_hash);
+        let range = elements.len() as u64 * FILTER_M;
+        let mut values: Vec<u64> = elements.iter().map(|element| key.hash_to_range(element, range)).collect();
+        values.sort_unstable();
+
+        let mut content = Vec::new();
+        encoding::write_varint(&mut content, elements.len() as u64);
+        let mut writer = BitWriter::new(content);
+        let mut last = 0;
+        for value in values {
+            writer.write_golomb_rice(value - last);
+            last = value;
+        }
+
+        BlockFilter { block_hash, content: writer.finish() }
+    }
+
+    /// Number of elements in the set
+    pub fn element_count(&self) -> Result<u64> {
+        Reader::new(&self.content).read_varint()
+    }
+
+    /// Whether any of `scripts` may be in the block
+    pub fn match_any(&self, scripts: &[&[u8]]) -> Result<bool> {
+        let mut reader = Reader::new(&self.content);
+        let count = reader.read_varint()?;
+        if count == 0 || scripts.is_empty() {
+            return Ok(false);
+        }
+
+        let key = SipKey::from_block_hash(&self.block_hash);
+        let range = count
+            .checked_mul(FILTER_M)
+            .ok_or_else(|| Error::DecodingError("filter element count too large".to_string()))?;
+        let mut queries: Vec<u64> = scripts.iter().map(|script| key.hash_to_range(script, range)).collect();
+        queries.sort_unstable();
+
+        // Walk both sorted lists together
+        let mut bits = BitReader::new(reader.read_bytes(reader.remaining())?);
+        let mut value = 0u64;
+        let mut queries = queries.into_iter().peekable();
+        for _ in 0..count {
+            value += bits.read_golomb_rice()?;
+            while let Some(&query) = queries.peek() {
+                if query == value {
+                    return Ok(true);
+                }
+                if query > value {
+                    break;
+                }
+                queries.next();
+            }
+            if queries.peek().is_none() {
+                break;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Double SHA-256 of the serialized filter
+    pub fn filter_hash(&self) -> BlockHash {
+        sha256d(&self.content)
+    }
+
+    /// Header chaining this filter to the previous block's filter header
+    ///
+    /// The first block's predecessor is the zero hash.
+    pub fn header(&self, prev_header: &BlockHash) -> BlockHash {
+        filter_header(&self.filter_hash(), prev_header)
+    }
+}
+
+/// Filter header from a filter hash and the previous filter header
+pub fn filter_header(filter_hash: &BlockHash, prev_header: &BlockHash) -> BlockHash {
+    let mut preimage = [0u8; 64];
+    preimage[..32].copy_from_slice(&filter_hash.0);
+    preimage[32..].copy_from_slice(&prev_header.0);
+    sha256d(&preimage)
+}
+
+/// SipHash-2-4 key taken from the first 16 bytes of the block hash
+struct SipKey {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipKey {
+    fn from_block_hash(hash: &BlockHash) -> Self {
+        let bytes = &hash.0;
+        SipKey {
+            k0: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            k1: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        }
+    }
+
+    /// Map `data` uniformly onto `0..range`
+    fn hash_to_range(&self, data: &[u8], range: u64) -> u64 {
+        ((siphash24(self.k0, self.k1, data) as u128 * range as u128) >> 64) as u64
+    }
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+/// SipHash-2-4 of `data`
+fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v = [
+        k0 ^ 0x736f6d6570736575,
+        k1 ^ 0x646f72616e646f6d,
+        k0 ^ 0x6c7967656e657261,
+        k1 ^ 0x7465646279746573,
+    ];
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let m = u64::from_le_bytes(chunk.try_into().unwrap());
+        v[3] ^= m;
+        sip_round(&mut v);
+        sip_round(&mut v);
+        v[0] ^= m;
+    }
+
+    // Last block: remaining bytes with the length in the top byte
+    let mut last = [0u8; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    let m = u64::from_le_bytes(last) | ((data.len() as u64) << 56);
+    v[3] ^= m;
+    sip_round(&mut v);
+    sip_round(&mut v);
+    v[0] ^= m;
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+/// Most-significant-bit-first bit writer appending to a byte vector
+struct BitWriter {
+    out: Vec<u8>,
+    /// Bits used in the last byte, 0 when a new byte is needed
+    used: u8,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> Self {
+        BitWriter { out, used: 0 }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        if self.used == 0 {
+            self.out.push(0);
+        }
+        if bit {
+            *self.out.last_mut().unwrap() |= 0x80 >> self.used;
+        }
+        self.used = (self.used + 1) % 8;
+    }
+
+    /// Quotient in unary, then the low `FILTER_P` bits
+    fn write_golomb_rice(&mut self, value: u64) {
+        for _ in 0..value >> FILTER_P {
+            self.write_bit(true);
+        }
+        self.write_bit(false);
+        for shift in (0..FILTER_P).rev() {
+            self.write_bit((value >> shift) & 1 == 1);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+/// Reader for the bit stream written by `BitWriter`
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or_else(|| Error::DecodingError("filter bit stream truncated".to_string()))?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Ok(bit)
+    }
+
+    fn read_golomb_rice(&mut self) -> Result<u64> {
+        let mut quotient = 0u64;
+        while self.read_bit()? {
+            quotient += 1;
+        }
+        let mut remainder = 0u64;
+        for _ in 0..FILTER_P {
+            remainder = (remainder << 1) | self.read_bit()? as u64;
+        }
+        Ok((quotient << FILTER_P) | remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{Transaction, TxOutput};
+
+    /// Block hash as stored, from the byte-reversed hex shown by explorers
+    fn hash_from_display(hex_str: &str) -> BlockHash {
+        let mut bytes: [u8; 32] = hex::decode(hex_str).unwrap().try_into().unwrap();
+        bytes.reverse();
+        BlockHash::from_bytes(bytes)
+    }
+
+    #[test]
+    fn test_siphash_reference_vector() {
+        // Reference SipHash-2-4 output for key 00..0f and message 00..0e
+        let k0 = u64::from_le_bytes([0, 1, 2, 3, 4, 5, 6, 7]);
+        let k1 = u64::from_le_bytes([8, 9, 10, 11, 12, 13, 14, 15]);
+        let data: Vec<u8> = (0..15).collect();
+        assert_eq!(siphash24(k0, k1, &data), 0xa129ca6149be45e5);
+        assert_eq!(siphash24(k0, k1, &[]), 0x726fdb47dd0e0e31);
+    }
+
+    #[test]
+    fn test_bip158_testnet_genesis_vector() {
+        // BIP158 test vector: the testnet genesis block's single output script
+        let block_hash = hash_from_display("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943");
+        let script = hex::decode(
+            "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac",
+        )
+        .unwrap();
+
+        let filter = BlockFilter::from_elements(block_hash, [script.as_slice()]);
+        assert_eq!(hex::encode(&filter.content), "019dfca8");
+        assert_eq!(filter.element_count().unwrap(), 1);
+
+        let header = filter.header(&BlockHash::zero());
+        assert_eq!(header, hash_from_display("21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750"));
+    }
+
+    #[test]
+    fn test_block_filter_matches_its_scripts() {
+        let payout = vec![0x76, 0xa9, 0x14, 0x01];
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![],
+            outputs: vec![
+                TxOutput { amount: 1, script_pubkey: payout.clone() },
+                TxOutput { amount: 0, script_pubkey: vec![OP_RETURN, 0x01] },
+                TxOutput { amount: 1, script_pubkey: payout.clone() },
+            ],
+            locktime: 0,
+        };
+        let block = Block::new(1, BlockHash::zero(), vec![tx], 1_000, 0x207fffff, 1);
+        let spent = vec![0x51u8, 0x52];
+
+        let filter = BlockFilter::build(&block, [spent.as_slice(), &[][..]]);
+        assert_eq!(filter.block_hash, block.hash());
+        // The payout once, the spent script; no OP_RETURN or empty script
+        assert_eq!(filter.element_count().unwrap(), 2);
+
+        assert!(filter.match_any(&[&payout]).unwrap());
+        assert!(filter.match_any(&[b"other", &spent]).unwrap());
+        assert!(!filter.match_any(&[b"other"]).unwrap());
+        assert!(!filter.match_any(&[&[OP_RETURN, 0x01][..]]).unwrap());
+
+        let empty = BlockFilter::from_elements(block.hash(), []);
+        assert_eq!(empty.content, vec![0]);
+        assert!(!empty.match_any(&[&payout]).unwrap());
+    }
+}
@@ -0,0 +1,416 @@
+// atmn-core/src/chain.rs
+// Block tree with most-work fork choice and chain reorganization
+//
+// Every accepted block gets a BlockIndexEntry with its cumulative chainwork.
+// Blocks off the active chain are kept in the side-block store. When a block
+// makes a branch heavier than the current tip, the active chain is rewound to
+// the fork point and the new branch is connected with full validation.
+
+use std::collections::HashSet;
+use crate::block::Block;
+use crate::chain_params::ChainParams;
+use crate::consensus::{Consensus, ProofOfWork};
+use crate::error::{Error, Result, ValidationError};
+use crate::storage::{BlockIndexEntry, Storage};
+use crate::transaction::Transaction;
+use crate::types::BlockHash;
+
+/// Changes to the active chain caused by processing one block
+#[derive(Debug, Clone, Default)]
+pub struct ChainUpdate {
+    /// Blocks connected, oldest first
+    pub connected: Vec<Block>,
+    /// Blocks disconnected, tip first
+    pub disconnected: Vec<Block>,
+    /// Non-coinbase transactions from disconnected blocks that the new branch
+    /// does not include, in chain order; the mempool should re-add them
+    pub disconnected_transactions: Vec<Transaction>,
+}
+
+impl ChainUpdate {
+    /// True if the active tip changed
+    pub fn tip_changed(&self) -> bool {
+        !self.connected.is_empty()
+    }
+
+    /// True if blocks were disconnected
+    pub fn is_reorg(&self) -> bool {
+        !self.disconnected.is_empty()
+    }
+}
+
+/// Tracks the block tree and keeps the most-work chain active
+#[derive(Clone)]
+pub struct ChainManager {
+    storage: Storage,
+    consensus: Consensus,
+}
+
+impl ChainManager {
+    pub fn new(storage: Storage, chain_params: ChainParams) -> Self {
+        Self {
+            storage,
+            consensus: Consensus::new(chain_params),
+        }
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn consensus(&self) -> &Consensus {
+        &self.consensus
+    }
+
+    /// Index entry of the active tip
+    pub fn tip(&self) -> Result<Option<BlockIndexEntry>> {
+        let Some(height) = self.storage.get_best_height()? else {
+            return Ok(None);
+        };
+        let hash = self.storage.get_block_hash(height)?
+            .ok_or(Error::InvalidBlockHeight(height))?;
+        let entry = self.storage.get_block_index(&hash)?
+            .ok_or_else(|| Error::DatabaseError(format!("No index entry for tip {}", hash)))?;
+        Ok(Some(entry))
+    }
+
+    /// Accept a block into the tree and switch to it if it ends the most-work chain
+    pub fn process_block(&self, block: Block) -> Result<ChainUpdate> {
+        let hash = block.hash();
+        if self.storage.get_block_index(&hash)?.is_some() {
+            return Err(Error::DuplicateBlock);
+        }
+
+        let tip = self.tip()?;
+        match self.storage.get_block_index(&block.header.prev_block_hash)? {
+            Some(parent) => {
+                if parent.invalid {
+                    return Err(Error::InvalidBlock("Block builds on an invalid block".to_string()));
+                }
+                if block.height != parent.height + 1 {
+                    return Err(ValidationError::BadHeight {
+                        expected: parent.height + 1,
+                        actual: block.height,
+                    }.into());
+                }
+            }
+            // Only the first block of an empty chain may have no parent
+            None if tip.is_none() && block.header.prev_block_hash == BlockHash::zero() => {}
+            None => return Err(Error::OrphanBlock),
+        }
+
+        // Cheap checks before storing anything: the header meets its own
+        // target, and that target is no easier than the network allows
+        self.consensus.check_pow_limit(block.header.bits)?;
+        ProofOfWork::new(block.header.bits).verify_block(&block)?;
+        // A body that doesn't match its header is rejected before the hash
+        // is indexed, so the genuine block can still arrive
+        self.consensus.check_merkle_root(&block)?;
+
+        let entry = self.storage.index_entry_for(&block)?;
+        self.storage.put_side_block(&block)?;
+        self.storage.put_block_index(&entry)?;
+
+        match tip {
+            Some(tip) if entry.chainwork <= tip.chainwork => Ok(ChainUpdate::default()),
+            tip => self.reorganize(tip, &entry),
+        }
+    }
+
+    /// Make `new_tip` the active tip
+    fn reorganize(&self, tip: Option<BlockIndexEntry>, new_tip: &BlockIndexEntry) -> Result<ChainUpdate> {
+        // Walk back from the new tip to the first block on the active chain
+        let mut branch = Vec::new();
+        let mut fork = None;
+        let mut cursor = Some(new_tip.clone());
+        while let Some(entry) = cursor {
+            if self.is_active(&entry)? {
+                fork = Some(entry);
+                break;
+            }
+            if entry.invalid {
+                return Err(Error::InvalidBlock(format!("Branch contains invalid block {}", entry.hash)));
+            }
+            cursor = self.storage.get_block_index(&entry.prev_hash)?;
+            branch.push(entry);
+        }
+        branch.reverse();
+
+        let depth = match (&tip, &fork) {
+            (Some(tip), Some(fork)) => tip.height - fork.height,
+            (Some(tip), None) => tip.height + 1,
+            (None, _) => 0,
+        };
+        let max = self.consensus.chain_params.max_reorg_depth;
+        if depth > max as u64 {
+            return Err(Error::ReorgTooDeep { depth, max });
+        }
+
+        let mut update = ChainUpdate::default();
+        for _ in 0..depth {
+            let block = self.storage.disconnect_block()?;
+            self.storage.put_side_block(&block)?;
+            update.disconnected.push(block);
+        }
+
+        for entry in &branch {
+            let block = self.storage.get_side_block(&entry.hash)?
+                .ok_or_else(|| Error::DatabaseError(format!("Missing block data for {}", entry.hash)))?;
+
+            if let Err(e) = self.connect_block(&block) {
+                self.storage.put_block_index(&BlockIndexEntry { invalid: true, ..entry.clone() })?;
+                self.restore(&update)?;
+                return Err(e);
+            }
+            update.connected.push(block);
+        }
+
+        // Transactions confirmed again on the new branch stay out of the mempool
+        let reconfirmed: HashSet<_> = update.connected.iter()
+            .flat_map(|block| block.transactions.iter().map(|tx| tx.hash()))
+            .collect();
+        update.disconnected_transactions = update.disconnected.iter()
+            .rev()
+            .flat_map(|block| block.transactions.iter())
+            .filter(|tx| !tx.is_coinbase() && !reconfirmed.contains(&tx.hash()))
+            .cloned()
+            .collect();
+
+        Ok(update)
+    }
+
+    /// Validate a block against the current tip and connect it
+    fn connect_block(&self, block: &Block) -> Result<()> {
+        let ctx = self.consensus.context_for_next_block(&self.storage)?;
+        self.consensus.verify_block(block, &ctx, &self.storage)?;
+        self.storage.put_block(block.height, block)?;
+        self.storage.delete_side_block(&block.hash())
+    }
+
+    /// Undo a failed reorganization, reconnecting the previous chain
+    fn restore(&self, update: &ChainUpdate) -> Result<()> {
+        for _ in 0..update.connected.len() {
+            let block = self.storage.disconnect_block()?;
+            self.storage.put_side_block(&block)?;
+        }
+        for block in update.disconnected.iter().rev() {
+            self.storage.put_block(block.height, block)?;
+            self.storage.delete_side_block(&block.hash())?;
+        }
+        Ok(())
+    }
+
+    /// True if the entry is on the active chain
+    fn is_active(&self, entry: &BlockIndexEntry) -> Result<bool> {
+        Ok(self.storage.get_block_hash(entry.height)? == Some(entry.hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script;
+    use crate::consensus::{bits_to_target, verify_hash_difficulty};
+    use crate::tx_builder::{secret_key_address, TransactionBuilder};
+    use secp256k1::SecretKey;
+    use tempfile::TempDir;
+
+    fn mine(mut block: Block) -> Block {
+        let target = bits_to_target(block.header.bits);
+        while !verify_hash_difficulty(&block.hash(), &target) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    fn genesis(secret: &SecretKey) -> Block {
+        let params = ChainParams::regtest();
+        let coinbase = Block::create_coinbase_tx(0, &secret_key_address(secret), 10_000, &params).unwrap();
+        mine(Block::new(1, BlockHash::zero(), vec![coinbase], params.genesis_timestamp, params.genesis_bits, 0))
+    }
+
+    /// Child block paying its coinbase to an address derived from `miner`
+    fn child(parent: &Block, miner: &str, mut txs: Vec<Transaction>) -> Block {
+        let height = parent.height + 1;
+        let address = script::pubkey_hash_to_address(&script::hash160(miner.as_bytes()));
+        txs.insert(0, Block::create_coinbase_tx(height, &address, 1, &ChainParams::regtest()).unwrap());
+        mine(Block::new(1, parent.hash(), txs, parent.header.timestamp + 1, parent.header.bits, height))
+    }
+
+    fn setup(secret: &SecretKey, params: ChainParams) -> (ChainManager, Block, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let chain = ChainManager::new(Storage::new(temp_dir.path()).unwrap(), params);
+        let genesis = genesis(secret);
+        let update = chain.process_block(genesis.clone()).unwrap();
+        assert_eq!(update.connected.len(), 1);
+        (chain, genesis, temp_dir)
+    }
+
+    fn payment(chain: &ChainManager, secret: &SecretKey) -> Transaction {
+        let builder = TransactionBuilder::new(chain.storage().clone()).with_chain_params(ChainParams::regtest());
+        let to = crate::script::pubkey_hash_to_address(&[0x66; 20]);
+        let mut tx = builder.create_payment(&secret_key_address(secret), &to, 6_000, 100).unwrap();
+        builder.sign_all(&mut tx, &[*secret]).unwrap();
+        tx
+    }
+
+    fn tip_hash(chain: &ChainManager) -> BlockHash {
+        chain.tip().unwrap().unwrap().hash
+    }
+
+    #[test]
+    fn test_extends_tip() {
+        let secret = SecretKey::from_slice(&[0x41; 32]).unwrap();
+        let (chain, genesis, _temp_dir) = setup(&secret, ChainParams::regtest());
+
+        let a1 = child(&genesis, "a", vec![payment(&chain, &secret)]);
+        let update = chain.process_block(a1.clone()).unwrap();
+        assert!(update.tip_changed());
+        assert!(!update.is_reorg());
+
+        let tip = chain.tip().unwrap().unwrap();
+        assert_eq!(tip.hash, a1.hash());
+        assert_eq!(tip.height, 1);
+        assert_eq!(tip.chainwork, crate::uint::U256::from_u64(4));
+        assert!(matches!(chain.process_block(a1), Err(Error::DuplicateBlock)));
+    }
+
+    #[test]
+    fn test_mutated_block_does_not_poison_hash() {
+        let secret = SecretKey::from_slice(&[0x47; 32]).unwrap();
+        let (chain, genesis, _temp_dir) = setup(&secret, ChainParams::regtest());
+
+        let a1 = child(&genesis, "a", vec![payment(&chain, &secret), crate::block::test_coinbase(7, 1)]);
+        // Repeating the last of three transactions keeps the root and hash
+        let mut mutated = a1.clone();
+        mutated.transactions.push(a1.transactions[2].clone());
+        assert_eq!(mutated.hash(), a1.hash());
+
+        match chain.process_block(mutated) {
+            Err(Error::Validation(e)) => assert_eq!(e.code(), "bad-txns-duplicate"),
+            other => panic!("expected validation error, got {:?}", other),
+        }
+        assert!(chain.storage().get_block_index(&a1.hash()).unwrap().is_none());
+
+        // The block itself is still judged on its own body
+        match chain.process_block(a1) {
+            Err(Error::Validation(e)) => assert_eq!(e.code(), "bad-cb-multiple"),
+            other => panic!("expected validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_orphan_rejected() {
+        let secret = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let (chain, genesis, _temp_dir) = setup(&secret, ChainParams::regtest());
+
+        let a1 = child(&genesis, "a", vec![]);
+        let a2 = child(&a1, "a", vec![]);
+        assert!(matches!(chain.process_block(a2), Err(Error::OrphanBlock)));
+    }
+
+    #[test]
+    fn test_equal_work_branch_stays_on_side() {
+        let secret = SecretKey::from_slice(&[0x43; 32]).unwrap();
+        let (chain, genesis, _temp_dir) = setup(&secret, ChainParams::regtest());
+
+        let a1 = child(&genesis, "a", vec![]);
+        let b1 = child(&genesis, "b", vec![]);
+        chain.process_block(a1.clone()).unwrap();
+        let update = chain.process_block(b1.clone()).unwrap();
+
+        assert!(!update.tip_changed());
+        assert_eq!(tip_hash(&chain), a1.hash());
+        assert!(chain.storage().get_side_block(&b1.hash()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reorg_to_most_work_branch() {
+        let secret = SecretKey::from_slice(&[0x44; 32]).unwrap();
+        let (chain, genesis, _temp_dir) = setup(&secret, ChainParams::regtest());
+        let storage = chain.storage().clone();
+        let funding = genesis.transactions[0].hash();
+
+        let spend = payment(&chain, &secret);
+        let a1 = child(&genesis, "a", vec![spend.clone()]);
+        let a2 = child(&a1, "a", vec![]);
+        chain.process_block(a1.clone()).unwrap();
+        chain.process_block(a2.clone()).unwrap();
+        assert!(storage.get_utxo(&funding, 0).unwrap().is_none());
+
+        let b1 = child(&genesis, "b", vec![]);
+        let b2 = child(&b1, "b", vec![]);
+        let b3 = child(&b2, "b", vec![]);
+        assert!(!chain.process_block(b1).unwrap().tip_changed());
+        assert!(!chain.process_block(b2).unwrap().tip_changed());
+
+        let update = chain.process_block(b3.clone()).unwrap();
+        assert!(update.is_reorg());
+        assert_eq!(update.disconnected.len(), 2);
+        assert_eq!(update.connected.len(), 3);
+        assert_eq!(update.disconnected_transactions.len(), 1);
+        assert_eq!(update.disconnected_transactions[0].hash(), spend.hash());
+
+        assert_eq!(tip_hash(&chain), b3.hash());
+        assert_eq!(storage.get_best_height().unwrap(), Some(3));
+
+        // The spent output is back and the spend's outputs are gone
+        assert!(storage.get_utxo(&funding, 0).unwrap().is_some());
+        assert!(storage.get_utxo(&spend.hash(), 0).unwrap().is_none());
+        assert!(storage.get_transaction(&spend.hash()).unwrap().is_none());
+        assert!(storage.get_side_block(&a1.hash()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reorg_depth_limit() {
+        let secret = SecretKey::from_slice(&[0x45; 32]).unwrap();
+        let mut params = ChainParams::regtest();
+        params.max_reorg_depth = 1;
+        let (chain, genesis, _temp_dir) = setup(&secret, params);
+
+        let a1 = child(&genesis, "a", vec![]);
+        let a2 = child(&a1, "a", vec![]);
+        chain.process_block(a1).unwrap();
+        chain.process_block(a2.clone()).unwrap();
+
+        let b1 = child(&genesis, "b", vec![]);
+        let b2 = child(&b1, "b", vec![]);
+        let b3 = child(&b2, "b", vec![]);
+        chain.process_block(b1).unwrap();
+        chain.process_block(b2).unwrap();
+        assert!(matches!(chain.process_block(b3), Err(Error::ReorgTooDeep { depth: 2, max: 1 })));
+        assert_eq!(tip_hash(&chain), a2.hash());
+    }
+
+    #[test]
+    fn test_invalid_branch_restores_chain() {
+        let secret = SecretKey::from_slice(&[0x46; 32]).unwrap();
+        let (chain, genesis, _temp_dir) = setup(&secret, ChainParams::regtest());
+        let storage = chain.storage().clone();
+
+        let spend = payment(&chain, &secret);
+        let a1 = child(&genesis, "a", vec![spend.clone()]);
+        chain.process_block(a1.clone()).unwrap();
+
+        // b2 claims far more than the block reward
+        let b1 = child(&genesis, "b", vec![]);
+        let mut b2 = child(&b1, "b", vec![]);
+        b2.transactions[0].outputs[0].amount = 100 * 100_000_000;
+        b2.header.merkle_root = Block::calculate_merkle_root(&b2.transactions);
+        let b2 = mine(b2);
+        chain.process_block(b1.clone()).unwrap();
+
+        match chain.process_block(b2.clone()) {
+            Err(Error::Validation(e)) => assert_eq!(e.code(), "bad-cb-amount"),
+            other => panic!("expected validation error, got {:?}", other),
+        }
+
+        assert_eq!(tip_hash(&chain), a1.hash());
+        assert!(storage.get_transaction(&spend.hash()).unwrap().is_some());
+        assert!(storage.get_utxo(&spend.hash(), 0).unwrap().is_some());
+        assert!(storage.get_block_index(&b2.hash()).unwrap().unwrap().invalid);
+
+        let b3 = child(&b2, "b", vec![]);
+        assert!(chain.process_block(b3).is_err());
+    }
+}
@@ -19,11 +19,20 @@ pub struct ChainParams {
     /// RPC Port
     pub rpc_port: u16,
     
+    /// Human-readable prefix of addresses on this network
+    pub address_hrp: String,
+    
     /// Block time in seconds
     pub block_time: u32,
     
-    /// Target timespan in seconds for difficulty adjustment
-    pub target_timespan: u32,
+    /// Number of recent blocks the LWMA difficulty algorithm averages over
+    pub difficulty_window: u32,
+    
+    /// First height whose bits come from LWMA; earlier blocks keep their parent's bits
+    pub difficulty_activation_height: BlockHeight,
+    
+    /// Never retarget: every block keeps its parent's bits (regtest)
+    pub no_retargeting: bool,
     
     /// Maximum block size in bytes
     pub max_block_size: u32,
@@ -64,8 +73,11 @@ impl ChainParams {
             magic_bytes: [0xa7, 0xc2, 0xd2, 0xf9],
             p2p_port: 7676,
             rpc_port: 7674,
+            address_hrp: "atmn".to_string(),
             block_time: 12,  // 12 seconds
-            target_timespan: 20 * 60,  // 20 minutes
+            difficulty_window: 90,  // 18 minutes of 12-second blocks
+            difficulty_activation_height: 5_000,  // Blocks below were mined at fixed difficulty
+            no_retargeting: false,
             max_block_size: 8 * 1024 * 1024,  // 8 MB
             max_tx_size: 1024 * 1024,  // 1 MB
             total_supply: 500_000_000 * SATOSHI_PER_ATMN,  // 500M ATMN
@@ -86,8 +98,11 @@ impl ChainParams {
             magic_bytes: [0x09, 0x11, 0x05, 0x88],
             p2p_port: 17676,
             rpc_port: 17674,
+            address_hrp: "tatmn".to_string(),
             block_time: 12,
-            target_timespan: 20 * 60,
+            difficulty_window: 90,
+            difficulty_activation_height: 0,
+            no_retargeting: false,
             max_block_size: 8 * 1024 * 1024,
             max_tx_size: 1024 * 1024,
             total_supply: 500_000_000 * SATOSHI_PER_ATMN,
@@ -108,8 +123,11 @@ impl ChainParams {
             magic_bytes: [0xfa, 0xbf, 0xb5, 0xda],
             p2p_port: 18444,
             rpc_port: 18332,
+            address_hrp: "ratmn".to_string(),
             block_time: 1,  // 1 second for testing
-            target_timespan: 10 * 60,
+            difficulty_window: 90,
+            difficulty_activation_height: 0,
+            no_retargeting: true,
             max_block_size: 8 * 1024 * 1024,
             max_tx_size: 1024 * 1024,
             total_supply: 500_000_000 * SATOSHI_PER_ATMN,
@@ -123,14 +141,61 @@ impl ChainParams {
         }
     }
     
-    /// Get block reward for given height (Pure PoW model)
+    /// Block subsidy at `height`: `genesis_subsidy` for genesis, then
+    /// `REWARD_SCHEDULE`, and zero once `total_supply` has been issued
     pub fn get_block_reward(&self, height: BlockHeight) -> Amount {
         match height {
-            0 => self.genesis_subsidy,
-            1..=525_600 => 50 * SATOSHI_PER_ATMN,  // Year 1: 50 ATMN
-            525_601..=1_051_200 => 25 * SATOSHI_PER_ATMN,  // Year 2: 25 ATMN
-            1_051_201..=2_628_000 => 12_500_000_000,  // Year 3: 12.5 ATMN (1250M satoshi)
-            _ => 6_250_000_000,  // Year 4+: 6.25 ATMN (indefinite)
+            0 => self.issued_supply(0),
+            _ => self.issued_supply(height) - self.issued_supply(height - 1),
+        }
+    }
+    
+    /// Total subsidy of blocks `0..=height`, never more than `total_supply`
+    pub fn issued_supply(&self, height: BlockHeight) -> Amount {
+        let mut issued = self.genesis_subsidy as u128;
+        let mut first = 1;
+        for &(last, reward) in &REWARD_SCHEDULE {
+            if first > height {
+                break;
+            }
+            let blocks = last.min(height) - first + 1;
+            issued += blocks as u128 * reward as u128;
+            first = last.saturating_add(1);
+        }
+        issued.min(self.total_supply as u128) as Amount
+    }
+    
+    /// Height of the last block paying a subsidy, or None if the cap is never reached
+    pub fn final_subsidy_height(&self) -> Option<BlockHeight> {
+        if self.genesis_subsidy >= self.total_supply {
+            return Some(0);
+        }
+        let mut issued = self.genesis_subsidy;
+        let mut first = 1;
+        for &(last, reward) in &REWARD_SCHEDULE {
+            let remaining = self.total_supply - issued;
+            if reward > 0 {
+                let needed = remaining.div_ceil(reward);
+                if needed <= last - first + 1 {
+                    return Some(first + needed - 1);
+                }
+                issued += (last - first + 1) * reward;
+            }
+            first = last.saturating_add(1);
+        }
+        None
+    }
+    
+    /// Issuance at `height` measured against `total_supply`
+    pub fn supply_projection(&self, height: BlockHeight) -> SupplyProjection {
+        let issued = self.issued_supply(height);
+        SupplyProjection {
+            height,
+            block_reward: self.get_block_reward(height),
+            issued,
+            remaining: self.total_supply - issued,
+            total_supply: self.total_supply,
+            final_subsidy_height: self.final_subsidy_height(),
         }
     }
     
@@ -141,9 +206,34 @@ impl ChainParams {
 
 }
 
+/// Issuance at one height against the supply cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupplyProjection {
+    pub height: BlockHeight,
+    /// Subsidy of the block at `height`
+    pub block_reward: Amount,
+    /// Total subsidy of blocks `0..=height`
+    pub issued: Amount,
+    /// Subsidy still to be issued after `height`
+    pub remaining: Amount,
+    pub total_supply: Amount,
+    /// Last block paying a subsidy
+    pub final_subsidy_height: Option<BlockHeight>,
+}
+
 // Constants
 pub const SATOSHI_PER_ATMN: u64 = 100_000_000;  // 1 ATMN = 100M satoshis
 
+/// Reward per block after genesis: (last height of era, reward)
+///
+/// The last era runs until `total_supply` is reached.
+pub const REWARD_SCHEDULE: [(BlockHeight, Amount); 4] = [
+    (525_600, 50 * SATOSHI_PER_ATMN),  // Year 1: 50 ATMN
+    (1_051_200, 25 * SATOSHI_PER_ATMN),  // Year 2: 25 ATMN
+    (2_628_000, 1_250_000_000),  // Years 3-5: 12.5 ATMN
+    (BlockHeight::MAX, 625_000_000),  // Then 6.25 ATMN until the cap
+];
+
 /// PoW limit for mainnet
 pub const POW_LIMIT_MAINNET: [u8; 32] = [
     0x00, 0x00, 0x0f, 0xff, 0x00, 0x00, 0x00, 0x00,
@@ -195,8 +285,12 @@ mod tests {
         assert_eq!(params.get_block_reward(525_601), 25 * SATOSHI_PER_ATMN);
         assert_eq!(params.get_block_reward(1_051_200), 25 * SATOSHI_PER_ATMN);
         
-        // Year 3+
-        assert_eq!(params.get_block_reward(1_051_201), 12_500_000_000);
+        // Years 3-5
+        assert_eq!(params.get_block_reward(1_051_201), 1_250_000_000);
+        assert_eq!(params.get_block_reward(2_628_000), 1_250_000_000);
+        
+        // Tail
+        assert_eq!(params.get_block_reward(2_628_001), 625_000_000);
     }
 
     #[test]
@@ -208,4 +302,43 @@ mod tests {
         assert!(params.is_pow_phase(5_256_000));
         assert!(params.is_pow_phase(10_000_000));
     }
+
+    #[test]
+    fn test_issued_supply_matches_rewards() {
+        let params = ChainParams::mainnet();
+        assert_eq!(params.issued_supply(0), params.genesis_subsidy);
+        assert_eq!(params.issued_supply(100), params.genesis_subsidy + 100 * 50 * SATOSHI_PER_ATMN);
+        
+        // Summing rewards across each era boundary gives the same total
+        for &(last, _) in &REWARD_SCHEDULE[..3] {
+            let before = params.issued_supply(last - 2);
+            let summed: Amount = (last - 1..=last + 1).map(|h| params.get_block_reward(h)).sum();
+            assert_eq!(params.issued_supply(last + 1), before + summed);
+        }
+        
+        // 50M genesis + 26.28M + 13.14M + 19.71M
+        assert_eq!(params.issued_supply(2_628_000), 109_130_000 * SATOSHI_PER_ATMN);
+    }
+
+    #[test]
+    fn test_supply_cap() {
+        let params = ChainParams::mainnet();
+        // (500M - 109.13M) / 6.25 ATMN = 62,539,200 tail blocks
+        let last = params.final_subsidy_height().unwrap();
+        assert_eq!(last, 2_628_000 + 62_539_200);
+        assert_eq!(params.get_block_reward(last), 625_000_000);
+        assert_eq!(params.get_block_reward(last + 1), 0);
+        assert_eq!(params.issued_supply(last), params.total_supply);
+        assert_eq!(params.issued_supply(BlockHeight::MAX), params.total_supply);
+        
+        let projection = params.supply_projection(1);
+        assert_eq!(projection.issued + projection.remaining, params.total_supply);
+        assert_eq!(projection.block_reward, 50 * SATOSHI_PER_ATMN);
+        
+        // A cap inside an era ends issuance part-way through a block reward
+        let small = ChainParams { total_supply: params.genesis_subsidy + 75 * SATOSHI_PER_ATMN, ..params };
+        assert_eq!(small.final_subsidy_height(), Some(2));
+        assert_eq!(small.get_block_reward(2), 25 * SATOSHI_PER_ATMN);
+        assert_eq!(small.get_block_reward(3), 0);
+    }
 }
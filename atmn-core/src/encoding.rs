@@ -0,0 +1,531 @@
+// atmn-core/src/encoding.rs
+// Consensus binary encoding for blocks and transactions
+//
+// Format (version 1):
+// - integers are fixed-width little-endian (u32, u64)
+// - hashes are 32 raw bytes
+// - counts and byte-string lengths use Bitcoin-style CompactSize varints,
+//   which must be minimally encoded
+// - scripts are length-prefixed (varint length + bytes)
+//
+// BlockHeader:  version u32 | prev hash 32 | merkle root 32 | timestamp u32 | bits u32 | nonce u32
+// TxInput:      prev tx hash 32 | prev index u32 | script var_bytes | sequence u32
+// TxOutput:     amount u64 | script_pubkey var_bytes
+// Transaction:  version u32 | varint n | inputs | varint n | outputs | locktime u32
+// Block:        header (80) | height u64 | varint n | transactions
+//
+// Header and transaction bytes are what get hashed. Anything persisted or sent
+// to peers is prefixed with a one-byte ENCODING_VERSION so the format can evolve.
+// Databases written before the version prefix hold bincode records, which
+// `deserialize_versioned` rejects; `Storage` detects them and rebuilds them
+// from their blocks when opened.
+
+use crate::block::{Block, BlockHeader};
+use crate::error::{Error, Result};
+use crate::transaction::{Transaction, TxInput, TxOutput};
+use crate::types::{BlockHash, TxHash};
+
+/// Current encoding version used for storage and P2P payloads
+pub const ENCODING_VERSION: u8 = 1;
+
+/// Serialized block header size in bytes
+pub const BLOCK_HEADER_SIZE: usize = 80;
+
+/// Types with a consensus binary encoding
+pub trait Encodable {
+    /// Append the encoding of `self` to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Encoded size in bytes
+    fn encoded_len(&self) -> usize {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out.len()
+    }
+}
+
+/// Types that can be decoded from the consensus binary encoding
+pub trait Decodable: Sized {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self>;
+}
+
+/// Encode a value to a new byte vector
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.encoded_len());
+    value.encode(&mut out);
+    out
+}
+
+/// Decode a value, requiring that every byte is consumed
+pub fn deserialize<T: Decodable>(bytes: &[u8]) -> Result<T> {
+    let mut reader = Reader::new(bytes);
+    let value = T::decode(&mut reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Encode a value prefixed with ENCODING_VERSION (storage and P2P payloads)
+pub fn serialize_versioned<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + value.encoded_len());
+    out.push(ENCODING_VERSION);
+    value.encode(&mut out);
+    out
+}
+
+/// Decode a value written by `serialize_versioned`
+pub fn deserialize_versioned<T: Decodable>(bytes: &[u8]) -> Result<T> {
+    match bytes.split_first() {
+        Some((&ENCODING_VERSION, rest)) => deserialize(rest),
+        Some((version, _)) => Err(Error::DecodingError(format!(
+            "unsupported encoding version {}",
+            version
+        ))),
+        None => Err(Error::DecodingError("empty payload".to_string())),
+    }
+}
+
+/// Append a CompactSize varint
+pub fn write_varint(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+/// Length of a CompactSize varint
+pub fn varint_len(n: u64) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Append a length-prefixed byte string
+pub fn write_var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Length of a length-prefixed byte string
+pub fn var_bytes_len(bytes: &[u8]) -> usize {
+    varint_len(bytes.len() as u64) + bytes.len()
+}
+
+/// Bounds-checked cursor over encoded bytes
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    /// Bytes not yet consumed
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Fail if any bytes are left over
+    pub fn finish(&self) -> Result<()> {
+        if self.remaining() != 0 {
+            return Err(Error::DecodingError(format!(
+                "{} trailing bytes",
+                self.remaining()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            return Err(Error::DecodingError(format!(
+                "unexpected end of data: need {} bytes, have {}",
+                len,
+                self.remaining()
+            )));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Read a CompactSize varint, rejecting non-minimal encodings
+    pub fn read_varint(&mut self) -> Result<u64> {
+        let (value, min) = match self.read_u8()? {
+            0xff => (self.read_u64()?, 0x1_0000_0000),
+            0xfe => (self.read_u32()? as u64, 0x1_0000),
+            0xfd => (self.read_u16()? as u64, 0xfd),
+            n => return Ok(n as u64),
+        };
+        if value < min {
+            return Err(Error::DecodingError("non-canonical varint".to_string()));
+        }
+        Ok(value)
+    }
+
+    /// Read an element count; each element takes at least `min_item_size`
+    /// bytes, so counts larger than the remaining data are rejected before
+    /// anything is allocated
+    pub fn read_count(&mut self, min_item_size: usize) -> Result<usize> {
+        let count = self.read_varint()?;
+        let max = (self.remaining() / min_item_size.max(1)) as u64;
+        if count > max {
+            return Err(Error::DecodingError(format!(
+                "element count {} exceeds remaining data",
+                count
+            )));
+        }
+        Ok(count as usize)
+    }
+
+    /// Read a length-prefixed byte string
+    pub fn read_var_bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.read_count(1)?;
+        Ok(self.read_bytes(len)?.to_vec())
+    }
+}
+
+impl Encodable for BlockHash {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+
+    fn encoded_len(&self) -> usize {
+        32
+    }
+}
+
+impl Decodable for BlockHash {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(BlockHash(reader.read_array()?))
+    }
+}
+
+impl Encodable for TxHash {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn encoded_len(&self) -> usize {
+        32
+    }
+}
+
+impl Decodable for TxHash {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TxHash::from_bytes(reader.read_array()?))
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.version.to_le_bytes());
+        self.prev_block_hash.encode(out);
+        self.merkle_root.encode(out);
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&self.bits.to_le_bytes());
+        out.extend_from_slice(&self.nonce.to_le_bytes());
+    }
+
+    fn encoded_len(&self) -> usize {
+        BLOCK_HEADER_SIZE
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(BlockHeader {
+            version: reader.read_u32()?,
+            prev_block_hash: BlockHash::decode(reader)?,
+            merkle_root: BlockHash::decode(reader)?,
+            timestamp: reader.read_u32()?,
+            bits: reader.read_u32()?,
+            nonce: reader.read_u32()?,
+        })
+    }
+}
+
+/// Smallest possible input: hash + index + empty script + sequence
+const MIN_TX_INPUT_SIZE: usize = 32 + 4 + 1 + 4;
+
+/// Smallest possible output: amount + empty script
+const MIN_TX_OUTPUT_SIZE: usize = 8 + 1;
+
+/// Smallest possible transaction: version + two empty counts + locktime
+const MIN_TRANSACTION_SIZE: usize = 4 + 1 + 1 + 4;
+
+impl Encodable for TxInput {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.prev_tx_hash.encode(out);
+        out.extend_from_slice(&self.prev_tx_index.to_le_bytes());
+        write_var_bytes(out, &self.script);
+        out.extend_from_slice(&self.sequence.to_le_bytes());
+    }
+
+    fn encoded_len(&self) -> usize {
+        32 + 4 + var_bytes_len(&self.script) + 4
+    }
+}
+
+impl Decodable for TxInput {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TxInput {
+            prev_tx_hash: TxHash::decode(reader)?,
+            prev_tx_index: reader.read_u32()?,
+            script: reader.read_var_bytes()?,
+            sequence: reader.read_u32()?,
+        })
+    }
+}
+
+impl Encodable for TxOutput {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.amount.to_le_bytes());
+        write_var_bytes(out, &self.script_pubkey);
+    }
+
+    fn encoded_len(&self) -> usize {
+        8 + var_bytes_len(&self.script_pubkey)
+    }
+}
+
+impl Decodable for TxOutput {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TxOutput {
+            amount: reader.read_u64()?,
+            script_pubkey: reader.read_var_bytes()?,
+        })
+    }
+}
+
+impl Encodable for Transaction {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.version.to_le_bytes());
+        write_varint(out, self.inputs.len() as u64);
+        for input in &self.inputs {
+            input.encode(out);
+        }
+        write_varint(out, self.outputs.len() as u64);
+        for output in &self.outputs {
+            output.encode(out);
+        }
+        out.extend_from_slice(&self.locktime.to_le_bytes());
+    }
+
+    fn encoded_len(&self) -> usize {
+        let inputs: usize = self.inputs.iter().map(|i| i.encoded_len()).sum();
+        let outputs: usize = self.outputs.iter().map(|o| o.encoded_len()).sum();
+
+        4 + varint_len(self.inputs.len() as u64) + inputs
+            + varint_len(self.outputs.len() as u64) + outputs
+            + 4
+    }
+}
+
+impl Decodable for Transaction {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        let version = reader.read_u32()?;
+
+        let input_count = reader.read_count(MIN_TX_INPUT_SIZE)?;
+        let mut inputs = Vec::with_capacity(input_count);
+        for _ in 0..input_count {
+            inputs.push(TxInput::decode(reader)?);
+        }
+
+        let output_count = reader.read_count(MIN_TX_OUTPUT_SIZE)?;
+        let mut outputs = Vec::with_capacity(output_count);
+        for _ in 0..output_count {
+            outputs.push(TxOutput::decode(reader)?);
+        }
+
+        Ok(Transaction {
+            version,
+            inputs,
+            outputs,
+            locktime: reader.read_u32()?,
+        })
+    }
+}
+
+impl Encodable for Block {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.header.encode(out);
+        out.extend_from_slice(&self.height.to_le_bytes());
+        write_varint(out, self.transactions.len() as u64);
+        for tx in &self.transactions {
+            tx.encode(out);
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let txs: usize = self.transactions.iter().map(|tx| tx.encoded_len()).sum();
+        BLOCK_HEADER_SIZE + 8 + varint_len(self.transactions.len() as u64) + txs
+    }
+}
+
+impl Decodable for Block {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        let header = BlockHeader::decode(reader)?;
+        let height = reader.read_u64()?;
+
+        let tx_count = reader.read_count(MIN_TRANSACTION_SIZE)?;
+        let mut transactions = Vec::with_capacity(tx_count);
+        for _ in 0..tx_count {
+            transactions.push(Transaction::decode(reader)?);
+        }
+
+        Ok(Block {
+            header,
+            transactions,
+            height,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_transaction() -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TxInput {
+                prev_tx_hash: TxHash::from_bytes([7u8; 32]),
+                prev_tx_index: 3,
+                script: vec![0x51; 300],
+                sequence: 0xFFFFFFFE,
+            }],
+            outputs: vec![
+                TxOutput { amount: 5_000_000_000, script_pubkey: vec![0x76, 0xa9] },
+                TxOutput { amount: 1, script_pubkey: vec![] },
+            ],
+            locktime: 42,
+        }
+    }
+
+    fn sample_block() -> Block {
+        let transactions = vec![
+            crate::block::test_coinbase(9, 50),
+            sample_transaction(),
+        ];
+        Block::new(1, BlockHash([3u8; 32]), transactions, 1704067200, 0x1d00ffff, 9)
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        for n in [0u64, 0xfc, 0xfd, 0xffff, 0x1_0000, 0xffff_ffff, 0x1_0000_0000, u64::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, n);
+            assert_eq!(out.len(), varint_len(n));
+            assert_eq!(Reader::new(&out).read_varint().unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn test_non_canonical_varint_rejected() {
+        // 5 encoded with the 0xfd prefix
+        let bytes = [0xfd, 0x05, 0x00];
+        assert!(Reader::new(&bytes).read_varint().is_err());
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let header = sample_block().header;
+        let bytes = serialize(&header);
+        assert_eq!(bytes.len(), BLOCK_HEADER_SIZE);
+        assert_eq!(&bytes[0..4], &1u32.to_le_bytes());
+        assert_eq!(&bytes[4..36], &[3u8; 32]);
+        assert_eq!(&bytes[68..72], &1704067200u32.to_le_bytes());
+        assert_eq!(&bytes[72..76], &0x1d00ffffu32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_transaction_round_trip() {
+        let tx = sample_transaction();
+        let bytes = serialize(&tx);
+        assert_eq!(bytes.len(), tx.encoded_len());
+
+        let decoded: Transaction = deserialize(&bytes).unwrap();
+        assert_eq!(decoded.hash(), tx.hash());
+        assert_eq!(serialize(&decoded), bytes);
+    }
+
+    #[test]
+    fn test_block_round_trip() {
+        let block = sample_block();
+        let bytes = serialize_versioned(&block);
+        assert_eq!(bytes[0], ENCODING_VERSION);
+
+        let decoded: Block = deserialize_versioned(&bytes).unwrap();
+        assert_eq!(decoded.hash(), block.hash());
+        assert_eq!(decoded.height, block.height);
+        assert_eq!(decoded.transactions.len(), 2);
+        assert_eq!(
+            Block::calculate_merkle_root(&decoded.transactions),
+            block.header.merkle_root
+        );
+    }
+
+    #[test]
+    fn test_truncated_and_trailing_data_rejected() {
+        let bytes = serialize(&sample_transaction());
+        assert!(deserialize::<Transaction>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(deserialize::<Transaction>(&extended).is_err());
+    }
+
+    #[test]
+    fn test_oversized_count_rejected() {
+        // Version followed by a claimed 2^32 inputs and nothing else
+        let mut bytes = 1u32.to_le_bytes().to_vec();
+        write_varint(&mut bytes, 0x1_0000_0000);
+        assert!(deserialize::<Transaction>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_unknown_version_rejected() {
+        let mut bytes = serialize_versioned(&sample_transaction());
+        bytes[0] = ENCODING_VERSION + 1;
+        assert!(deserialize_versioned::<Transaction>(&bytes).is_err());
+        assert!(deserialize_versioned::<Transaction>(&[]).is_err());
+    }
+}
@@ -2,6 +2,7 @@
 // Error handling for Antimony blockchain
 
 use std::fmt;
+use crate::types::{Amount, BlockHash, OutPoint, TxHash};
 
 #[derive(Debug)]
 pub enum Error {
@@ -19,26 +20,36 @@ pub enum Error {
     ChainNotInitialized,
     OrphanBlock,
     DuplicateBlock,
+    ReorgTooDeep { depth: u64, max: u32 },
     
     // Database errors
     DatabaseError(String),
+    IoError(String),
     
     // Validation errors
+    Validation(ValidationError),
     InvalidSignature,
+    ScriptError(String),
     InvalidAddress,
+    Address(AddressError),
     InsufficientBalance,
     InvalidAmount,
     
     // Transaction errors
     DuplicateTransaction,
+    DuplicateInput,
     TransactionTooLarge,
     FeeTooLow,
     
     // Mempool errors
     MempoolFull,
+    MempoolConflict { outpoint: OutPoint, spent_by: TxHash },
+    ReplacementRejected(String),
+    PackageTooLarge(String),
     
     // Serialization errors
     SerializationError,
+    DecodingError(String),
     
     // Network errors
     NetworkError(String),
@@ -61,16 +72,30 @@ impl fmt::Display for Error {
             Error::ChainNotInitialized => write!(f, "Blockchain not initialized"),
             Error::OrphanBlock => write!(f, "Orphan block received"),
             Error::DuplicateBlock => write!(f, "Duplicate block"),
+            Error::ReorgTooDeep { depth, max } => {
+                write!(f, "Reorganization of {} blocks exceeds maximum depth {}", depth, max)
+            }
             Error::DatabaseError(e) => write!(f, "Database error: {}", e),
+            Error::IoError(e) => write!(f, "I/O error: {}", e),
+            Error::Validation(e) => write!(f, "Block validation failed: {}", e),
             Error::InvalidSignature => write!(f, "Invalid signature"),
+            Error::ScriptError(e) => write!(f, "Script verification failed: {}", e),
             Error::InvalidAddress => write!(f, "Invalid address"),
+            Error::Address(e) => write!(f, "Invalid address: {}", e),
             Error::InsufficientBalance => write!(f, "Insufficient balance"),
             Error::InvalidAmount => write!(f, "Invalid amount"),
             Error::DuplicateTransaction => write!(f, "Duplicate transaction in mempool"),
+            Error::DuplicateInput => write!(f, "Transaction spends the same output twice"),
             Error::TransactionTooLarge => write!(f, "Transaction size exceeds maximum"),
             Error::FeeTooLow => write!(f, "Transaction fee too low"),
             Error::MempoolFull => write!(f, "Mempool is full"),
+            Error::MempoolConflict { outpoint, spent_by } => {
+                write!(f, "Output {} already spent by mempool transaction {}", outpoint, spent_by)
+            }
+            Error::ReplacementRejected(reason) => write!(f, "Replacement rejected: {}", reason),
+            Error::PackageTooLarge(reason) => write!(f, "Transaction package too large: {}", reason),
             Error::SerializationError => write!(f, "Serialization error"),
+            Error::DecodingError(e) => write!(f, "Decoding error: {}", e),
             Error::NetworkError(e) => write!(f, "Network error: {}", e),
             Error::Other(e) => write!(f, "Error: {}", e),
         }
@@ -79,6 +104,186 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+/// Reasons a block fails contextual validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    // Header
+    PrevHashMismatch { expected: BlockHash, actual: BlockHash },
+    BadHeight { expected: u64, actual: u64 },
+    UnexpectedBits { expected: u32, actual: u32 },
+    HighHash,
+    TimeTooOld { median_time_past: u32, timestamp: u32 },
+    TimeTooNew { max: u32, timestamp: u32 },
+
+    // Body
+    BlockTooLarge { size: usize, max: usize },
+    NoTransactions,
+    FirstNotCoinbase,
+    MultipleCoinbase { index: usize },
+    BadMerkleRoot,
+    MutatedMerkleTree,
+    BadTransaction { index: usize, reason: String },
+
+    // Contextual transaction checks
+    MissingInput { index: usize, outpoint: OutPoint },
+    DoubleSpend { index: usize, outpoint: OutPoint },
+    ImmatureCoinbaseSpend { index: usize, outpoint: OutPoint },
+    NonFinalLocktime { index: usize, locktime: u32 },
+    SequenceLockNotMet { index: usize, outpoint: OutPoint },
+    InputValueTooLow { index: usize, inputs: Amount, outputs: Amount },
+    ValueOverflow { index: usize },
+    ScriptFailure { index: usize, reason: String },
+    BadCoinbaseValue { value: Amount, max: Amount },
+}
+
+impl ValidationError {
+    /// Short stable reject code for APIs and peers
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::PrevHashMismatch { .. } => "bad-prevblk",
+            ValidationError::BadHeight { .. } => "bad-height",
+            ValidationError::UnexpectedBits { .. } => "bad-diffbits",
+            ValidationError::HighHash => "high-hash",
+            ValidationError::TimeTooOld { .. } => "time-too-old",
+            ValidationError::TimeTooNew { .. } => "time-too-new",
+            ValidationError::BlockTooLarge { .. } => "bad-blk-length",
+            ValidationError::NoTransactions => "bad-blk-empty",
+            ValidationError::FirstNotCoinbase => "bad-cb-missing",
+            ValidationError::MultipleCoinbase { .. } => "bad-cb-multiple",
+            ValidationError::BadMerkleRoot => "bad-txnmrklroot",
+            ValidationError::MutatedMerkleTree => "bad-txns-duplicate",
+            ValidationError::BadTransaction { .. } => "bad-txns",
+            ValidationError::MissingInput { .. } => "bad-txns-inputs-missingorspent",
+            ValidationError::DoubleSpend { .. } => "bad-txns-inputs-duplicate",
+            ValidationError::ImmatureCoinbaseSpend { .. } => "bad-txns-premature-spend-of-coinbase",
+            ValidationError::NonFinalLocktime { .. } => "bad-txns-nonfinal",
+            ValidationError::SequenceLockNotMet { .. } => "non-BIP68-final",
+            ValidationError::InputValueTooLow { .. } => "bad-txns-in-belowout",
+            ValidationError::ValueOverflow { .. } => "bad-txns-inputvalues-outofrange",
+            ValidationError::ScriptFailure { .. } => "mandatory-script-verify-flag-failed",
+            ValidationError::BadCoinbaseValue { .. } => "bad-cb-amount",
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::PrevHashMismatch { expected, actual } => {
+                write!(f, "previous block {} does not match tip {}", actual, expected)
+            }
+            ValidationError::BadHeight { expected, actual } => {
+                write!(f, "block height {} (expected {})", actual, expected)
+            }
+            ValidationError::UnexpectedBits { expected, actual } => {
+                write!(f, "difficulty bits {:#010x} (expected {:#010x})", actual, expected)
+            }
+            ValidationError::HighHash => write!(f, "block hash does not meet target"),
+            ValidationError::TimeTooOld { median_time_past, timestamp } => {
+                write!(f, "timestamp {} not after median time past {}", timestamp, median_time_past)
+            }
+            ValidationError::TimeTooNew { max, timestamp } => {
+                write!(f, "timestamp {} too far in future (max {})", timestamp, max)
+            }
+            ValidationError::BlockTooLarge { size, max } => {
+                write!(f, "block size {} exceeds maximum {}", size, max)
+            }
+            ValidationError::NoTransactions => write!(f, "block has no transactions"),
+            ValidationError::FirstNotCoinbase => write!(f, "first transaction is not a coinbase"),
+            ValidationError::MultipleCoinbase { index } => {
+                write!(f, "transaction {} is an extra coinbase", index)
+            }
+            ValidationError::BadMerkleRoot => write!(f, "merkle root mismatch"),
+            ValidationError::MutatedMerkleTree => write!(f, "duplicate transactions in merkle tree"),
+            ValidationError::BadTransaction { index, reason } => {
+                write!(f, "transaction {} invalid: {}", index, reason)
+            }
+            ValidationError::MissingInput { index, outpoint } => {
+                write!(f, "transaction {} spends missing or spent output {}", index, outpoint)
+            }
+            ValidationError::DoubleSpend { index, outpoint } => {
+                write!(f, "transaction {} double-spends {} within block", index, outpoint)
+            }
+            ValidationError::ImmatureCoinbaseSpend { index, outpoint } => {
+                write!(f, "transaction {} spends immature coinbase {}", index, outpoint)
+            }
+            ValidationError::NonFinalLocktime { index, locktime } => {
+                write!(f, "transaction {} locktime {} not yet reached", index, locktime)
+            }
+            ValidationError::SequenceLockNotMet { index, outpoint } => {
+                write!(f, "transaction {} relative lock on {} not yet met", index, outpoint)
+            }
+            ValidationError::InputValueTooLow { index, inputs, outputs } => {
+                write!(f, "transaction {} outputs {} exceed inputs {}", index, outputs, inputs)
+            }
+            ValidationError::ValueOverflow { index } => {
+                write!(f, "transaction {} input values out of range", index)
+            }
+            ValidationError::ScriptFailure { index, reason } => {
+                write!(f, "transaction {} script verification failed: {}", index, reason)
+            }
+            ValidationError::BadCoinbaseValue { value, max } => {
+                write!(f, "coinbase pays {} (max {})", value, max)
+            }
+        }
+    }
+}
+
+/// Reasons an address string fails to parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// Longer than bech32 allows, or too short to hold a checksum
+    InvalidLength(usize),
+    MixedCase,
+    MissingSeparator,
+    InvalidCharacter { position: usize, character: char },
+    /// The checksum does not match: most likely a typo
+    InvalidChecksum,
+    /// A valid address for another network
+    WrongNetwork { expected: String, found: String },
+    UnknownVersion(u8),
+    InvalidPadding,
+    InvalidPayloadLength(usize),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::InvalidLength(len) => write!(f, "invalid length {}", len),
+            AddressError::MixedCase => write!(f, "mixed upper and lower case"),
+            AddressError::MissingSeparator => write!(f, "missing prefix separator"),
+            AddressError::InvalidCharacter { position, character } => {
+                write!(f, "invalid character {:?} at position {}", character, position)
+            }
+            AddressError::InvalidChecksum => write!(f, "checksum mismatch, check for typos"),
+            AddressError::WrongNetwork { expected, found } => {
+                write!(f, "prefix {} is for another network (expected {})", found, expected)
+            }
+            AddressError::UnknownVersion(version) => write!(f, "unknown address version {}", version),
+            AddressError::InvalidPadding => write!(f, "invalid padding"),
+            AddressError::InvalidPayloadLength(len) => write!(f, "payload of {} bytes (expected 20)", len),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IoError(e.to_string())
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        Error::Validation(e)
+    }
+}
+
+impl From<AddressError> for Error {
+    fn from(e: AddressError) -> Self {
+        Error::Address(e)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[cfg(test)]
@@ -99,4 +304,11 @@ mod tests {
         let err = Error::FeeTooLow;
         assert_eq!(format!("{}", err), "Transaction fee too low");
     }
+
+    #[test]
+    fn test_validation_error_code() {
+        let err: Error = ValidationError::HighHash.into();
+        assert_eq!(format!("{}", err), "Block validation failed: block hash does not meet target");
+        assert_eq!(ValidationError::BadMerkleRoot.code(), "bad-txnmrklroot");
+    }
 }
@@ -0,0 +1,422 @@
+// atmn-core/src/fee_estimator.rs
+// Fee estimation from observed confirmation times
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::encoding::{self, Reader};
+use crate::types::{Amount, BlockHeight, TxHash};
+use crate::error::{Error, Result};
+
+/// File name of the saved fee estimates inside a data directory
+pub const FEE_ESTIMATES_FILE_NAME: &str = "fee_estimates.dat";
+
+/// Format version written at the start of the fee estimates file
+pub const FEE_ESTIMATES_FILE_VERSION: u8 = 1;
+
+/// Longest confirmation target tracked, in blocks
+pub const MAX_CONFIRMATION_TARGET: usize = 48;
+
+/// Confirmation target used when a caller does not pick one, in blocks
+pub const DEFAULT_CONFIRMATION_TARGET: usize = 6;
+
+/// Share of transactions that must confirm within the target for a fee rate
+/// to be recommended
+pub const DEFAULT_CONFIDENCE: f64 = 0.85;
+
+/// Weight history keeps per block, so data halves in about 350 blocks
+const DECAY: f64 = 0.998;
+
+/// Lowest bucket boundary, per 1000 bytes
+const MIN_BUCKET_FEE_PER_KB: u64 = 1_000;
+
+/// Highest bucket boundary, per 1000 bytes
+const MAX_BUCKET_FEE_PER_KB: u64 = 10_000_000;
+
+/// Ratio between neighbouring bucket boundaries
+const BUCKET_SPACING: f64 = 1.1;
+
+/// Decayed transactions a range of buckets needs before its success rate counts
+const SUFFICIENT_TXS: f64 = 4.0;
+
+/// A recommended fee rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeEstimate {
+    /// Fee per 1000 bytes
+    pub fee_per_kb: u64,
+    /// Blocks within which a transaction paying this rate is expected to confirm
+    pub target_blocks: usize,
+    /// Share of past transactions at this rate that confirmed within the target
+    pub confidence: f64,
+}
+
+impl FeeEstimate {
+    /// Fee for a transaction of `size` bytes, rounded up
+    pub fn fee_for_size(&self, size: usize) -> Amount {
+        (self.fee_per_kb as u128 * size as u128).div_ceil(1000) as Amount
+    }
+}
+
+/// Tracks how long transactions in each fee-rate bucket take to confirm
+///
+/// Pooled transactions are registered with `process_transaction` and settled
+/// by `process_block` (confirmed) or `remove_transaction` (evicted, replaced
+/// or expired). History decays every block so recent behaviour dominates.
+#[derive(Debug, Clone)]
+pub struct FeeEstimator {
+    /// Lower boundary of each bucket, per 1000 bytes
+    buckets: Vec<u64>,
+    /// Decayed count of confirmed transactions per bucket
+    tx_counts: Vec<f64>,
+    /// `confirmed_within[t][b]`: confirmed in bucket `b` within `t + 1` blocks
+    confirmed_within: Vec<Vec<f64>>,
+    /// `failed_after[t][b]`: left the pool unconfirmed from bucket `b` after
+    /// waiting at least `t + 1` blocks
+    failed_after: Vec<Vec<f64>>,
+    /// Pooled transactions with their entry height and bucket
+    unconfirmed: HashMap<TxHash, (BlockHeight, usize)>,
+    /// Height of the last block processed
+    best_height: BlockHeight,
+}
+
+impl FeeEstimator {
+    /// Create an estimator with no history
+    pub fn new() -> Self {
+        let mut buckets = Vec::new();
+        let mut boundary = MIN_BUCKET_FEE_PER_KB as f64;
+        while boundary <= MAX_BUCKET_FEE_PER_KB as f64 {
+            buckets.push(boundary as u64);
+            boundary *= BUCKET_SPACING;
+        }
+        let count = buckets.len();
+
+        FeeEstimator {
+            buckets,
+            tx_counts: vec![0.0; count],
+            confirmed_within: vec![vec![0.0; count]; MAX_CONFIRMATION_TARGET],
+            failed_after: vec![vec![0.0; count]; MAX_CONFIRMATION_TARGET],
+            unconfirmed: HashMap::new(),
+            best_height: 0,
+        }
+    }
+
+    /// Height of the last block processed
+    pub fn best_height(&self) -> BlockHeight {
+        self.best_height
+    }
+
+    /// Number of pooled transactions being tracked
+    pub fn tracked_count(&self) -> usize {
+        self.unconfirmed.len()
+    }
+
+    /// Start tracking a transaction that entered the pool with the chain at `height`
+    ///
+    /// Entries seen with a tip older than the last processed block are ignored,
+    /// since their waiting time would be overstated.
+    pub fn process_transaction(&mut self, tx_hash: TxHash, fee_per_kb: u64, height: BlockHeight) {
+        if height < self.best_height {
+            return;
+        }
+        self.unconfirmed.insert(tx_hash, (height, self.bucket_index(fee_per_kb)));
+    }
+
+    /// Record the tracked transactions confirmed by the block at `height`
+    ///
+    /// Blocks at or below the last processed height (reorgs, replays) only
+    /// stop tracking their transactions.
+    pub fn process_block(&mut self, height: BlockHeight, confirmed: &[TxHash]) {
+        if height <= self.best_height {
+            for tx_hash in confirmed {
+                self.unconfirmed.remove(tx_hash);
+            }
+            return;
+        }
+
+        for count in self.tx_counts.iter_mut()
+            .chain(self.confirmed_within.iter_mut().flatten())
+            .chain(self.failed_after.iter_mut().flatten())
+        {
+            *count *= DECAY;
+        }
+
+        for tx_hash in confirmed {
+            let Some((entry_height, bucket)) = self.unconfirmed.remove(tx_hash) else { continue };
+            let blocks = height.saturating_sub(entry_height).max(1) as usize;
+            self.tx_counts[bucket] += 1.0;
+            for within in self.confirmed_within.iter_mut().skip(blocks - 1) {
+                within[bucket] += 1.0;
+            }
+        }
+        self.best_height = height;
+    }
+
+    /// Stop tracking a transaction that left the pool without confirming
+    ///
+    /// Counts as a failure for every target shorter than the time it waited.
+    pub fn remove_transaction(&mut self, tx_hash: &TxHash) {
+        let Some((entry_height, bucket)) = self.unconfirmed.remove(tx_hash) else { return };
+        let waited = self.best_height.saturating_sub(entry_height) as usize;
+        for failed in self.failed_after.iter_mut().take(waited) {
+            failed[bucket] += 1.0;
+        }
+    }
+
+    /// Stop tracking every pooled transaction without recording anything
+    pub fn clear_unconfirmed(&mut self) {
+        self.unconfirmed.clear();
+    }
+
+    /// Lowest fee rate expected to confirm within `target_blocks` with
+    /// `DEFAULT_CONFIDENCE`
+    ///
+    /// Without enough data for the target, longer targets are tried in turn;
+    /// `target_blocks` of the result is the one actually met. `None` means no
+    /// target up to `MAX_CONFIRMATION_TARGET` has enough history yet.
+    pub fn estimate_fee(&self, target_blocks: usize) -> Option<FeeEstimate> {
+        (target_blocks.clamp(1, MAX_CONFIRMATION_TARGET)..=MAX_CONFIRMATION_TARGET)
+            .find_map(|target| self.estimate_fee_with_confidence(target, DEFAULT_CONFIDENCE))
+    }
+
+    /// Lowest fee rate at which at least `confidence` of transactions
+    /// confirmed within `target_blocks`
+    ///
+    /// Buckets are scanned from the highest rate down, grouping neighbours
+    /// until they hold `SUFFICIENT_TXS`; the scan stops at the first group
+    /// below `confidence`. Transactions still pooled after `target_blocks`
+    /// count against their bucket.
+    pub fn estimate_fee_with_confidence(&self, target_blocks: usize, confidence: f64) -> Option<FeeEstimate> {
+        if target_blocks == 0 || target_blocks > MAX_CONFIRMATION_TARGET {
+            return None;
+        }
+        let t = target_blocks - 1;
+
+        let mut still_waiting = vec![0.0; self.buckets.len()];
+        for &(entry_height, bucket) in self.unconfirmed.values() {
+            if self.best_height.saturating_sub(entry_height) as usize >= target_blocks {
+                still_waiting[bucket] += 1.0;
+            }
+        }
+
+        let mut best = None;
+        let (mut confirmed, mut total) = (0.0, 0.0);
+        for bucket in (0..self.buckets.len()).rev() {
+            confirmed += self.confirmed_within[t][bucket];
+            total += self.tx_counts[bucket] + self.failed_after[t][bucket] + still_waiting[bucket];
+            if total < SUFFICIENT_TXS {
+                continue;
+            }
+
+            let rate = confirmed / total;
+            if rate < confidence {
+                break;
+            }
+            best = Some(FeeEstimate {
+                fee_per_kb: self.buckets[bucket],
+                target_blocks,
+                confidence: rate,
+            });
+            confirmed = 0.0;
+            total = 0.0;
+        }
+        best
+    }
+
+    /// Write the confirmation history to `path`, replacing it atomically
+    ///
+    /// Tracked pool transactions are not saved; they are re-added with the
+    /// mempool.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut out = vec![FEE_ESTIMATES_FILE_VERSION];
+        out.extend_from_slice(&self.best_height.to_le_bytes());
+        encoding::write_varint(&mut out, self.buckets.len() as u64);
+        for &boundary in &self.buckets {
+            out.extend_from_slice(&boundary.to_le_bytes());
+        }
+        encoding::write_varint(&mut out, MAX_CONFIRMATION_TARGET as u64);
+        for count in self.tx_counts.iter()
+            .chain(self.confirmed_within.iter().flatten())
+            .chain(self.failed_after.iter().flatten())
+        {
+            out.extend_from_slice(&count.to_bits().to_le_bytes());
+        }
+
+        let tmp = path.with_extension("dat.tmp");
+        fs::write(&tmp, &out)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Read history written by `save`; a missing file gives an empty estimator
+    ///
+    /// Files from another version or bucket layout are rejected rather than
+    /// misread.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut estimator = Self::new();
+        let mut reader = Reader::new(&data);
+        let version = reader.read_u8()?;
+        if version != FEE_ESTIMATES_FILE_VERSION {
+            return Err(Error::DecodingError(format!("unsupported fee estimates file version {}", version)));
+        }
+        estimator.best_height = reader.read_u64()?;
+
+        let bucket_count = reader.read_count(8)?;
+        let mut buckets = Vec::with_capacity(bucket_count);
+        for _ in 0..bucket_count {
+            buckets.push(reader.read_u64()?);
+        }
+        let targets = reader.read_varint()?;
+        if buckets != estimator.buckets || targets != MAX_CONFIRMATION_TARGET as u64 {
+            return Err(Error::DecodingError("fee estimates file has a different bucket layout".to_string()));
+        }
+
+        for count in estimator.tx_counts.iter_mut()
+            .chain(estimator.confirmed_within.iter_mut().flatten())
+            .chain(estimator.failed_after.iter_mut().flatten())
+        {
+            *count = f64::from_bits(reader.read_u64()?);
+        }
+        reader.finish()?;
+        Ok(estimator)
+    }
+
+    /// Bucket holding `fee_per_kb`; rates below the lowest boundary share bucket 0
+    fn bucket_index(&self, fee_per_kb: u64) -> usize {
+        self.buckets.partition_point(|&boundary| boundary <= fee_per_kb).saturating_sub(1)
+    }
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(n: u32) -> TxHash {
+        let mut bytes = [0u8; 32];
+        bytes[..4].copy_from_slice(&n.to_le_bytes());
+        TxHash::from_bytes(bytes)
+    }
+
+    /// Each block, 10 transactions at 50_000/kB confirm in the next block and
+    /// 10 at 2_000/kB wait four blocks
+    fn estimator_with_history(blocks: u64) -> FeeEstimator {
+        let mut estimator = FeeEstimator::new();
+        let mut next = 0;
+        let mut slow: Vec<(BlockHeight, Vec<TxHash>)> = Vec::new();
+        for height in 1..=blocks {
+            let entry_height = height - 1;
+            let fast: Vec<TxHash> = (0..10).map(|_| { next += 1; tx(next) }).collect();
+            let cheap: Vec<TxHash> = (0..10).map(|_| { next += 1; tx(next) }).collect();
+            for hash in &fast {
+                estimator.process_transaction(*hash, 50_000, entry_height);
+            }
+            for hash in &cheap {
+                estimator.process_transaction(*hash, 2_000, entry_height);
+            }
+            slow.push((entry_height, cheap));
+
+            let mut confirmed = fast;
+            if let Some(pos) = slow.iter().position(|(entered, _)| height - entered >= 4) {
+                confirmed.extend(slow.remove(pos).1);
+            }
+            estimator.process_block(height, &confirmed);
+        }
+        estimator
+    }
+
+    #[test]
+    fn test_no_estimate_without_history() {
+        let estimator = FeeEstimator::new();
+        assert!(estimator.estimate_fee(1).is_none());
+        assert!(estimator.estimate_fee_with_confidence(0, DEFAULT_CONFIDENCE).is_none());
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        let estimator = FeeEstimator::new();
+        assert_eq!(estimator.bucket_index(0), 0);
+        assert_eq!(estimator.bucket_index(MIN_BUCKET_FEE_PER_KB), 0);
+        assert_eq!(estimator.bucket_index(1_100), 1);
+        assert_eq!(estimator.bucket_index(u64::MAX), estimator.buckets.len() - 1);
+    }
+
+    #[test]
+    fn test_estimates_follow_confirmation_times() {
+        let estimator = estimator_with_history(50);
+
+        // Only the high rate confirms in the next block
+        let next_block = estimator.estimate_fee(1).unwrap();
+        assert_eq!(next_block.target_blocks, 1);
+        assert!(next_block.fee_per_kb > 2_000 && next_block.fee_per_kb <= 50_000);
+        assert!(next_block.confidence >= DEFAULT_CONFIDENCE);
+
+        // Within four blocks the cheap rate is good enough
+        let patient = estimator.estimate_fee(4).unwrap();
+        assert!(patient.fee_per_kb <= 2_000);
+        assert!(patient.fee_per_kb < next_block.fee_per_kb);
+        assert_eq!(patient.fee_for_size(250), (patient.fee_per_kb * 250).div_ceil(1000));
+    }
+
+    #[test]
+    fn test_stuck_transactions_count_as_failures() {
+        let mut estimator = FeeEstimator::new();
+        for n in 0..20 {
+            estimator.process_transaction(tx(n), 3_000, 0);
+        }
+        for height in 1..=5 {
+            estimator.process_block(height, &[]);
+        }
+        assert!(estimator.estimate_fee_with_confidence(2, 0.5).is_none());
+
+        // Evicted after waiting: still failures, and no longer tracked
+        for n in 0..20 {
+            estimator.remove_transaction(&tx(n));
+        }
+        assert_eq!(estimator.tracked_count(), 0);
+        assert!(estimator.estimate_fee_with_confidence(2, 0.5).is_none());
+    }
+
+    #[test]
+    fn test_stale_entries_and_blocks_ignored() {
+        let mut estimator = estimator_with_history(5);
+        let before = estimator.clone();
+
+        estimator.process_transaction(tx(9_999), 1_000, 1);
+        assert_eq!(estimator.tracked_count(), before.tracked_count());
+
+        estimator.process_block(3, &[]);
+        assert_eq!(estimator.best_height(), 5);
+        assert_eq!(estimator.tx_counts, before.tx_counts);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join(FEE_ESTIMATES_FILE_NAME);
+
+        assert_eq!(FeeEstimator::load(&path).unwrap().best_height(), 0);
+
+        let estimator = estimator_with_history(30);
+        estimator.save(&path).unwrap();
+        let restored = FeeEstimator::load(&path).unwrap();
+        assert_eq!(restored.best_height(), 30);
+        assert_eq!(restored.tracked_count(), 0);
+        assert_eq!(restored.estimate_fee(1), estimator.estimate_fee(1));
+
+        let mut data = std::fs::read(&path).unwrap();
+        data[0] = FEE_ESTIMATES_FILE_VERSION + 1;
+        std::fs::write(&path, data).unwrap();
+        assert!(FeeEstimator::load(&path).is_err());
+    }
+}
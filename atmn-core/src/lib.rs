@@ -2,30 +2,47 @@
 // ANTIMONY COIN 2.0 - Core Blockchain Implementation
 
 pub mod chain_params;
+pub mod chain;
 pub mod consensus;
+pub mod encoding;
 pub mod network;
 pub mod storage;
 pub mod transaction;
+pub mod script;
+pub mod sighash;
 pub mod block;
+pub mod block_assembler;
 pub mod error;
 pub mod types;
+pub mod address;
 pub mod miner;
 pub mod miner_mt;
+pub mod mining_job;
 pub mod difficulty;
+pub mod merkle;
+pub mod block_filter;
 pub mod mempool;
+pub mod fee_estimator;
 pub mod genesis;
+pub mod uint;
 
 pub use chain_params::ChainParams;
+pub use chain::{ChainManager, ChainUpdate};
 pub use consensus::{Consensus, ProofOfWork};
-pub use network::{Node, P2PNetwork};
+pub use network::{Node, NetworkMessage, P2PNetwork};
 pub use storage::Storage;
 pub use transaction::Transaction;
 pub use block::Block;
+pub use block_filter::BlockFilter;
+pub use block_assembler::BlockAssembler;
 pub use error::{Error, Result};
 pub use miner::{Miner, MinerConfig, BlockTemplate, MiningResult, MiningStats};
 pub use miner_mt::MultiThreadedMiner;
-pub use mempool::{Mempool, MempoolConfig, MempoolStats};
+pub use mining_job::{JobControl, JobOutcome, MiningJob, MiningProgress};
+pub use mempool::{Mempool, MempoolConfig, MempoolEntry, MempoolStats};
+pub use fee_estimator::{FeeEstimate, FeeEstimator};
 pub use genesis::{create_genesis_block, initialize_genesis};
+pub use uint::U256;
 
 /// ATMN Core Library Version
 pub const VERSION: &str = "0.1.0";
@@ -2,64 +2,219 @@
 // Transaction Memory Pool for ATMN
 
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, BinaryHeap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::cmp::Ordering;
-use crate::transaction::Transaction;
-use crate::types::{TxHash, BlockHash};
-use crate::error::{Error, Result};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::block::Block;
+use crate::chain_params::ChainParams;
+use crate::consensus::{Consensus, ValidationContext};
+use crate::encoding::{self, Decodable, Encodable, Reader};
+use crate::storage::{UtxoEntry, UtxoView};
+use crate::transaction::{Transaction, TxOutput};
+use crate::types::{Amount, BlockHeight, OutPoint, Timestamp, TxHash};
+use crate::error::{Error, Result, ValidationError};
+use crate::fee_estimator::{FeeEstimate, FeeEstimator};
 
-/// Transaction priority for mempool ordering
+/// File name of the saved mempool inside a data directory
+pub const MEMPOOL_FILE_NAME: &str = "mempool.dat";
+
+/// Format version written at the start of the mempool file
+pub const MEMPOOL_FILE_VERSION: u8 = 1;
+
+/// Fee rate the rolling minimum rises above the evicted package's, per 1000 bytes
+pub const INCREMENTAL_RELAY_FEE_PER_KB: u64 = 1_000;
+
+/// Seconds for the rolling minimum fee to fall by half
+pub const ROLLING_FEE_HALFLIFE: u64 = 12 * 60 * 60;
+
+/// A pooled transaction with its fee and package totals
+///
+/// Ancestors are the pooled transactions it spends from, directly or
+/// indirectly; descendants are those spending from it. Both package totals
+/// include the transaction itself.
 #[derive(Debug, Clone)]
-struct TxPriority {
-    /// Fee per byte (higher is better)
-    fee_per_byte: u64,
-    /// Transaction hash
+pub struct MempoolEntry {
+    pub transaction: Transaction,
+    /// Input value minus output value
+    pub fee: Amount,
+    /// Serialized size in bytes
+    pub size: usize,
+    /// When it entered the pool, in Unix seconds
+    pub time: u64,
+    pub ancestor_count: usize,
+    pub ancestor_size: usize,
+    pub ancestor_fees: Amount,
+    pub descendant_count: usize,
+    pub descendant_size: usize,
+    pub descendant_fees: Amount,
+}
+
+impl MempoolEntry {
+    fn new(transaction: Transaction, fee: Amount, size: usize, time: u64) -> Self {
+        MempoolEntry {
+            transaction,
+            fee,
+            size,
+            time,
+            ancestor_count: 1,
+            ancestor_size: size,
+            ancestor_fees: fee,
+            descendant_count: 1,
+            descendant_size: size,
+            descendant_fees: fee,
+        }
+    }
+
+    /// Fee per byte, rounded down
+    pub fn fee_per_byte(&self) -> u64 {
+        self.fee / self.size.max(1) as u64
+    }
+
+    /// Compare fee rates exactly, without rounding
+    fn cmp_fee_rate(&self, other: &MempoolEntry) -> Ordering {
+        cmp_fee_rate(self.fee, self.size, other.fee, other.size)
+    }
+}
+
+/// Compare `fee_a / size_a` with `fee_b / size_b` without rounding
+fn cmp_fee_rate(fee_a: Amount, size_a: usize, fee_b: Amount, size_b: usize) -> Ordering {
+    (fee_a as u128 * size_b as u128).cmp(&(fee_b as u128 * size_a as u128))
+}
+
+/// Eviction order: lowest descendant-package fee rate first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EvictionKey {
+    descendant_fees: Amount,
+    descendant_size: usize,
     tx_hash: TxHash,
-    /// Transaction
-    transaction: Transaction,
 }
 
-impl Eq for TxPriority {}
+impl EvictionKey {
+    fn new(tx_hash: TxHash, entry: &MempoolEntry) -> Self {
+        EvictionKey {
+            descendant_fees: entry.descendant_fees,
+            descendant_size: entry.descendant_size,
+            tx_hash,
+        }
+    }
+}
+
+impl Ord for EvictionKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_fee_rate(self.descendant_fees, self.descendant_size, other.descendant_fees, other.descendant_size)
+            .then_with(|| self.tx_hash.as_bytes().cmp(other.tx_hash.as_bytes()))
+    }
+}
+
+impl PartialOrd for EvictionKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-impl PartialEq for TxPriority {
-    fn eq(&self, other: &Self) -> bool {
-        self.tx_hash == other.tx_hash
+/// Block selection order: highest ancestor-package fee rate last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AncestorKey {
+    ancestor_fees: Amount,
+    ancestor_size: usize,
+    tx_hash: TxHash,
+}
+
+impl AncestorKey {
+    fn new(tx_hash: TxHash, entry: &MempoolEntry) -> Self {
+        AncestorKey {
+            ancestor_fees: entry.ancestor_fees,
+            ancestor_size: entry.ancestor_size,
+            tx_hash,
+        }
     }
 }
 
-impl Ord for TxPriority {
+impl Ord for AncestorKey {
     fn cmp(&self, other: &Self) -> Ordering {
-        // Higher fee per byte = higher priority
-        self.fee_per_byte.cmp(&other.fee_per_byte)
+        // Equal rates go to the lowest hash first
+        cmp_fee_rate(self.ancestor_fees, self.ancestor_size, other.ancestor_fees, other.ancestor_size)
+            .then_with(|| other.tx_hash.as_bytes().cmp(self.tx_hash.as_bytes()))
     }
 }
 
-impl PartialOrd for TxPriority {
+impl PartialOrd for AncestorKey {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
+/// The chain's UTXO set plus outputs created by pooled transactions
+///
+/// Pooled outputs are reported at `height`, the next block's, as if they
+/// confirmed in it.
+pub struct MempoolView<'a> {
+    mempool: &'a Mempool,
+    chain: &'a dyn UtxoView,
+    height: BlockHeight,
+}
+
+impl UtxoView for MempoolView<'_> {
+    fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
+        if let Some(entry) = self.mempool.transactions.get(&outpoint.tx_hash) {
+            let output = entry.transaction.outputs.get(outpoint.index as usize);
+            return Ok(output.map(|output| UtxoEntry {
+                tx_hash: outpoint.tx_hash,
+                output_index: outpoint.index,
+                amount: output.amount,
+                script_pubkey: output.script_pubkey.clone(),
+                block_height: self.height,
+                is_coinbase: false,
+            }));
+        }
+        self.chain.get_utxo(outpoint)
+    }
+
+    fn median_time_past(&self, height: BlockHeight) -> Result<Timestamp> {
+        self.chain.median_time_past(height)
+    }
+}
+
 /// Transaction mempool configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MempoolConfig {
     /// Maximum transactions in mempool
     pub max_size: usize,
+    /// Maximum total size of pooled transactions in bytes
+    pub max_bytes: usize,
     /// Maximum transaction size in bytes
     pub max_tx_size: usize,
     /// Minimum fee per byte (in satoshis)
     pub min_fee_per_byte: u64,
     /// Transaction expiration time in seconds
     pub tx_expiration: u64,
+    /// Most transactions, descendants included, one replacement may evict
+    pub max_replacement_evictions: usize,
+    /// Most pooled ancestors of a transaction, itself included
+    pub max_ancestor_count: usize,
+    /// Largest total size in bytes of a transaction and its pooled ancestors
+    pub max_ancestor_size: usize,
+    /// Most pooled descendants of a transaction, itself included
+    pub max_descendant_count: usize,
+    /// Largest total size in bytes of a transaction and its pooled descendants
+    pub max_descendant_size: usize,
 }
 
 impl Default for MempoolConfig {
     fn default() -> Self {
         MempoolConfig {
             max_size: 50_000,
+            max_bytes: 300_000_000,  // 300 MB
             max_tx_size: 100_000,
             min_fee_per_byte: 1,
             tx_expiration: 86400, // 24 hours
+            max_replacement_evictions: 100,
+            max_ancestor_count: 25,
+            max_ancestor_size: 101_000,
+            max_descendant_count: 25,
+            max_descendant_size: 101_000,
         }
     }
 }
@@ -69,12 +224,26 @@ impl Default for MempoolConfig {
 pub struct Mempool {
     /// Configuration
     config: MempoolConfig,
-    /// Transactions by hash for quick lookup
-    transactions: HashMap<TxHash, Transaction>,
-    /// Priority queue for transaction ordering
-    priority_queue: BinaryHeap<TxPriority>,
+    /// Transactions by hash with their fees
+    transactions: HashMap<TxHash, MempoolEntry>,
+    /// Pooled transaction spending each outpoint
+    spends: HashMap<OutPoint, TxHash>,
+    /// Entries by descendant-package fee rate, for eviction
+    by_descendant_rate: BTreeSet<EvictionKey>,
+    /// Entries by ancestor-package fee rate, for block selection
+    by_ancestor_rate: BTreeSet<AncestorKey>,
+    /// Entries by entry time, for expiry
+    by_time: BTreeSet<(u64, [u8; 32])>,
     /// Total size in bytes
     total_size: usize,
+    /// Minimum fee rate per 1000 bytes raised by evictions, decaying over time
+    rolling_min_fee_per_kb: u64,
+    /// When `rolling_min_fee_per_kb` was last raised or decayed
+    rolling_fee_time: u64,
+    /// Confirmation times of pooled transactions by fee rate
+    fee_estimator: FeeEstimator,
+    /// Chain height new transactions are recorded as entering at
+    tip_height: BlockHeight,
 }
 
 impl Mempool {
@@ -88,13 +257,45 @@ impl Mempool {
         Mempool {
             config,
             transactions: HashMap::new(),
-            priority_queue: BinaryHeap::new(),
+            spends: HashMap::new(),
+            by_descendant_rate: BTreeSet::new(),
+            by_ancestor_rate: BTreeSet::new(),
+            by_time: BTreeSet::new(),
             total_size: 0,
+            rolling_min_fee_per_kb: 0,
+            rolling_fee_time: 0,
+            fee_estimator: FeeEstimator::new(),
+            tip_height: 0,
         }
     }
 
-    /// Add transaction to mempool
-    pub fn add_transaction(&mut self, tx: Transaction) -> Result<()> {
+    /// Add transaction to mempool as if it arrived at `time` (Unix seconds)
+    ///
+    /// Callers check finality, coinbase maturity and relative locks first;
+    /// see `accept_transaction_at`.
+    ///
+    /// `utxos` is the confirmed UTXO set; inputs may also spend outputs of
+    /// pooled transactions. The fee is input value minus output value. Input
+    /// scripts are verified under `params` before the transaction can replace
+    /// or join anything in the pool.
+    ///
+    /// A transaction spending an output some pooled transaction already
+    /// spends is rejected, unless every such transaction signals
+    /// replaceability (an input sequence of at most `MAX_BIP125_RBF_SEQUENCE`)
+    /// and the newcomer pays enough to replace them and their descendants.
+    ///
+    /// Expired transactions are dropped first. If the pool is then over
+    /// `max_size` or `max_bytes`, the packages with the lowest descendant fee
+    /// rate are evicted, which may be the new transaction itself.
+    fn add_transaction_at(
+        &mut self,
+        tx: Transaction,
+        utxos: &dyn UtxoView,
+        params: &ChainParams,
+        time: u64,
+    ) -> Result<()> {
+        self.expire(time);
+
         // Calculate transaction hash
         let tx_hash = tx.hash();
 
@@ -106,84 +307,393 @@ impl Mempool {
         // Validate transaction
         self.validate_transaction(&tx)?;
 
-        // Calculate fee (output sum - input sum, but we need UTXO set for inputs)
-        // For now, estimate fee based on transaction size
-        let tx_size = self.estimate_tx_size(&tx);
-        let estimated_fee = tx_size as u64; // 1 sat per byte minimum
-        
-        let fee_per_byte = if tx_size > 0 {
-            estimated_fee / tx_size as u64
-        } else {
-            0
-        };
+        let (fee, spent_outputs) = self.calculate_fee(&tx, utxos)?;
+        let size = self.estimate_tx_size(&tx);
+        let mut entry = MempoolEntry::new(tx, fee, size, time);
 
         // Check if fee meets minimum
-        if fee_per_byte < self.config.min_fee_per_byte {
+        if (entry.fee as u128) * 1000 < self.min_fee_per_kb(time) as u128 * entry.size as u128 {
             return Err(Error::FeeTooLow);
         }
 
-        // Check mempool size limit
-        if self.transactions.len() >= self.config.max_size {
-            // Try to evict lowest priority transaction
-            self.evict_lowest_priority()?;
+        // Only correctly signed transactions may replace or evict others
+        entry.transaction.verify_scripts(&spent_outputs, params).map_err(|e| {
+            ValidationError::ScriptFailure { index: 0, reason: e.to_string() }
+        })?;
+
+        let replaced = self.check_replacement(&entry)?;
+        let ancestors = self.ancestors_of(&entry.transaction);
+        self.check_package_limits(&entry, &ancestors)?;
+
+        for replaced_hash in &replaced {
+            self.remove_transaction(replaced_hash);
         }
 
-        // Add to priority queue
-        self.priority_queue.push(TxPriority {
-            fee_per_byte,
-            tx_hash,
-            transaction: tx.clone(),
-        });
+        for ancestor_hash in &ancestors {
+            let ancestor = self.transactions.get(ancestor_hash).expect("ancestor is pooled");
+            let key = EvictionKey::new(*ancestor_hash, ancestor);
+            self.by_descendant_rate.remove(&key);
 
-        // Add to transactions map
-        self.transactions.insert(tx_hash, tx);
-        self.total_size += tx_size;
+            let ancestor = self.transactions.get_mut(ancestor_hash).expect("ancestor is pooled");
+            ancestor.descendant_count += 1;
+            ancestor.descendant_size += entry.size;
+            ancestor.descendant_fees += entry.fee;
+            entry.ancestor_count += 1;
+            entry.ancestor_size += ancestor.size;
+            entry.ancestor_fees += ancestor.fee;
+            self.by_descendant_rate.insert(EvictionKey::new(*ancestor_hash, ancestor));
+        }
 
+        for input in &entry.transaction.inputs {
+            self.spends.insert(input.outpoint(), tx_hash);
+        }
+        let fee_per_kb = (entry.fee as u128 * 1000 / entry.size.max(1) as u128) as u64;
+        self.total_size += entry.size;
+        self.by_descendant_rate.insert(EvictionKey::new(tx_hash, &entry));
+        self.by_ancestor_rate.insert(AncestorKey::new(tx_hash, &entry));
+        self.by_time.insert((entry.time, *tx_hash.as_bytes()));
+        self.transactions.insert(tx_hash, entry);
+
+        self.trim_to_size(time);
+        if !self.transactions.contains_key(&tx_hash) {
+            return Err(Error::MempoolFull);
+        }
+
+        self.fee_estimator.process_transaction(tx_hash, fee_per_kb, self.tip_height);
         Ok(())
     }
 
+    /// Remove transactions older than `tx_expiration` at `now`, with their
+    /// descendants; returns how many were removed
+    pub fn expire(&mut self, now: u64) -> usize {
+        let cutoff = now.saturating_sub(self.config.tx_expiration);
+        let expired: Vec<TxHash> = self.by_time
+            .range(..(cutoff, [0u8; 32]))
+            .map(|(_, hash)| TxHash::from_bytes(*hash))
+            .collect();
+        let mut removed = 0;
+        for tx_hash in self.with_descendants(&expired) {
+            if self.remove_transaction(&tx_hash).is_some() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Minimum fee per 1000 bytes a new transaction must pay at `now`: the
+    /// configured minimum or, after evictions, the decaying rolling minimum
+    pub fn min_fee_per_kb(&mut self, now: u64) -> u64 {
+        if self.rolling_min_fee_per_kb > 0 && now > self.rolling_fee_time {
+            let halvings = (now - self.rolling_fee_time) as f64 / ROLLING_FEE_HALFLIFE as f64;
+            self.rolling_min_fee_per_kb = (self.rolling_min_fee_per_kb as f64 / 2f64.powf(halvings)) as u64;
+            self.rolling_fee_time = now;
+            if self.rolling_min_fee_per_kb < INCREMENTAL_RELAY_FEE_PER_KB / 2 {
+                self.rolling_min_fee_per_kb = 0;
+            }
+        }
+        self.rolling_min_fee_per_kb.max(self.config.min_fee_per_byte.saturating_mul(1000))
+    }
+
+    /// Evict lowest descendant fee-rate packages until within `max_size` and
+    /// `max_bytes`, raising the rolling minimum fee above each evicted rate
+    fn trim_to_size(&mut self, now: u64) {
+        while self.transactions.len() > self.config.max_size || self.total_size > self.config.max_bytes {
+            let Some(lowest) = self.by_descendant_rate.first().copied() else { break };
+
+            let evicted_rate = (lowest.descendant_fees as u128 * 1000 / lowest.descendant_size.max(1) as u128) as u64;
+            self.min_fee_per_kb(now);
+            self.rolling_min_fee_per_kb = self.rolling_min_fee_per_kb
+                .max(evicted_rate.saturating_add(INCREMENTAL_RELAY_FEE_PER_KB));
+            self.rolling_fee_time = now;
+
+            for tx_hash in self.with_descendants(&[lowest.tx_hash]) {
+                self.remove_transaction(&tx_hash);
+            }
+        }
+    }
+
+    /// Add a transaction that can be mined in the next block
+    ///
+    /// `ctx` describes the next block (see `Consensus::context_for_next_block`)
+    /// and `utxos` the chain it builds on. Immature coinbase spends, unreached
+    /// locktimes and unmet relative locks are rejected; so are transactions
+    /// with invalid scripts, too low a fee or unreplaceable conflicts.
+    pub fn accept_transaction(
+        &mut self,
+        tx: Transaction,
+        consensus: &Consensus,
+        ctx: &ValidationContext,
+        utxos: &dyn UtxoView,
+    ) -> Result<()> {
+        self.accept_transaction_at(tx, consensus, ctx, utxos, unix_time())
+    }
+
+    /// `accept_transaction` as if the transaction arrived at `time` (Unix seconds)
+    ///
+    /// The pool takes the chain height for fee estimation from `ctx`.
+    pub fn accept_transaction_at(
+        &mut self,
+        tx: Transaction,
+        consensus: &Consensus,
+        ctx: &ValidationContext,
+        utxos: &dyn UtxoView,
+        time: u64,
+    ) -> Result<()> {
+        consensus.check_transaction_locks(&tx, ctx, &self.view(utxos, ctx.height))?;
+        self.tip_height = ctx.height.saturating_sub(1);
+        self.add_transaction_at(tx, utxos, &consensus.chain_params, time)
+    }
+
+    /// Write every pooled transaction with its entry time to `path`
+    ///
+    /// Parents are written before their children. The file is replaced
+    /// atomically, so a crash mid-write leaves the previous dump intact.
+    /// Returns the number of transactions written.
+    pub fn dump(&self, path: &Path) -> Result<usize> {
+        let mut entries: Vec<&MempoolEntry> = self.transactions.values().collect();
+        entries.sort_by_key(|entry| (entry.ancestor_count, entry.time));
+
+        let mut out = vec![MEMPOOL_FILE_VERSION];
+        encoding::write_varint(&mut out, entries.len() as u64);
+        for entry in &entries {
+            out.extend_from_slice(&entry.time.to_le_bytes());
+            entry.transaction.encode(&mut out);
+        }
+
+        let tmp = path.with_extension("dat.tmp");
+        fs::write(&tmp, &out)?;
+        fs::rename(&tmp, path)?;
+        Ok(entries.len())
+    }
+
+    /// Re-add the transactions saved by `dump` at `path`, keeping their entry times
+    ///
+    /// Each transaction goes through `accept_transaction_at` against the
+    /// current chain, so ones that were mined, double-spent or otherwise became
+    /// invalid while the node was down are dropped, as are expired ones. A
+    /// missing file loads nothing. Restored transactions are not used for fee
+    /// estimation, since how long they have really waited is unknown.
+    pub fn load(
+        &mut self,
+        path: &Path,
+        consensus: &Consensus,
+        ctx: &ValidationContext,
+        utxos: &dyn UtxoView,
+        now: u64,
+    ) -> Result<MempoolLoadStats> {
+        let mut stats = MempoolLoadStats::default();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stats),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = Reader::new(&data);
+        let version = reader.read_u8()?;
+        if version != MEMPOOL_FILE_VERSION {
+            return Err(Error::DecodingError(format!("unsupported mempool file version {}", version)));
+        }
+        let count = reader.read_count(8)?;
+        let mut saved = Vec::with_capacity(count);
+        for _ in 0..count {
+            let time = reader.read_u64()?;
+            saved.push((time, Transaction::decode(&mut reader)?));
+        }
+        reader.finish()?;
+
+        for (time, tx) in saved {
+            if time.saturating_add(self.config.tx_expiration) <= now {
+                stats.expired += 1;
+            } else {
+                let tx_hash = tx.hash();
+                if self.accept_transaction_at(tx, consensus, ctx, utxos, time).is_ok() {
+                    self.fee_estimator.remove_transaction(&tx_hash);
+                    stats.accepted += 1;
+                } else {
+                    stats.rejected += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// `utxos` with the outputs of pooled transactions added, confirming
+    /// at `height`
+    pub fn view<'a>(&'a self, utxos: &'a dyn UtxoView, height: BlockHeight) -> MempoolView<'a> {
+        MempoolView { mempool: self, chain: utxos, height }
+    }
+
     /// Remove transaction from mempool
     pub fn remove_transaction(&mut self, tx_hash: &TxHash) -> Option<Transaction> {
-        if let Some(tx) = self.transactions.remove(tx_hash) {
-            let tx_size = self.estimate_tx_size(&tx);
-            self.total_size = self.total_size.saturating_sub(tx_size);
-            
-            // Rebuild priority queue without this transaction
-            self.rebuild_priority_queue();
-            
-            Some(tx)
-        } else {
-            None
+        let ancestors = self.ancestors_of(&self.transactions.get(tx_hash)?.transaction);
+        let descendants = self.with_descendants(&[*tx_hash]);
+        let entry = self.transactions.remove(tx_hash)?;
+        self.by_descendant_rate.remove(&EvictionKey::new(*tx_hash, &entry));
+        self.by_ancestor_rate.remove(&AncestorKey::new(*tx_hash, &entry));
+        self.by_time.remove(&(entry.time, *tx_hash.as_bytes()));
+
+        // Take it out of the package totals of everything still pooled
+        for ancestor_hash in &ancestors {
+            if let Some(ancestor) = self.transactions.get_mut(ancestor_hash) {
+                self.by_descendant_rate.remove(&EvictionKey::new(*ancestor_hash, ancestor));
+                ancestor.descendant_count -= 1;
+                ancestor.descendant_size -= entry.size;
+                ancestor.descendant_fees -= entry.fee;
+                self.by_descendant_rate.insert(EvictionKey::new(*ancestor_hash, ancestor));
+            }
+        }
+        for descendant_hash in &descendants {
+            if let Some(descendant) = self.transactions.get_mut(descendant_hash) {
+                self.by_ancestor_rate.remove(&AncestorKey::new(*descendant_hash, descendant));
+                descendant.ancestor_count -= 1;
+                descendant.ancestor_size -= entry.size;
+                descendant.ancestor_fees -= entry.fee;
+                self.by_ancestor_rate.insert(AncestorKey::new(*descendant_hash, descendant));
+            }
         }
+
+        for input in &entry.transaction.inputs {
+            if self.spends.get(&input.outpoint()) == Some(tx_hash) {
+                self.spends.remove(&input.outpoint());
+            }
+        }
+        self.total_size = self.total_size.saturating_sub(entry.size);
+        self.fee_estimator.remove_transaction(tx_hash);
+        Some(entry.transaction)
     }
 
     /// Get transaction by hash
     pub fn get_transaction(&self, tx_hash: &TxHash) -> Option<&Transaction> {
+        self.transactions.get(tx_hash).map(|entry| &entry.transaction)
+    }
+
+    /// Get a pooled transaction with its fee
+    pub fn get_entry(&self, tx_hash: &TxHash) -> Option<&MempoolEntry> {
         self.transactions.get(tx_hash)
     }
 
-    /// Get transactions sorted by priority (highest fee first)
+    /// Get transactions for a block, best ancestor fee rate first
+    ///
+    /// Each pick is a transaction together with its not yet selected
+    /// ancestors, ranked by the fee rate of that whole package, so a
+    /// high-fee child pulls in its low-fee parent (child pays for parent).
+    /// Parents always come before their children.
     pub fn get_ordered_transactions(&self, limit: usize) -> Vec<Transaction> {
-        self.priority_queue
-            .iter()
-            .take(limit)
-            .map(|p| p.transaction.clone())
-            .collect()
+        self.select_packages(limit, usize::MAX)
+    }
+
+    /// `get_ordered_transactions` filling at most `max_bytes` instead of a count
+    pub fn get_block_transactions(&self, max_bytes: usize) -> Vec<Transaction> {
+        self.select_packages(usize::MAX, max_bytes)
+    }
+
+    /// Packages by ancestor fee rate, up to `limit` transactions and `max_bytes`
+    fn select_packages(&self, limit: usize, max_bytes: usize) -> Vec<Transaction> {
+        // Package totals of unselected ancestors; only the descendants of
+        // each selected transaction need their key updated
+        let mut packages = self.by_ancestor_rate.clone();
+        let mut updated: HashMap<TxHash, AncestorKey> = HashMap::new();
+        let mut selected = HashSet::new();
+        let mut ordered = Vec::new();
+        let mut bytes = 0;
+
+        while ordered.len() < limit {
+            let Some(best) = packages.pop_last() else { break };
+
+            let mut package: Vec<TxHash> = self.ancestors_of(&self.transactions[&best.tx_hash].transaction)
+                .into_iter()
+                .filter(|tx_hash| !selected.contains(tx_hash))
+                .chain(std::iter::once(best.tx_hash))
+                .collect();
+            if ordered.len() + package.len() > limit || bytes + best.ancestor_size > max_bytes {
+                // Doesn't fit; a smaller package still might
+                continue;
+            }
+            bytes += best.ancestor_size;
+
+            // An ancestor always has fewer ancestors than its descendants
+            package.sort_by_key(|tx_hash| self.transactions[tx_hash].ancestor_count);
+            for tx_hash in package {
+                let entry = &self.transactions[&tx_hash];
+                for descendant in self.with_descendants(&[tx_hash]) {
+                    let key = updated.get(&descendant).copied()
+                        .unwrap_or_else(|| AncestorKey::new(descendant, &self.transactions[&descendant]));
+                    if packages.remove(&key) && descendant != tx_hash {
+                        let key = AncestorKey {
+                            ancestor_fees: key.ancestor_fees - entry.fee,
+                            ancestor_size: key.ancestor_size - entry.size,
+                            tx_hash: descendant,
+                        };
+                        packages.insert(key);
+                        updated.insert(descendant, key);
+                    }
+                }
+                selected.insert(tx_hash);
+                ordered.push(entry.transaction.clone());
+            }
+        }
+        ordered
+    }
+
+    /// Pooled transaction spending `outpoint`, if any
+    pub fn get_spender(&self, outpoint: &OutPoint) -> Option<TxHash> {
+        self.spends.get(outpoint).copied()
     }
 
     /// Remove transactions that are included in a block
+    ///
+    /// Pooled transactions spending the same outputs as a block transaction
+    /// can never confirm, so they are dropped with their descendants.
     pub fn remove_confirmed_transactions(&mut self, confirmed_txs: &[Transaction]) {
         for tx in confirmed_txs {
             let tx_hash = tx.hash();
             self.remove_transaction(&tx_hash);
+
+            for input in &tx.inputs {
+                if let Some(conflict) = self.get_spender(&input.outpoint()) {
+                    for removed in self.with_descendants(&[conflict]) {
+                        self.remove_transaction(&removed);
+                    }
+                }
+            }
         }
     }
 
+    /// Update the pool for a block connected to the chain
+    ///
+    /// Records how long its transactions waited for fee estimation, then
+    /// removes them and anything conflicting with them.
+    pub fn connect_block(&mut self, block: &Block) {
+        let confirmed: Vec<TxHash> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        self.fee_estimator.process_block(block.height, &confirmed);
+        self.tip_height = self.tip_height.max(block.height);
+        self.remove_confirmed_transactions(&block.transactions);
+    }
+
+    /// Fee rate expected to confirm within `target_blocks`, see `FeeEstimator::estimate_fee`
+    pub fn estimate_fee(&self, target_blocks: usize) -> Option<FeeEstimate> {
+        self.fee_estimator.estimate_fee(target_blocks)
+    }
+
+    /// The pool's fee estimator
+    pub fn fee_estimator(&self) -> &FeeEstimator {
+        &self.fee_estimator
+    }
+
+    /// The pool's fee estimator, e.g. to replace it with saved history
+    pub fn fee_estimator_mut(&mut self) -> &mut FeeEstimator {
+        &mut self.fee_estimator
+    }
+
     /// Clear all transactions from mempool
     pub fn clear(&mut self) {
         self.transactions.clear();
-        self.priority_queue.clear();
+        self.spends.clear();
+        self.by_descendant_rate.clear();
+        self.by_ancestor_rate.clear();
+        self.by_time.clear();
         self.total_size = 0;
+        self.fee_estimator.clear_unconfirmed();
     }
 
     /// Get mempool statistics
@@ -192,6 +702,8 @@ impl Mempool {
             transaction_count: self.transactions.len(),
             total_size_bytes: self.total_size,
             max_size: self.config.max_size,
+            max_bytes: self.config.max_bytes,
+            min_fee_per_kb: self.rolling_min_fee_per_kb.max(self.config.min_fee_per_byte.saturating_mul(1000)),
         }
     }
     
@@ -213,14 +725,14 @@ impl Mempool {
             return Err(Error::TransactionTooLarge);
         }
 
-        // Check if has inputs and outputs
-        if tx.inputs.is_empty() && !tx.is_coinbase() {
+        // Coinbase transactions are only valid inside a block
+        if tx.is_coinbase() {
             return Err(Error::InvalidTransaction);
         }
 
-        if tx.outputs.is_empty() {
-            return Err(Error::InvalidTransaction);
-        }
+        // Context-free checks: inputs/outputs present, no duplicate inputs,
+        // no output value overflow
+        tx.check_structure()?;
 
         // Check output amounts are positive
         for output in &tx.outputs {
@@ -229,67 +741,156 @@ impl Mempool {
             }
         }
 
-        // Additional validation can be added here:
-        // - Signature verification
-        // - Input UTXO existence
-        // - Double-spend check
-        // - Script validation
-
         Ok(())
     }
 
-    /// Estimate transaction size in bytes
+    /// Transaction size in bytes (canonical serialized size)
     fn estimate_tx_size(&self, tx: &Transaction) -> usize {
-        // Estimate based on UTXO transaction structure:
-        // - Version: 4 bytes
-        // - Input count: 1-9 bytes (varint)
-        // - Inputs: ~180 bytes each (outpoint + script + sequence)
-        // - Output count: 1-9 bytes (varint)
-        // - Outputs: ~34 bytes each (amount + script)
-        // - Locktime: 4 bytes
-        
-        let base_size = 4 + 1 + 1 + 4; // version + input_count + output_count + locktime
-        let input_size = tx.inputs.len() * 180;
-        let output_size = tx.outputs.len() * 34;
-        
-        base_size + input_size + output_size
+        tx.size()
     }
 
-    /// Evict lowest priority transaction
-    fn evict_lowest_priority(&mut self) -> Result<()> {
-        // Get all transactions sorted by priority (lowest first)
-        let mut txs: Vec<_> = self.priority_queue.iter().cloned().collect();
-        txs.sort_by(|a, b| a.fee_per_byte.cmp(&b.fee_per_byte));
-        
-        if let Some(lowest) = txs.first() {
-            self.remove_transaction(&lowest.tx_hash);
-            Ok(())
-        } else {
-            Err(Error::MempoolFull)
+    /// Input value minus output value, and the outputs spent, looking inputs
+    /// up in the pool and `utxos`
+    fn calculate_fee(&self, tx: &Transaction, utxos: &dyn UtxoView) -> Result<(Amount, Vec<TxOutput>)> {
+        let view = self.view(utxos, self.tip_height + 1);
+        let mut input_total: Amount = 0;
+        let mut spent_outputs = Vec::with_capacity(tx.inputs.len());
+        for input in &tx.inputs {
+            let outpoint = input.outpoint();
+            let coin = view.get_utxo(&outpoint)?
+                .ok_or(ValidationError::MissingInput { index: 0, outpoint })?;
+            input_total = input_total
+                .checked_add(coin.amount)
+                .ok_or(ValidationError::ValueOverflow { index: 0 })?;
+            spent_outputs.push(TxOutput { amount: coin.amount, script_pubkey: coin.script_pubkey });
         }
+
+        let output_total = tx.total_output_amount();
+        let fee = input_total.checked_sub(output_total).ok_or(
+            ValidationError::InputValueTooLow { index: 0, inputs: input_total, outputs: output_total }
+        )?;
+        Ok((fee, spent_outputs))
     }
 
-    /// Rebuild priority queue (after removing transactions)
-    fn rebuild_priority_queue(&mut self) {
-        let mut new_queue = BinaryHeap::new();
-        
-        for (tx_hash, tx) in &self.transactions {
-            let tx_size = self.estimate_tx_size(tx);
-            let estimated_fee = tx_size as u64;
-            let fee_per_byte = if tx_size > 0 {
-                estimated_fee / tx_size as u64
-            } else {
-                0
-            };
-            
-            new_queue.push(TxPriority {
-                fee_per_byte,
-                tx_hash: *tx_hash,
-                transaction: tx.clone(),
-            });
+    /// Pooled transactions `tx` spends from, directly or through other pooled transactions
+    fn ancestors_of(&self, tx: &Transaction) -> HashSet<TxHash> {
+        let mut ancestors = HashSet::new();
+        let mut queue: Vec<TxHash> = tx.inputs.iter().map(|input| input.prev_tx_hash).collect();
+        while let Some(tx_hash) = queue.pop() {
+            let Some(entry) = self.transactions.get(&tx_hash) else { continue };
+            if ancestors.insert(tx_hash) {
+                queue.extend(entry.transaction.inputs.iter().map(|input| input.prev_tx_hash));
+            }
         }
-        
-        self.priority_queue = new_queue;
+        ancestors
+    }
+
+    /// Reject `entry` if it or one of its `ancestors` would exceed the package limits
+    fn check_package_limits(&self, entry: &MempoolEntry, ancestors: &HashSet<TxHash>) -> Result<()> {
+        let ancestor_size: usize = ancestors.iter().map(|tx_hash| self.transactions[tx_hash].size).sum();
+        if ancestors.len() + 1 > self.config.max_ancestor_count {
+            return Err(Error::PackageTooLarge(format!(
+                "{} ancestors (max {})", ancestors.len() + 1, self.config.max_ancestor_count
+            )));
+        }
+        if ancestor_size + entry.size > self.config.max_ancestor_size {
+            return Err(Error::PackageTooLarge(format!(
+                "ancestor size {} (max {})", ancestor_size + entry.size, self.config.max_ancestor_size
+            )));
+        }
+
+        for ancestor_hash in ancestors {
+            let ancestor = &self.transactions[ancestor_hash];
+            if ancestor.descendant_count + 1 > self.config.max_descendant_count {
+                return Err(Error::PackageTooLarge(format!(
+                    "{} would have {} descendants (max {})",
+                    ancestor_hash, ancestor.descendant_count + 1, self.config.max_descendant_count
+                )));
+            }
+            if ancestor.descendant_size + entry.size > self.config.max_descendant_size {
+                return Err(Error::PackageTooLarge(format!(
+                    "{} would have descendant size {} (max {})",
+                    ancestor_hash, ancestor.descendant_size + entry.size, self.config.max_descendant_size
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Transactions `entry` would replace: the pooled transactions spending
+    /// its inputs and all their descendants
+    ///
+    /// Empty when nothing conflicts. The replacement must pay a higher fee
+    /// rate than each conflicting transaction, a higher absolute fee than
+    /// everything it evicts plus relay of its own bytes, must not evict more
+    /// than `max_replacement_evictions` transactions and must not spend their
+    /// outputs.
+    fn check_replacement(&self, entry: &MempoolEntry) -> Result<Vec<TxHash>> {
+        let mut conflicts = Vec::new();
+        for input in &entry.transaction.inputs {
+            let outpoint = input.outpoint();
+            let Some(spent_by) = self.get_spender(&outpoint) else { continue };
+            if !self.transactions[&spent_by].transaction.signals_rbf() {
+                return Err(Error::MempoolConflict { outpoint, spent_by });
+            }
+            if !conflicts.contains(&spent_by) {
+                conflicts.push(spent_by);
+            }
+        }
+        if conflicts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        for conflict in &conflicts {
+            if entry.cmp_fee_rate(&self.transactions[conflict]) != Ordering::Greater {
+                return Err(Error::ReplacementRejected(format!(
+                    "fee rate does not exceed that of {}", conflict
+                )));
+            }
+        }
+
+        let replaced = self.with_descendants(&conflicts);
+        if replaced.len() > self.config.max_replacement_evictions {
+            return Err(Error::ReplacementRejected(format!(
+                "would evict {} transactions (max {})", replaced.len(), self.config.max_replacement_evictions
+            )));
+        }
+
+        if entry.transaction.inputs.iter().any(|input| replaced.contains(&input.prev_tx_hash)) {
+            return Err(Error::ReplacementRejected("spends an output of a replaced transaction".to_string()));
+        }
+
+        let replaced_fees: u128 = replaced.iter().map(|tx_hash| self.transactions[tx_hash].fee as u128).sum();
+        let relay_fee = self.config.min_fee_per_byte as u128 * entry.size as u128;
+        if (entry.fee as u128) < replaced_fees + relay_fee {
+            return Err(Error::ReplacementRejected(format!(
+                "fee {} does not cover replaced fees {} plus relay fee {}", entry.fee, replaced_fees, relay_fee
+            )));
+        }
+
+        Ok(replaced)
+    }
+
+    /// `roots` and every pooled transaction that spends their outputs, directly
+    /// or through other pooled transactions
+    fn with_descendants(&self, roots: &[TxHash]) -> Vec<TxHash> {
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        let mut queue = roots.to_vec();
+        while let Some(tx_hash) = queue.pop() {
+            if !seen.insert(tx_hash) {
+                continue;
+            }
+            if let Some(entry) = self.transactions.get(&tx_hash) {
+                for index in 0..entry.transaction.outputs.len() {
+                    if let Some(child) = self.get_spender(&OutPoint::new(tx_hash, index as u32)) {
+                        queue.push(child);
+                    }
+                }
+            }
+            found.push(tx_hash);
+        }
+        found
     }
 }
 
@@ -299,6 +900,25 @@ impl Default for Mempool {
     }
 }
 
+/// Current Unix time in seconds
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Outcome of `Mempool::load`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MempoolLoadStats {
+    /// Transactions back in the pool
+    pub accepted: usize,
+    /// Transactions that no longer validate against the chain
+    pub rejected: usize,
+    /// Transactions older than `tx_expiration`
+    pub expired: usize,
+}
+
 /// Mempool statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MempoolStats {
@@ -308,13 +928,81 @@ pub struct MempoolStats {
     pub total_size_bytes: usize,
     /// Maximum size
     pub max_size: usize,
+    /// Maximum total size in bytes
+    pub max_bytes: usize,
+    /// Current minimum fee per 1000 bytes for new transactions
+    pub min_fee_per_kb: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::transaction::{TxInput, TxOutput};
-    use crate::types::TxHash;
+    use crate::transaction::{TxInput, TxOutput, MAX_BIP125_RBF_SEQUENCE};
+    use crate::chain_params::ChainParams;
+    use crate::script::{self, OP_1};
+    use crate::sighash;
+    use crate::types::{BlockHash, TxHash};
+    use secp256k1::{Message, PublicKey, SecretKey, SECP256K1};
+
+    /// Confirmed anyone-can-spend outputs for tests, all at height 1
+    #[derive(Default)]
+    struct TestUtxos(HashMap<OutPoint, UtxoEntry>);
+
+    impl TestUtxos {
+        fn with_coins(amounts: &[Amount]) -> Self {
+            let mut utxos = TestUtxos::default();
+            for (index, &amount) in amounts.iter().enumerate() {
+                let outpoint = OutPoint::new(TxHash::from_bytes([1u8; 32]), index as u32);
+                utxos.0.insert(outpoint, UtxoEntry {
+                    tx_hash: outpoint.tx_hash,
+                    output_index: outpoint.index,
+                    amount,
+                    script_pubkey: vec![OP_1],
+                    block_height: 1,
+                    is_coinbase: false,
+                });
+            }
+            utxos
+        }
+    }
+
+    impl UtxoView for TestUtxos {
+        fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
+            Ok(self.0.get(outpoint).cloned())
+        }
+
+        fn median_time_past(&self, _height: BlockHeight) -> Result<Timestamp> {
+            Ok(0)
+        }
+    }
+
+    fn spending(prev_tx_hash: TxHash, prev_tx_index: u32, output_amount: u64) -> Transaction {
+        let mut tx = create_test_transaction(output_amount);
+        tx.inputs[0].prev_tx_hash = prev_tx_hash;
+        tx.inputs[0].prev_tx_index = prev_tx_index;
+        tx
+    }
+
+    /// Owner of the p2pkh outputs in the signature tests
+    fn test_key() -> (SecretKey, Vec<u8>) {
+        let secret = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(SECP256K1, &secret).serialize().to_vec();
+        (secret, pubkey)
+    }
+
+    fn test_key_script() -> Vec<u8> {
+        script::p2pkh_script_pubkey(&script::hash160(&test_key().1))
+    }
+
+    /// Sign the only input of `tx`, which spends an output of `test_key`
+    fn sign(tx: &mut Transaction) {
+        let (secret, pubkey) = test_key();
+        let network_id = ChainParams::regtest().network_id;
+        let digest = sighash::signature_hash(tx, 0, &test_key_script(), sighash::SIGHASH_ALL, network_id).unwrap();
+        let mut sig = SECP256K1.sign_ecdsa(&Message::from_digest(digest), &secret).serialize_der().to_vec();
+        sig.push(sighash::SIGHASH_ALL);
+        tx.inputs[0].script = script::p2pkh_script_sig(&sig, &pubkey);
+    }
 
     fn create_test_transaction(output_amount: u64) -> Transaction {
         Transaction {
@@ -327,7 +1015,7 @@ mod tests {
             }],
             outputs: vec![TxOutput {
                 amount: output_amount,
-                script_pubkey: vec![],
+                script_pubkey: vec![OP_1],
             }],
             locktime: 0,
         }
@@ -343,20 +1031,25 @@ mod tests {
     #[test]
     fn test_add_transaction() {
         let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[2000000]);
         let tx = create_test_transaction(1000000);
+        let tx_hash = tx.hash();
         
-        let result = mempool.add_transaction(tx);
+        let result = mempool.add_transaction_at(tx, &utxos, &params, 0);
         assert!(result.is_ok());
         assert_eq!(mempool.transactions.len(), 1);
+        assert_eq!(mempool.get_entry(&tx_hash).unwrap().fee, 1000000);
     }
 
     #[test]
     fn test_remove_transaction() {
         let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
         let tx = create_test_transaction(1000000);
         let tx_hash = tx.hash();
         
-        let _ = mempool.add_transaction(tx);
+        mempool.add_transaction_at(tx, &TestUtxos::with_coins(&[2000000]), &params, 0).unwrap();
         
         let removed = mempool.remove_transaction(&tx_hash);
         assert!(removed.is_some());
@@ -366,12 +1059,435 @@ mod tests {
     #[test]
     fn test_mempool_stats() {
         let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
         let tx = create_test_transaction(1000000);
         
-        let _ = mempool.add_transaction(tx);
+        mempool.add_transaction_at(tx, &TestUtxos::with_coins(&[2000000]), &params, 0).unwrap();
         
         let stats = mempool.stats();
         assert_eq!(stats.transaction_count, 1);
         assert!(stats.total_size_bytes > 0);
     }
+
+    #[test]
+    fn test_accept_transaction_enforces_locks() {
+        use crate::block::Block;
+        use crate::storage::Storage;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let params = ChainParams::regtest();
+        let owner = script::script_pubkey_to_address(&test_key_script(), &params).unwrap();
+        let coinbase = Block::create_coinbase_tx(0, &owner, 5_000, &params).unwrap();
+        let block = Block::new(1, BlockHash::zero(), vec![coinbase.clone()], 1701657600, 0x207fffff, 0);
+        storage.put_block(0, &block).unwrap();
+
+        let consensus = Consensus::new(ChainParams { block_maturity: 2, ..ChainParams::regtest() });
+        let ctx = consensus.context_for_next_block(&storage).unwrap();
+        let mut mempool = Mempool::new();
+        let mut accept = |tx: &Transaction, ctx: &ValidationContext| {
+            match mempool.accept_transaction(tx.clone(), &consensus, ctx, &storage) {
+                Ok(()) => "ok",
+                Err(Error::Validation(e)) => e.code(),
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+        };
+
+        let mut tx = create_test_transaction(1_000);
+        tx.inputs[0].prev_tx_hash = coinbase.hash();
+        assert_eq!(accept(&tx, &ctx), "bad-txns-premature-spend-of-coinbase");
+
+        // One block later the coinbase is mature
+        let ctx = ValidationContext { height: 2, ..ctx };
+        tx.locktime = 2;
+        tx.inputs[0].sequence = 0;
+        assert_eq!(accept(&tx, &ctx), "bad-txns-nonfinal");
+
+        tx.locktime = 1;
+        tx.version = 2;
+        tx.inputs[0].sequence = 3;
+        assert_eq!(accept(&tx, &ctx), "non-BIP68-final");
+
+        tx.inputs[0].sequence = 2;
+        assert_eq!(accept(&tx, &ctx), "mandatory-script-verify-flag-failed");
+        sign(&mut tx);
+        assert_eq!(accept(&tx, &ctx), "ok");
+    }
+
+    #[test]
+    fn test_child_relative_lock_on_pooled_parent() {
+        let consensus = Consensus::new(ChainParams::regtest());
+        let utxos = TestUtxos::with_coins(&[100_000]);
+        let ctx = ValidationContext {
+            prev_hash: BlockHash::zero(),
+            height: 2,
+            expected_bits: 0,
+            median_time_past: 0,
+            now: 0,
+        };
+        let mut mempool = Mempool::new();
+        let parent = create_test_transaction(90_000);
+        mempool.accept_transaction(parent.clone(), &consensus, &ctx, &utxos).unwrap();
+
+        // The parent can confirm in the next block, but not before it
+        let mut child = spending(parent.hash(), 0, 80_000);
+        child.version = 2;
+        child.inputs[0].sequence = 1;
+        match mempool.accept_transaction(child.clone(), &consensus, &ctx, &utxos) {
+            Err(Error::Validation(e)) => assert_eq!(e.code(), "non-BIP68-final"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        child.inputs[0].sequence = 0;
+        mempool.accept_transaction(child, &consensus, &ctx, &utxos).unwrap();
+    }
+
+    #[test]
+    fn test_fee_needs_known_inputs() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[10_000]);
+        let mut add = |tx: Transaction| match mempool.add_transaction_at(tx, &utxos, &params, 0) {
+            Ok(()) => "ok",
+            Err(Error::Validation(e)) => e.code(),
+            Err(Error::FeeTooLow) => "fee-too-low",
+            Err(e) => panic!("unexpected error {:?}", e),
+        };
+
+        assert_eq!(add(spending(TxHash::from_bytes([2u8; 32]), 0, 1_000)), "bad-txns-inputs-missingorspent");
+        assert_eq!(add(create_test_transaction(10_001)), "bad-txns-in-belowout");
+        // Less than one satoshi per byte
+        assert_eq!(add(create_test_transaction(9_999)), "fee-too-low");
+
+        let parent = create_test_transaction(5_000);
+        assert_eq!(add(parent.clone()), "ok");
+        // Unconfirmed outputs can be spent, but only once they exist
+        assert_eq!(add(spending(parent.hash(), 1, 1_000)), "bad-txns-inputs-missingorspent");
+        assert_eq!(add(spending(parent.hash(), 0, 1_000)), "ok");
+    }
+
+    #[test]
+    fn test_scripts_verified_before_replacement() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let mut utxos = TestUtxos::with_coins(&[100_000]);
+        let outpoint = OutPoint::new(TxHash::from_bytes([1u8; 32]), 0);
+        utxos.0.get_mut(&outpoint).unwrap().script_pubkey = test_key_script();
+        let replaceable = |output_amount| {
+            let mut tx = create_test_transaction(output_amount);
+            tx.inputs[0].sequence = MAX_BIP125_RBF_SEQUENCE;
+            tx
+        };
+
+        let mut signed = replaceable(90_000);
+        sign(&mut signed);
+        mempool.add_transaction_at(signed.clone(), &utxos, &params, 0).unwrap();
+
+        let mut add = |tx: Transaction| match mempool.add_transaction_at(tx, &utxos, &params, 0) {
+            Ok(()) => "ok",
+            Err(Error::Validation(e)) => e.code(),
+            Err(e) => panic!("unexpected error {:?}", e),
+        };
+        // Pays enough to replace the signed spend, but isn't signed
+        let mut bump = replaceable(50_000);
+        assert_eq!(add(bump.clone()), "mandatory-script-verify-flag-failed");
+        // A signature over another transaction doesn't carry over
+        bump.inputs[0].script = signed.inputs[0].script.clone();
+        assert_eq!(add(bump.clone()), "mandatory-script-verify-flag-failed");
+        assert_eq!(mempool.get_spender(&outpoint), Some(signed.hash()));
+
+        sign(&mut bump);
+        mempool.add_transaction_at(bump.clone(), &utxos, &params, 0).unwrap();
+        assert_eq!(mempool.get_spender(&outpoint), Some(bump.hash()));
+        assert!(mempool.get_transaction(&signed.hash()).is_none());
+    }
+
+    #[test]
+    fn test_ordered_by_fee_rate() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000, 100_000]);
+
+        let low = spending(TxHash::from_bytes([1u8; 32]), 0, 99_000);
+        let mid = spending(TxHash::from_bytes([1u8; 32]), 1, 90_000);
+        let high = spending(TxHash::from_bytes([1u8; 32]), 2, 50_000);
+        // Pays enough to lift its low-fee parent above mid
+        let child = spending(low.hash(), 0, 10_000);
+        for tx in [&low, &child, &high, &mid] {
+            mempool.add_transaction_at(tx.clone(), &utxos, &params, 0).unwrap();
+        }
+
+        let hashes: Vec<_> = mempool.get_ordered_transactions(10).iter().map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![high.hash(), low.hash(), child.hash(), mid.hash()]);
+
+        // The low/child package doesn't fit after high, so mid goes next
+        let hashes: Vec<_> = mempool.get_ordered_transactions(2).iter().map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![high.hash(), mid.hash()]);
+
+        // Same when the limit is in bytes
+        let two_txs = high.size() + mid.size();
+        let hashes: Vec<_> = mempool.get_block_transactions(two_txs).iter().map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![high.hash(), mid.hash()]);
+    }
+
+    #[test]
+    fn test_full_pool_evicts_lowest_fee_rate() {
+        let mut mempool = Mempool::with_config(MempoolConfig { max_size: 2, ..MempoolConfig::default() });
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000, 100_000, 100_000]);
+
+        let low = spending(TxHash::from_bytes([1u8; 32]), 0, 99_000);
+        let mid = spending(TxHash::from_bytes([1u8; 32]), 1, 90_000);
+        mempool.add_transaction_at(low.clone(), &utxos, &params, 0).unwrap();
+        mempool.add_transaction_at(mid.clone(), &utxos, &params, 0).unwrap();
+
+        // Not better than anything in the pool
+        let lower = spending(TxHash::from_bytes([1u8; 32]), 2, 99_500);
+        assert!(matches!(mempool.add_transaction_at(lower, &utxos, &params, 0), Err(Error::MempoolFull)));
+
+        let high = spending(TxHash::from_bytes([1u8; 32]), 3, 50_000);
+        mempool.add_transaction_at(high.clone(), &utxos, &params, 0).unwrap();
+        assert!(mempool.get_transaction(&low.hash()).is_none());
+        assert!(mempool.get_transaction(&mid.hash()).is_some());
+        assert_eq!(mempool.stats().total_size_bytes, mid.size() + high.size());
+    }
+
+    #[test]
+    fn test_conflicting_spend_rejected() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000]);
+
+        let first = create_test_transaction(90_000);
+        mempool.add_transaction_at(first.clone(), &utxos, &params, 0).unwrap();
+        assert_eq!(mempool.get_spender(&first.inputs[0].outpoint()), Some(first.hash()));
+
+        // Paying more doesn't help when the original doesn't signal replaceability
+        let second = create_test_transaction(50_000);
+        match mempool.add_transaction_at(second, &utxos, &params, 0) {
+            Err(Error::MempoolConflict { spent_by, .. }) => assert_eq!(spent_by, first.hash()),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(mempool.get_ordered_transactions(10).len(), 1);
+
+        // Once removed the output is free again
+        mempool.remove_transaction(&first.hash());
+        assert!(mempool.get_spender(&first.inputs[0].outpoint()).is_none());
+        mempool.add_transaction_at(create_test_transaction(50_000), &utxos, &params, 0).unwrap();
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000]);
+        let replaceable = |output_amount| {
+            let mut tx = create_test_transaction(output_amount);
+            tx.inputs[0].sequence = MAX_BIP125_RBF_SEQUENCE;
+            tx
+        };
+
+        // Original pays 10,000 and its child another 10,000
+        let original = replaceable(90_000);
+        assert!(original.signals_rbf());
+        let child = spending(original.hash(), 0, 80_000);
+        mempool.add_transaction_at(original.clone(), &utxos, &params, 0).unwrap();
+        mempool.add_transaction_at(child.clone(), &utxos, &params, 0).unwrap();
+
+        let rejected = |result: Result<()>| matches!(result, Err(Error::ReplacementRejected(_)));
+        // Higher fee rate than the original, but not more than both fees
+        assert!(rejected(mempool.add_transaction_at(replaceable(81_000), &utxos, &params, 0)));
+        // Enough fee, but evicting two transactions is over the limit
+        mempool.config.max_replacement_evictions = 1;
+        assert!(rejected(mempool.add_transaction_at(replaceable(70_000), &utxos, &params, 0)));
+        mempool.config.max_replacement_evictions = 100;
+
+        let bump = replaceable(70_000);
+        mempool.add_transaction_at(bump.clone(), &utxos, &params, 0).unwrap();
+        assert!(mempool.get_transaction(&original.hash()).is_none());
+        assert!(mempool.get_transaction(&child.hash()).is_none());
+        assert_eq!(mempool.get_spender(&bump.inputs[0].outpoint()), Some(bump.hash()));
+        assert_eq!(mempool.stats().total_size_bytes, bump.size());
+    }
+
+    #[test]
+    fn test_block_conflicts_evicted() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000]);
+
+        let pooled = create_test_transaction(90_000);
+        let child = spending(pooled.hash(), 0, 80_000);
+        let unrelated = spending(TxHash::from_bytes([1u8; 32]), 1, 90_000);
+        for tx in [&pooled, &child, &unrelated] {
+            mempool.add_transaction_at(tx.clone(), &utxos, &params, 0).unwrap();
+        }
+
+        // A block confirms a different spend of the same coin
+        let confirmed = create_test_transaction(95_000);
+        mempool.remove_confirmed_transactions(&[confirmed]);
+        assert_eq!(mempool.size(), 1);
+        assert!(mempool.get_transaction(&unrelated.hash()).is_some());
+    }
+
+    #[test]
+    fn test_connect_block_feeds_fee_estimator() {
+        let mut mempool = Mempool::new();
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000]);
+
+        let mined = create_test_transaction(90_000);
+        let waiting = spending(TxHash::from_bytes([1u8; 32]), 1, 90_000);
+        mempool.add_transaction_at(mined.clone(), &utxos, &params, 0).unwrap();
+        mempool.add_transaction_at(waiting.clone(), &utxos, &params, 0).unwrap();
+        assert_eq!(mempool.fee_estimator().tracked_count(), 2);
+
+        let block = Block::new(1, BlockHash::zero(), vec![mined.clone()], 0, 0, 1);
+        mempool.connect_block(&block);
+        assert_eq!(mempool.size(), 1);
+        assert_eq!(mempool.fee_estimator().best_height(), 1);
+        assert_eq!(mempool.fee_estimator().tracked_count(), 1);
+
+        mempool.remove_transaction(&waiting.hash());
+        assert_eq!(mempool.fee_estimator().tracked_count(), 0);
+    }
+
+    #[test]
+    fn test_package_totals_and_limits() {
+        let config = MempoolConfig { max_ancestor_count: 3, max_descendant_count: 3, ..MempoolConfig::default() };
+        let mut mempool = Mempool::with_config(config);
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000]);
+
+        // A chain of unconfirmed transactions, each paying 10,000
+        let mut chain = vec![create_test_transaction(90_000)];
+        for amount in [80_000, 70_000] {
+            let parent = chain.last().unwrap().hash();
+            chain.push(spending(parent, 0, amount));
+        }
+        for tx in &chain {
+            mempool.add_transaction_at(tx.clone(), &utxos, &params, 0).unwrap();
+        }
+
+        let root = mempool.get_entry(&chain[0].hash()).unwrap();
+        assert_eq!((root.descendant_count, root.descendant_fees), (3, 30_000));
+        let tip = mempool.get_entry(&chain[2].hash()).unwrap();
+        assert_eq!((tip.ancestor_count, tip.ancestor_fees), (3, 30_000));
+        assert_eq!(tip.ancestor_size, chain.iter().map(Transaction::size).sum::<usize>());
+
+        let too_long = spending(chain[2].hash(), 0, 60_000);
+        assert!(matches!(mempool.add_transaction_at(too_long, &utxos, &params, 0), Err(Error::PackageTooLarge(_))));
+
+        // Confirming the root leaves the rest with one ancestor fewer
+        mempool.remove_confirmed_transactions(&chain[..1]);
+        let tip = mempool.get_entry(&chain[2].hash()).unwrap();
+        assert_eq!((tip.ancestor_count, tip.ancestor_fees), (2, 20_000));
+        let middle = mempool.get_entry(&chain[1].hash()).unwrap();
+        assert_eq!((middle.descendant_count, middle.ancestor_count), (2, 1));
+        let keys: BTreeSet<AncestorKey> = mempool.transactions
+            .iter()
+            .map(|(tx_hash, entry)| AncestorKey::new(*tx_hash, entry))
+            .collect();
+        assert_eq!(mempool.by_ancestor_rate, keys);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut mempool = Mempool::with_config(MempoolConfig { tx_expiration: 100, ..MempoolConfig::default() });
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000, 100_000]);
+
+        let old = create_test_transaction(90_000);
+        let child = spending(old.hash(), 0, 80_000);
+        let young = spending(TxHash::from_bytes([1u8; 32]), 1, 90_000);
+        mempool.add_transaction_at(old.clone(), &utxos, &params, 1_000).unwrap();
+        mempool.add_transaction_at(child.clone(), &utxos, &params, 1_050).unwrap();
+        mempool.add_transaction_at(young.clone(), &utxos, &params, 1_090).unwrap();
+        assert_eq!(mempool.get_entry(&young.hash()).unwrap().time, 1_090);
+
+        assert_eq!(mempool.expire(1_100), 0);
+        // The child goes with its expired parent
+        assert_eq!(mempool.expire(1_101), 2);
+        assert_eq!(mempool.size(), 1);
+
+        // Adding a transaction expires the rest
+        let late = spending(TxHash::from_bytes([1u8; 32]), 2, 90_000);
+        mempool.add_transaction_at(late.clone(), &utxos, &params, 1_200).unwrap();
+        assert!(mempool.get_transaction(&young.hash()).is_none());
+        assert_eq!(mempool.stats().total_size_bytes, late.size());
+    }
+
+    #[test]
+    fn test_byte_cap_raises_rolling_min_fee() {
+        let size = create_test_transaction(0).size();
+        let config = MempoolConfig { max_bytes: 2 * size, ..MempoolConfig::default() };
+        let mut mempool = Mempool::with_config(config);
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000, 100_000, 100_000]);
+        let coin = |index, fee: u64| spending(TxHash::from_bytes([1u8; 32]), index, 100_000 - fee);
+
+        let cheap = coin(0, 10_000);
+        mempool.add_transaction_at(cheap.clone(), &utxos, &params, 0).unwrap();
+        mempool.add_transaction_at(coin(1, 20_000), &utxos, &params, 0).unwrap();
+        mempool.add_transaction_at(coin(2, 30_000), &utxos, &params, 0).unwrap();
+        assert!(mempool.get_transaction(&cheap.hash()).is_none());
+        assert_eq!(mempool.stats().total_size_bytes, 2 * size);
+
+        // New transactions must now beat the evicted fee rate
+        let rolling = 10_000 * 1000 / size as u64 + INCREMENTAL_RELAY_FEE_PER_KB;
+        assert_eq!(mempool.stats().min_fee_per_kb, rolling);
+        assert!(matches!(mempool.add_transaction_at(coin(3, 10_000), &utxos, &params, 0), Err(Error::FeeTooLow)));
+        // Too cheap to stay in a full pool, and evicting it raises the minimum again
+        assert!(matches!(mempool.add_transaction_at(coin(3, 15_000), &utxos, &params, 0), Err(Error::MempoolFull)));
+        let rolling = 15_000 * 1000 / size as u64 + INCREMENTAL_RELAY_FEE_PER_KB;
+        assert_eq!(mempool.stats().min_fee_per_kb, rolling);
+
+        // The rolling minimum halves every ROLLING_FEE_HALFLIFE
+        assert_eq!(mempool.min_fee_per_kb(ROLLING_FEE_HALFLIFE), rolling / 2);
+        assert_eq!(mempool.min_fee_per_kb(20 * ROLLING_FEE_HALFLIFE), 1_000);
+    }
+
+    #[test]
+    fn test_dump_and_load() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join(MEMPOOL_FILE_NAME);
+        let mut mempool = Mempool::with_config(MempoolConfig { tx_expiration: 1_000, ..MempoolConfig::default() });
+        let params = ChainParams::regtest();
+        let utxos = TestUtxos::with_coins(&[100_000, 100_000, 100_000]);
+
+        let parent = create_test_transaction(90_000);
+        let child = spending(parent.hash(), 0, 80_000);
+        let spent_meanwhile = spending(TxHash::from_bytes([1u8; 32]), 1, 90_000);
+        let old = spending(TxHash::from_bytes([1u8; 32]), 2, 90_000);
+        mempool.add_transaction_at(old, &utxos, &params, 100).unwrap();
+        mempool.add_transaction_at(parent.clone(), &utxos, &params, 500).unwrap();
+        mempool.add_transaction_at(child.clone(), &utxos, &params, 600).unwrap();
+        mempool.add_transaction_at(spent_meanwhile.clone(), &utxos, &params, 700).unwrap();
+        assert_eq!(mempool.dump(&path).unwrap(), 4);
+
+        let consensus = Consensus::new(ChainParams::regtest());
+        let ctx = ValidationContext {
+            prev_hash: BlockHash::zero(),
+            height: 2,
+            expected_bits: 0,
+            median_time_past: 0,
+            now: 1_200,
+        };
+        let mut utxos = utxos;
+        utxos.0.remove(&spent_meanwhile.inputs[0].outpoint());
+
+        let mut restored = Mempool::with_config(MempoolConfig { tx_expiration: 1_000, ..MempoolConfig::default() });
+        let stats = restored.load(&path, &consensus, &ctx, &utxos, 1_200).unwrap();
+        assert_eq!(stats, MempoolLoadStats { accepted: 2, rejected: 1, expired: 1 });
+        assert_eq!(restored.get_entry(&parent.hash()).unwrap().time, 500);
+        assert_eq!(restored.get_entry(&child.hash()).unwrap().ancestor_count, 2);
+        assert_eq!(restored.fee_estimator().tracked_count(), 0);
+
+        // Nothing saved yet is not an error; an unknown version is
+        let stats = Mempool::new().load(&temp_dir.path().join("missing.dat"), &consensus, &ctx, &utxos, 0).unwrap();
+        assert_eq!(stats, MempoolLoadStats::default());
+        let mut data = std::fs::read(&path).unwrap();
+        data[0] = MEMPOOL_FILE_VERSION + 1;
+        std::fs::write(&path, data).unwrap();
+        assert!(Mempool::new().load(&path, &consensus, &ctx, &utxos, 0).is_err());
+    }
 }
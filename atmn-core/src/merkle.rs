@@ -0,0 +1,354 @@
+// atmn-core/src/merkle.rs
+// Merkle tree over transaction ids: roots, branches and inclusion proofs
+
+use crate::block::{Block, BlockHeader};
+use crate::consensus::sha256d;
+use crate::encoding::{self, Decodable, Encodable, Reader, BLOCK_HEADER_SIZE};
+use crate::transaction::Transaction;
+use crate::types::{BlockHash, BlockHeight, TxHash};
+use crate::error::Result;
+
+/// Leaves of a block's tree: its transaction ids, in block order
+pub fn transaction_leaves(transactions: &[Transaction]) -> Vec<BlockHash> {
+    transactions.iter().map(|tx| BlockHash(*tx.hash().as_bytes())).collect()
+}
+
+/// Parent of two tree nodes
+pub fn hash_pair(left: &BlockHash, right: &BlockHash) -> BlockHash {
+    let mut combined = [0u8; 64];
+    combined[..32].copy_from_slice(&left.0);
+    combined[32..].copy_from_slice(&right.0);
+    sha256d(&combined)
+}
+
+/// Root over `leaves`, duplicating the last node of odd-sized levels
+///
+/// A single leaf is its own root; no leaves give the zero hash.
+pub fn merkle_root(leaves: &[BlockHash]) -> BlockHash {
+    merkle_root_checked(leaves).0
+}
+
+/// Root over `leaves`, and whether the tree is mutated
+///
+/// Duplicating the last node of odd levels lets a block with its trailing
+/// transactions repeated share a root with the original (CVE-2012-2459).
+/// Any level holding two identical siblings is flagged; a valid block never
+/// has one, as its transaction ids are unique.
+pub fn merkle_root_checked(leaves: &[BlockHash]) -> (BlockHash, bool) {
+    if leaves.is_empty() {
+        return (BlockHash::zero(), false);
+    }
+
+    let mut mutated = false;
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        mutated |= level.chunks(2).any(|pair| pair.len() == 2 && pair[0] == pair[1]);
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    (level[0], mutated)
+}
+
+/// Siblings of the leaf at `index` on its path to the root, lowest first
+///
+/// Returns an empty branch when `index` is out of range.
+pub fn merkle_branch(leaves: &[BlockHash], mut index: usize) -> Vec<BlockHash> {
+    if index >= leaves.len() {
+        return Vec::new();
+    }
+
+    let mut branch = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        // The last node of an odd level is its own sibling
+        let sibling = (index ^ 1).min(level.len() - 1);
+        branch.push(level[sibling]);
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    branch
+}
+
+/// Root reached from `leaf` at `index` by hashing up its `branch`
+///
+/// Changing one leaf, such as the coinbase when rolling its extra nonce,
+/// costs one hash per tree level instead of rebuilding the whole tree.
+pub fn root_from_branch(leaf: BlockHash, mut index: usize, branch: &[BlockHash]) -> BlockHash {
+    let mut node = leaf;
+    for sibling in branch {
+        node = if index & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+        index /= 2;
+    }
+    node
+}
+
+/// Number of levels above the leaves in a tree of `count` leaves
+fn tree_depth(count: usize) -> usize {
+    let mut depth = 0;
+    let mut width = count;
+    while width > 1 {
+        width = width.div_ceil(2);
+        depth += 1;
+    }
+    depth
+}
+
+/// Proof that a transaction is committed to by a block's merkle root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub txid: TxHash,
+    /// Position of the transaction in its block
+    pub index: u32,
+    /// Transactions in the block, fixing the shape of the tree
+    pub tx_count: u32,
+    /// Sibling hashes from the leaf up to the root
+    pub branch: Vec<BlockHash>,
+}
+
+impl MerkleProof {
+    /// Proof for the transaction at `index` of `transactions`
+    pub fn new(transactions: &[Transaction], index: usize) -> Option<Self> {
+        let txid = transactions.get(index)?.hash();
+        Some(MerkleProof {
+            txid,
+            index: index as u32,
+            tx_count: transactions.len() as u32,
+            branch: merkle_branch(&transaction_leaves(transactions), index),
+        })
+    }
+
+    /// Proof for `txid` in `block`, if the block holds it
+    pub fn for_transaction(block: &Block, txid: &TxHash) -> Option<Self> {
+        let index = block.transactions.iter().position(|tx| tx.hash() == *txid)?;
+        Self::new(&block.transactions, index)
+    }
+
+    /// Root the proof leads to
+    pub fn root(&self) -> BlockHash {
+        root_from_branch(BlockHash(*self.txid.as_bytes()), self.index as usize, &self.branch)
+    }
+
+    /// Whether the proof is well formed and leads to `merkle_root`
+    ///
+    /// The branch must match the tree shape given by `tx_count`, and only
+    /// the last node of an odd level may be paired with itself, so proofs
+    /// built from a mutated tree are rejected.
+    pub fn verify(&self, merkle_root: &BlockHash) -> bool {
+        let count = self.tx_count as usize;
+        let mut index = self.index as usize;
+        if index >= count || self.branch.len() != tree_depth(count) {
+            return false;
+        }
+
+        let mut node = BlockHash(*self.txid.as_bytes());
+        let mut width = count;
+        for sibling in &self.branch {
+            let unpaired = index == width - 1 && width % 2 == 1;
+            if unpaired != (*sibling == node) {
+                return false;
+            }
+            node = if index & 1 == 0 {
+                hash_pair(&node, sibling)
+            } else {
+                hash_pair(sibling, &node)
+            };
+            index /= 2;
+            width = width.div_ceil(2);
+        }
+        node == *merkle_root
+    }
+}
+
+impl Encodable for MerkleProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.txid.encode(out);
+        out.extend_from_slice(&self.index.to_le_bytes());
+        out.extend_from_slice(&self.tx_count.to_le_bytes());
+        encoding::write_varint(out, self.branch.len() as u64);
+        for hash in &self.branch {
+            hash.encode(out);
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        32 + 4 + 4 + encoding::varint_len(self.branch.len() as u64) + 32 * self.branch.len()
+    }
+}
+
+impl Decodable for MerkleProof {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        let txid = TxHash::decode(reader)?;
+        let index = reader.read_u32()?;
+        let tx_count = reader.read_u32()?;
+        let count = reader.read_count(32)?;
+        let branch = (0..count).map(|_| BlockHash::decode(reader)).collect::<Result<_>>()?;
+        Ok(MerkleProof { txid, index, tx_count, branch })
+    }
+}
+
+/// Inclusion proof bundled with the header that commits to it
+///
+/// Enough for a light client holding the header chain to check that a
+/// transaction was mined, without the rest of the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionProof {
+    pub header: BlockHeader,
+    pub height: BlockHeight,
+    pub proof: MerkleProof,
+}
+
+impl TransactionProof {
+    /// Whether the proof leads to the header's merkle root
+    pub fn verify(&self) -> bool {
+        self.proof.verify(&self.header.merkle_root)
+    }
+}
+
+impl Encodable for TransactionProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.header.encode(out);
+        out.extend_from_slice(&self.height.to_le_bytes());
+        self.proof.encode(out);
+    }
+
+    fn encoded_len(&self) -> usize {
+        BLOCK_HEADER_SIZE + 8 + self.proof.encoded_len()
+    }
+}
+
+impl Decodable for TransactionProof {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TransactionProof {
+            header: BlockHeader::decode(reader)?,
+            height: reader.read_u64()?,
+            proof: MerkleProof::decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<BlockHash> {
+        (0..count).map(|i| BlockHash::from_bytes([i + 1; 32])).collect()
+    }
+
+    /// `count` distinct transactions, a coinbase first
+    fn transactions(count: u64) -> Vec<Transaction> {
+        (0..count).map(|i| crate::block::test_coinbase(i, 1)).collect()
+    }
+
+    #[test]
+    fn test_root_matches_pairwise_hashing() {
+        assert_eq!(merkle_root(&[]), BlockHash::zero());
+        let three = leaves(3);
+        assert_eq!(merkle_root(&three[..1]), three[0]);
+
+        let left = hash_pair(&three[0], &three[1]);
+        let right = hash_pair(&three[2], &three[2]);
+        assert_eq!(merkle_root(&three), hash_pair(&left, &right));
+    }
+
+    #[test]
+    fn test_branch_leads_to_root() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = merkle_root(&leaves);
+            for index in 0..leaves.len() {
+                let branch = merkle_branch(&leaves, index);
+                assert_eq!(root_from_branch(leaves[index], index, &branch), root, "{} leaves, index {}", count, index);
+            }
+        }
+        assert!(merkle_branch(&leaves(3), 3).is_empty());
+    }
+
+    #[test]
+    fn test_branch_updates_changed_leaf() {
+        let mut leaves = leaves(6);
+        let branch = merkle_branch(&leaves, 0);
+        leaves[0] = BlockHash::from_bytes([0xAB; 32]);
+        assert_eq!(root_from_branch(leaves[0], 0, &branch), merkle_root(&leaves));
+    }
+
+    #[test]
+    fn test_duplicated_tail_is_flagged() {
+        // [a, b, c] and [a, b, c, c] share a root; only the copy is mutated
+        let three = leaves(3);
+        let (root, mutated) = merkle_root_checked(&three);
+        assert!(!mutated);
+
+        let mut copied = three.clone();
+        copied.push(three[2]);
+        assert_eq!(merkle_root_checked(&copied), (root, true));
+
+        // Same trick one level up: six leaves, last pair repeated
+        let six = leaves(6);
+        let mut copied = six.clone();
+        copied.extend_from_slice(&six[4..]);
+        assert_eq!(merkle_root_checked(&copied), (merkle_root(&six), true));
+        assert!(!merkle_root_checked(&six).1);
+    }
+
+    #[test]
+    fn test_proofs_verify_against_block_root() {
+        for count in 1..=7 {
+            let txs = transactions(count);
+            let root = Block::calculate_merkle_root(&txs);
+            for (index, tx) in txs.iter().enumerate() {
+                let proof = MerkleProof::new(&txs, index).unwrap();
+                assert_eq!(proof.txid, tx.hash());
+                assert!(proof.verify(&root), "{} txs, index {}", count, index);
+                assert!(!proof.verify(&BlockHash::zero()));
+            }
+        }
+        assert!(MerkleProof::new(&transactions(3), 3).is_none());
+    }
+
+    #[test]
+    fn test_malformed_proofs_rejected() {
+        let txs = transactions(5);
+        let root = Block::calculate_merkle_root(&txs);
+        let proof = MerkleProof::new(&txs, 4).unwrap();
+
+        let out_of_range = MerkleProof { index: 5, ..proof.clone() };
+        assert!(!out_of_range.verify(&root));
+        let short = MerkleProof { branch: proof.branch[..2].to_vec(), ..proof.clone() };
+        assert!(!short.verify(&root));
+
+        // The duplicated fifth transaction of the mutated six-transaction
+        // tree leads to the same root, but the claimed shape exposes it
+        let mut mutated = txs.clone();
+        mutated.push(txs[4].clone());
+        let fake = MerkleProof::new(&mutated, 5).unwrap();
+        assert_eq!(fake.root(), root);
+        assert!(!fake.verify(&root));
+    }
+
+    #[test]
+    fn test_transaction_proof_round_trip() {
+        let txs = transactions(4);
+        let block = Block::new(1, BlockHash::zero(), txs.clone(), 1_000, 0x207fffff, 9);
+        let proof = TransactionProof {
+            header: block.header.clone(),
+            height: block.height,
+            proof: MerkleProof::for_transaction(&block, &txs[2].hash()).unwrap(),
+        };
+        assert!(proof.verify());
+
+        let bytes = encoding::serialize(&proof);
+        assert_eq!(bytes.len(), proof.encoded_len());
+        let decoded: TransactionProof = encoding::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(encoding::deserialize::<TransactionProof>(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -2,22 +2,39 @@
 // Proof-of-Work Mining System for ATMN
 
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use crate::block::{Block, BlockHeader};
 use crate::transaction::Transaction;
-use crate::consensus::{sha256d, ProofOfWork};
-use crate::types::{BlockHash, Nonce, Timestamp};
+use crate::chain_params::ChainParams;
+use crate::consensus::{bits_to_target, verify_hash_difficulty, ProofOfWork};
+use crate::difficulty::{self, DifficultySample};
+use crate::merkle;
+use crate::types::{Amount, BlockHash, Timestamp};
 use crate::error::Result;
 
+/// Bytes of the block height at the start of the coinbase script
+pub const COINBASE_HEIGHT_SIZE: usize = 8;
+
+/// Bytes of the extra nonce following the height in the coinbase script
+pub const EXTRA_NONCE_SIZE: usize = 8;
+
+/// Nonces hashed between checks of a search's stop flag
+const STOP_CHECK_INTERVAL: u32 = 1 << 16;
+
 /// Mining configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MinerConfig {
-    /// Maximum nonce to try before giving up
+    /// Highest nonce tried for each coinbase and timestamp
     pub max_nonce: u32,
     /// Thread count for parallel mining
     pub thread_count: usize,
     /// Update mining template every N milliseconds
     pub update_interval_ms: u64,
+    /// Highest coinbase extra nonce to try before giving up
+    pub max_extra_nonce: u64,
+    /// Move the timestamp up to the clock when the nonces run out
+    pub roll_timestamp: bool,
 }
 
 impl Default for MinerConfig {
@@ -26,6 +43,8 @@ impl Default for MinerConfig {
             max_nonce: u32::MAX,
             thread_count: num_cpus::get(),
             update_interval_ms: 1000,
+            max_extra_nonce: u64::MAX,
+            roll_timestamp: true,
         }
     }
 }
@@ -74,6 +93,38 @@ pub struct BlockTemplate {
     pub version: u32,
     /// Template timestamp
     pub template_time: Timestamp,
+    /// Fees of the included transactions, claimed by the coinbase
+    pub total_fees: Amount,
+}
+
+impl BlockTemplate {
+    /// Merkle branch of the coinbase, for `set_extra_nonce`
+    pub fn coinbase_branch(&self) -> Vec<BlockHash> {
+        merkle::merkle_branch(&merkle::transaction_leaves(&self.transactions), 0)
+    }
+
+    /// Write `extra_nonce` into the coinbase and update the merkle root from
+    /// the coinbase's `branch`
+    ///
+    /// Returns false if the template has no coinbase to change.
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64, branch: &[BlockHash]) -> bool {
+        match self.transactions.first_mut() {
+            Some(coinbase) if coinbase.is_coinbase() => {
+                set_coinbase_extra_nonce(coinbase, extra_nonce);
+                let leaf = BlockHash(*coinbase.hash().as_bytes());
+                self.merkle_root = merkle::root_from_branch(leaf, 0, branch);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Set the extra nonce after the height in a coinbase's script
+pub fn set_coinbase_extra_nonce(coinbase: &mut Transaction, extra_nonce: u64) {
+    let script = &mut coinbase.inputs[0].script;
+    script.truncate(COINBASE_HEIGHT_SIZE);
+    script.extend_from_slice(&extra_nonce.to_le_bytes());
 }
 
 /// Result of mining attempt
@@ -117,91 +168,32 @@ impl Miner {
     }
 
     /// Mine a block template
-    /// Returns (block, hashes_tried) if successful
+    ///
+    /// Runs through extra nonces and timestamps until a block is found or
+    /// the configured limits are reached.
     pub fn mine_block(&mut self, template: BlockTemplate) -> Result<MiningResult> {
-        let pow = ProofOfWork::new(template.difficulty_bits);
-        let target = pow.target;
-
-        // Build block header
-        let header_base = BlockHeader {
-            version: template.version,
-            prev_block_hash: template.prev_block_hash.clone(),
-            merkle_root: template.merkle_root.clone(),
-            timestamp: current_timestamp(),
-            bits: template.difficulty_bits,
-            nonce: 0,
-        };
-
-        let mut nonce: Nonce = 0;
-        let mut hashes: u64 = 0;
+        let hashes = AtomicU64::new(0);
         let start_time = SystemTime::now();
 
-        // Iterate through nonce values until valid proof-of-work found
-        while nonce < self.config.max_nonce {
-            // Create header with current nonce
-            let mut current_header = header_base.clone();
-            current_header.nonce = nonce;
-
-            // Hash the header
-            let header_bytes = serialize_block_header(&current_header)?;
-            let block_hash = sha256d(&header_bytes);
-
-            hashes += 1;
+        let block = search_template(&template, &self.config, 0, 1, &AtomicBool::new(false), &hashes);
 
-            // Check if hash meets difficulty target
-            if verify_hash_difficulty(&block_hash, &target) {
-                // Found valid proof-of-work!
-                let block = Block {
-                    header: current_header,
-                    transactions: template.transactions.clone(),
-                    height: template.height,
-                };
-
-                // Update stats
-                self.stats.blocks_found += 1;
-                self.stats.last_block_time = Some(current_timestamp());
-
-                if let Ok(elapsed) = start_time.elapsed() {
-                    let secs = elapsed.as_secs_f64();
-                    if secs > 0.0 {
-                        self.stats.hash_rate = hashes as f64 / secs;
-                    }
-                }
-
-                return Ok(MiningResult {
-                    block: Some(block),
-                    hashes_attempted: (hashes & 0xFFFFFFFF) as u32,
-                    success: true,
-                });
-            }
-
-            nonce += 1;
-
-            // Periodically update hash rate
-            if nonce % 1_000_000 == 0 {
-                if let Ok(elapsed) = start_time.elapsed() {
-                    let secs = elapsed.as_secs_f64();
-                    if secs > 0.0 {
-                        self.stats.hash_rate = hashes as f64 / secs;
-                    }
-                }
-            }
-        }
-
-        // Max nonce reached without finding valid block
+        let hashes = hashes.load(Ordering::Relaxed);
         self.stats.hashes_computed += hashes;
-
         if let Ok(elapsed) = start_time.elapsed() {
             let secs = elapsed.as_secs_f64();
             if secs > 0.0 {
                 self.stats.hash_rate = hashes as f64 / secs;
             }
         }
+        if block.is_some() {
+            self.stats.blocks_found += 1;
+            self.stats.last_block_time = Some(current_timestamp());
+        }
 
         Ok(MiningResult {
-            block: None,
+            success: block.is_some(),
+            block,
             hashes_attempted: (hashes & 0xFFFFFFFF) as u32,
-            success: false,
         })
     }
 
@@ -210,63 +202,28 @@ impl Miner {
         let pow = ProofOfWork::new(difficulty_bits);
         let target = pow.target;
 
-        // Serialize header and hash
-        let header_bytes = serialize_block_header(&block.header)?;
-        let block_hash = sha256d(&header_bytes);
+        let block_hash = block.header.hash();
 
         Ok(verify_hash_difficulty(&block_hash, &target))
     }
 
-    /// Get difficulty adjustment for next block
+    /// Get difficulty bits for the block after the last of `blocks`
+    ///
+    /// `blocks` is the recent chain up to the tip, oldest first; it must reach
+    /// back to `difficulty::window_start` for the next height.
     pub fn difficulty_for_next_block(
+        params: &ChainParams,
         blocks: &[Block],
-        current_bits: u32,
     ) -> Result<u32> {
-        if blocks.len() < 2 {
-            return Ok(current_bits);
-        }
-
-        // Check if this is a difficulty adjustment block
-        let last_block = &blocks[blocks.len() - 1];
-        if last_block.height % 2016 != 0 {
-            return Ok(current_bits);
-        }
-
-        // Get first and last block of adjustment period
-        let first_index = if blocks.len() >= 2016 {
-            blocks.len() - 2016
-        } else {
-            0
-        };
-
-        let first_block = &blocks[first_index];
-        let last_block = &blocks[blocks.len() - 1];
-
-        // Calculate time span
-        let time_span = if last_block.header.timestamp > first_block.header.timestamp {
-            last_block.header.timestamp - first_block.header.timestamp
-        } else {
-            1 // Prevent division by zero
-        };
-
-        // Target timespan is 2 weeks (2016 blocks * 10 minutes per block)
-        const TARGET_TIMESPAN: u32 = 2 * 7 * 24 * 60 * 60; // 1,209,600 seconds
-        const MIN_ADJUSTMENT: u32 = TARGET_TIMESPAN / 4;
-        const MAX_ADJUSTMENT: u32 = TARGET_TIMESPAN * 4;
-
-        // Adjust difficulty
-        let adjusted_timespan = if time_span < MIN_ADJUSTMENT {
-            MIN_ADJUSTMENT
-        } else if time_span > MAX_ADJUSTMENT {
-            MAX_ADJUSTMENT
-        } else {
-            time_span
-        };
-
-        // new_difficulty = old_difficulty * time_span / target_timespan
-        let new_bits = calculate_new_difficulty(current_bits, adjusted_timespan)?;
-
-        Ok(new_bits)
+        let height = blocks.last().map_or(0, |tip| tip.height + 1);
+        let start = difficulty::window_start(params, height);
+        let samples: Vec<DifficultySample> = blocks
+            .iter()
+            .filter(|block| block.height >= start)
+            .map(|block| DifficultySample::from(&block.header))
+            .collect();
+
+        Ok(difficulty::next_work_required(params, height, &samples))
     }
 }
 
@@ -276,108 +233,90 @@ impl Default for Miner {
     }
 }
 
-/// Serialize block header for hashing
-fn serialize_block_header(header: &BlockHeader) -> Result<Vec<u8>> {
-    let mut bytes = Vec::new();
-
-    // Version (4 bytes, little-endian)
-    bytes.extend_from_slice(&header.version.to_le_bytes());
-
-    // Previous block hash (32 bytes)
-    bytes.extend_from_slice(&header.prev_block_hash.0);
-
-    // Merkle root (32 bytes)
-    bytes.extend_from_slice(&header.merkle_root.0);
-
-    // Timestamp (8 bytes, little-endian)
-    bytes.extend_from_slice(&header.timestamp.to_le_bytes());
-
-    // Difficulty bits (4 bytes, little-endian)
-    bytes.extend_from_slice(&header.bits.to_le_bytes());
-
-    // Nonce (4 bytes, little-endian)
-    bytes.extend_from_slice(&header.nonce.to_le_bytes());
-
-    Ok(bytes)
-}
-
-/// Check if a hash meets the target difficulty
-fn verify_hash_difficulty(hash: &BlockHash, target: &[u8; 32]) -> bool {
-    // Hash should be less than target
-    // Hashes are compared as little-endian numbers
-    hash.0 <= *target
-}
-
-/// Calculate new difficulty from timespan
-fn calculate_new_difficulty(bits: u32, timespan: u32) -> Result<u32> {
-    const TARGET_TIMESPAN: u32 = 2 * 7 * 24 * 60 * 60; // 1,209,600 seconds
-
-    // Parse difficulty from bits
-    let size = (bits >> 24) as usize;
-    if size == 0 || size > 32 {
-        return Ok(bits);
+/// Search `template` for a block until one is found, the work runs out or
+/// `stop` is set
+///
+/// Each pass hashes nonces `0..=max_nonce` from the header's SHA-256
+/// midstate. When a pass fails the timestamp
+/// moves up to the clock if rolling is on and the clock has advanced, which
+/// keeps it past the median-time-past and inside the future limit.
+/// Otherwise the coinbase gets the extra nonce `extra_nonce_step` past the
+/// last one, starting from `first_extra_nonce`, so searches with distinct
+/// starts and a shared step never overlap. Finding a block sets `stop` to
+/// halt the others.
+pub(crate) fn search_template(
+    template: &BlockTemplate,
+    config: &MinerConfig,
+    first_extra_nonce: u64,
+    extra_nonce_step: u64,
+    stop: &AtomicBool,
+    hashes: &AtomicU64,
+) -> Option<Block> {
+    let target = bits_to_target(template.difficulty_bits);
+    let mut template = template.clone();
+    let branch = template.coinbase_branch();
+
+    // Extra nonce 0 is the template as built
+    let mut extra_nonce = first_extra_nonce;
+    if extra_nonce > config.max_extra_nonce {
+        return None;
     }
-
-    let mut target = [0u8; 32];
-    let mantissa = (bits & 0xffffff) as usize;
-
-    if size <= 3 {
-        target[0] = (mantissa >> 8) as u8;
-        target[1] = (mantissa & 0xff) as u8;
-    } else {
-        for i in 0..3 {
-            target[32 - size + 3 - i] = ((mantissa >> (8 * i)) & 0xff) as u8;
-        }
+    if extra_nonce != 0 && !template.set_extra_nonce(extra_nonce, &branch) {
+        return None;
     }
 
-    // Multiply target by timespan / target_timespan
-    // new_target = old_target * timespan / target_timespan
-    let ratio = timespan as f64 / TARGET_TIMESPAN as f64;
-    
-    // Convert target to integer
-    let mut target_int = 0u128;
-    for &byte in target.iter() {
-        target_int = (target_int << 8) | (byte as u128);
-    }
-
-    // Apply ratio
-    let new_target_int = (target_int as f64 * ratio) as u128;
-
-    // Convert back to bytes
-    let mut new_target = [0u8; 32];
-    let mut value = new_target_int;
-    for i in (0..32).rev() {
-        new_target[i] = (value & 0xff) as u8;
-        value >>= 8;
-    }
+    let mut header = BlockHeader {
+        version: template.version,
+        prev_block_hash: template.prev_block_hash,
+        merkle_root: template.merkle_root,
+        timestamp: template.template_time,
+        bits: template.difficulty_bits,
+        nonce: 0,
+    };
+    let mut midstate = header.midstate();
+
+    loop {
+        let mut local_hashes: u64 = 0;
+        for nonce in 0..=config.max_nonce {
+            if nonce % STOP_CHECK_INTERVAL == 0 {
+                hashes.fetch_add(local_hashes, Ordering::Relaxed);
+                local_hashes = 0;
+                if stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+            }
 
-    // Convert target back to bits
-    let mut new_size = 32;
-    for i in (0..32).rev() {
-        if new_target[i] != 0 {
-            new_size = i + 1;
-            break;
+            local_hashes += 1;
+            if verify_hash_difficulty(&midstate.hash(nonce), &target) {
+                hashes.fetch_add(local_hashes, Ordering::Relaxed);
+                header.nonce = nonce;
+                stop.store(true, Ordering::Relaxed);
+                return Some(Block {
+                    header,
+                    transactions: template.transactions,
+                    height: template.height,
+                });
+            }
         }
-    }
+        hashes.fetch_add(local_hashes, Ordering::Relaxed);
 
-    if new_size == 0 {
-        return Ok(0);
-    }
-
-    let mut new_bits = if new_size <= 3 {
-        (new_target[new_size - 1] as u32) << 8
-    } else {
-        ((new_target[new_size - 1] as u32) << 16)
-            | ((new_target[new_size - 2] as u32) << 8)
-            | (new_target[new_size - 3] as u32)
-    };
+        let now = current_timestamp();
+        if config.roll_timestamp && now > header.timestamp {
+            header.timestamp = now;
+            midstate.set_timestamp(now);
+            continue;
+        }
 
-    if (new_bits & 0x00800000) != 0 {
-        new_bits >>= 8;
-        new_bits += 1;
+        extra_nonce = match extra_nonce.checked_add(extra_nonce_step) {
+            Some(next) if next <= config.max_extra_nonce => next,
+            _ => return None,
+        };
+        if !template.set_extra_nonce(extra_nonce, &branch) {
+            return None;
+        }
+        header.merkle_root = template.merkle_root;
+        midstate = header.midstate();
     }
-
-    Ok(((new_size as u32) << 24) | (new_bits & 0x00ffffff))
 }
 
 /// Get current timestamp
@@ -391,6 +330,13 @@ fn current_timestamp() -> Timestamp {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::block_assembler::tests::coinbase_only_template;
+    use crate::consensus::sha256d;
+
+    fn extra_nonce(block: &Block) -> u64 {
+        let script = &block.transactions[0].inputs[0].script;
+        u64::from_le_bytes(script[COINBASE_HEIGHT_SIZE..].try_into().unwrap())
+    }
 
     #[test]
     fn test_miner_creation() {
@@ -405,12 +351,45 @@ mod tests {
             max_nonce: 100_000,
             thread_count: 4,
             update_interval_ms: 500,
+            ..MinerConfig::default()
         };
         let miner = Miner::with_config(config);
         assert_eq!(miner.config.max_nonce, 100_000);
         assert_eq!(miner.config.thread_count, 4);
     }
 
+    #[test]
+    fn test_extra_nonce_extends_search() {
+        // One nonce per pass against a 1-in-256 target leaves the work to the extra nonce
+        let config = MinerConfig { max_nonce: 0, roll_timestamp: false, ..MinerConfig::default() };
+        let result = Miner::with_config(config.clone()).mine_block(coinbase_only_template(0x2000ffff)).unwrap();
+        let block = result.block.unwrap();
+        let found_at = extra_nonce(&block);
+        assert!(found_at > 0);
+        assert_eq!(result.hashes_attempted as u64, found_at + 1);
+        assert_eq!(block.header.merkle_root, Block::calculate_merkle_root(&block.transactions));
+        assert!(Miner::verify_block_pow(&block, block.header.bits).unwrap());
+
+        // Stopping one extra nonce short finds nothing
+        let config = MinerConfig { max_extra_nonce: found_at - 1, ..config };
+        let result = Miner::with_config(config).mine_block(coinbase_only_template(0x2000ffff)).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.hashes_attempted as u64, found_at);
+    }
+
+    #[test]
+    fn test_timestamp_rolls_when_nonces_run_out() {
+        // Without extra nonces a stale timestamp allows a single pass
+        let config = MinerConfig { max_nonce: 0, max_extra_nonce: 0, roll_timestamp: false, ..MinerConfig::default() };
+        let result = Miner::with_config(config.clone()).mine_block(coinbase_only_template(0x1d00ffff)).unwrap();
+        assert_eq!(result.hashes_attempted, 1);
+
+        // Rolling it up to the clock buys at least one more
+        let config = MinerConfig { roll_timestamp: true, ..config };
+        let result = Miner::with_config(config).mine_block(coinbase_only_template(0x1d00ffff)).unwrap();
+        assert!(result.hashes_attempted >= 2);
+    }
+
     #[test]
     fn test_current_timestamp() {
         let ts = current_timestamp();
@@ -429,8 +408,9 @@ mod tests {
 
             nonce: 0,
         };
-        let bytes = serialize_block_header(&header).unwrap();
+        let bytes = header.serialize();
         assert_eq!(bytes.len(), 80); // 4+32+32+4+4+4 = 80 bytes
+        assert_eq!(header.hash(), sha256d(&bytes));
     }
 }
 
@@ -473,45 +453,7 @@ pub fn create_coinbase_transaction(
 
 /// Calculate merkle root from transactions
 pub fn calculate_merkle_root(transactions: &[Transaction]) -> Result<BlockHash> {
-    use crate::error::Error;
-    
-    if transactions.is_empty() {
-        return Ok(BlockHash::zero());
-    }
-    
-    // Serialize and hash each transaction
-    let mut hashes: Vec<BlockHash> = transactions
-        .iter()
-        .map(|tx| {
-            let tx_bytes = bincode::serialize(tx)
-                .map_err(|_| Error::SerializationError)?;
-            Ok(sha256d(&tx_bytes))
-        })
-        .collect::<Result<Vec<BlockHash>>>()?;
-    
-    // Build merkle tree
-    while hashes.len() > 1 {
-        let mut next_level = Vec::new();
-        
-        for i in (0..hashes.len()).step_by(2) {
-            let left = &hashes[i];
-            let right = if i + 1 < hashes.len() {
-                &hashes[i + 1]
-            } else {
-                left // Duplicate last hash if odd number
-            };
-            
-            // Concatenate and hash
-            let mut combined = Vec::new();
-            combined.extend_from_slice(&left.0);
-            combined.extend_from_slice(&right.0);
-            next_level.push(sha256d(&combined));
-        }
-        
-        hashes = next_level;
-    }
-    
-    Ok(hashes[0].clone())
+    Ok(merkle::merkle_root(&merkle::transaction_leaves(transactions)))
 }
 
 #[cfg(test)]
@@ -0,0 +1,263 @@
+// atmn-core/src/mining_job.rs
+// Background mining jobs: cancellable, tip-aware, with progress reports
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::block::Block;
+use crate::miner::{search_template, BlockTemplate, MinerConfig};
+use crate::types::BlockHash;
+
+/// How often the job's supervisor checks on its search threads
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Why a job without a block stopped
+const RUNNING: u8 = 0;
+const CANCELLED: u8 = 1;
+const TIP_CHANGED: u8 = 2;
+
+/// How a mining job ended
+#[derive(Debug, Clone)]
+pub enum JobOutcome {
+    /// A block meeting the template's target
+    Found(Block),
+    /// Stopped by `cancel`
+    Cancelled,
+    /// Stopped because the template no longer extends the tip
+    TipChanged,
+    /// Every extra nonce and timestamp allowed by the config was tried
+    Exhausted,
+}
+
+/// Work done by a job so far
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiningProgress {
+    /// Hashes computed across all threads
+    pub hashes: u64,
+    /// Hashes per second since the job started
+    pub hash_rate: f64,
+    /// Time since the job started
+    pub elapsed: Duration,
+}
+
+/// State shared by a job's threads and its controls
+struct JobShared {
+    /// Parent of the template being mined
+    prev_hash: BlockHash,
+    started: Instant,
+    stop: AtomicBool,
+    reason: AtomicU8,
+    hashes: AtomicU64,
+}
+
+impl JobShared {
+    fn halt(&self, reason: u8) {
+        // The first reason wins; a found block sets `stop` without one
+        let _ = self.reason.compare_exchange(RUNNING, reason, Ordering::AcqRel, Ordering::Acquire);
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+/// Cloneable handle to stop a job or read its progress from any thread
+#[derive(Clone)]
+pub struct JobControl {
+    shared: Arc<JobShared>,
+}
+
+impl JobControl {
+    /// Stop the job; its threads exit at their next stop check
+    pub fn cancel(&self) {
+        self.shared.halt(CANCELLED);
+    }
+
+    /// Report the current chain tip, stopping the job if its template is
+    /// built on another block
+    ///
+    /// Returns true if the job was stopped.
+    pub fn tip_changed(&self, tip: &BlockHash) -> bool {
+        if *tip == self.shared.prev_hash {
+            return false;
+        }
+        self.shared.halt(TIP_CHANGED);
+        true
+    }
+
+    /// Hashes and hash rate so far
+    pub fn progress(&self) -> MiningProgress {
+        let hashes = self.shared.hashes.load(Ordering::Relaxed);
+        let elapsed = self.shared.started.elapsed();
+        let secs = elapsed.as_secs_f64();
+        MiningProgress {
+            hashes,
+            hash_rate: if secs > 0.0 { hashes as f64 / secs } else { 0.0 },
+            elapsed,
+        }
+    }
+}
+
+/// A block template being mined on background threads
+///
+/// The job runs until it finds a block, exhausts the work allowed by its
+/// `MinerConfig`, is cancelled or learns that the tip moved. Progress is
+/// sent every `update_interval_ms` while it runs.
+pub struct MiningJob {
+    control: JobControl,
+    progress: Receiver<MiningProgress>,
+    worker: JoinHandle<JobOutcome>,
+}
+
+impl MiningJob {
+    /// Start mining `template` on `config.thread_count` threads
+    ///
+    /// Each thread searches its own coinbase extra nonces: thread `i` takes
+    /// `i`, `i + threads`, `i + 2 * threads` and so on.
+    pub fn start(template: BlockTemplate, config: MinerConfig) -> Self {
+        let shared = Arc::new(JobShared {
+            prev_hash: template.prev_block_hash,
+            started: Instant::now(),
+            stop: AtomicBool::new(false),
+            reason: AtomicU8::new(RUNNING),
+            hashes: AtomicU64::new(0),
+        });
+        let control = JobControl { shared };
+        let (sender, progress) = mpsc::channel();
+
+        let worker_control = control.clone();
+        let worker = thread::spawn(move || run(template, config, worker_control, sender));
+
+        MiningJob { control, progress, worker }
+    }
+
+    /// Handle for stopping the job from elsewhere
+    pub fn control(&self) -> JobControl {
+        self.control.clone()
+    }
+
+    /// See `JobControl::cancel`
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    /// See `JobControl::tip_changed`
+    pub fn tip_changed(&self, tip: &BlockHash) -> bool {
+        self.control.tip_changed(tip)
+    }
+
+    /// Hashes and hash rate so far
+    pub fn progress(&self) -> MiningProgress {
+        self.control.progress()
+    }
+
+    /// Periodic progress reports; the stream ends when the job does
+    pub fn progress_updates(&self) -> &Receiver<MiningProgress> {
+        &self.progress
+    }
+
+    /// Whether the job has stopped, so `wait` returns at once
+    pub fn is_finished(&self) -> bool {
+        self.worker.is_finished()
+    }
+
+    /// Block until the job ends
+    pub fn wait(self) -> JobOutcome {
+        // A panicked search found nothing
+        self.worker.join().unwrap_or(JobOutcome::Exhausted)
+    }
+}
+
+/// Supervise the search threads, reporting progress until they all stop
+fn run(template: BlockTemplate, config: MinerConfig, control: JobControl, sender: Sender<MiningProgress>) -> JobOutcome {
+    let shared = &control.shared;
+    let threads = config.thread_count.max(1);
+    let interval = Duration::from_millis(config.update_interval_ms.max(1));
+    let running = AtomicUsize::new(threads);
+    let found = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for thread_id in 0..threads {
+            let (template, config, running, found) = (&template, &config, &running, &found);
+            scope.spawn(move || {
+                let block = search_template(template, config, thread_id as u64, threads as u64, &shared.stop, &shared.hashes);
+                if let Some(block) = block {
+                    log::info!("Thread {} found valid block at nonce {}", thread_id, block.header.nonce);
+                    found.lock().unwrap().get_or_insert(block);
+                }
+                running.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+
+        let mut next_report = shared.started + interval;
+        while running.load(Ordering::Acquire) > 0 {
+            thread::sleep(POLL_INTERVAL);
+            if Instant::now() >= next_report {
+                next_report += interval;
+                // Nobody listening is fine
+                let _ = sender.send(control.progress());
+            }
+        }
+    });
+
+    match found.into_inner().unwrap() {
+        Some(block) => JobOutcome::Found(block),
+        None => match shared.reason.load(Ordering::Acquire) {
+            CANCELLED => JobOutcome::Cancelled,
+            TIP_CHANGED => JobOutcome::TipChanged,
+            _ => JobOutcome::Exhausted,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_assembler::tests::coinbase_only_template;
+    use crate::miner::Miner;
+
+    fn config() -> MinerConfig {
+        MinerConfig { thread_count: 2, update_interval_ms: 10, ..MinerConfig::default() }
+    }
+
+    #[test]
+    fn test_job_finds_block() {
+        let job = MiningJob::start(coinbase_only_template(0x207fffff), config());
+        match job.wait() {
+            JobOutcome::Found(block) => assert!(Miner::verify_block_pow(&block, block.header.bits).unwrap()),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_job_reports_progress_until_cancelled() {
+        // Mainnet difficulty: nothing will be found
+        let job = MiningJob::start(coinbase_only_template(0x1d00ffff), config());
+        let progress = job
+            .progress_updates()
+            .iter()
+            .find(|progress| progress.hashes > 0)
+            .unwrap();
+        assert!(progress.hash_rate > 0.0);
+
+        job.control().cancel();
+        // The stream ends with the job
+        for _ in job.progress_updates() {}
+        assert!(matches!(job.wait(), JobOutcome::Cancelled));
+    }
+
+    #[test]
+    fn test_job_stops_on_new_tip() {
+        let job = MiningJob::start(coinbase_only_template(0x1d00ffff), config());
+        assert!(!job.tip_changed(&BlockHash::zero()));
+        assert!(job.tip_changed(&BlockHash::from_bytes([4u8; 32])));
+        assert!(matches!(job.wait(), JobOutcome::TipChanged));
+    }
+
+    #[test]
+    fn test_job_exhausts_limited_work() {
+        let config = MinerConfig { max_nonce: 0, max_extra_nonce: 0, roll_timestamp: false, ..config() };
+        let job = MiningJob::start(coinbase_only_template(0x1d00ffff), config);
+        assert!(matches!(job.wait(), JobOutcome::Exhausted));
+    }
+}
@@ -1,17 +1,89 @@
 // atmn-core/src/network.rs
 // P2P networking layer
 
-use crate::error::Result;
+use crate::block::Block;
+use crate::chain_params::ChainParams;
+use crate::consensus::sha256d;
+use crate::encoding;
+use crate::error::{Error, Result};
+use crate::transaction::Transaction;
+
+/// Frame header: magic (4) | command (1) | payload length (u32 LE) | checksum (4)
+pub const MESSAGE_HEADER_SIZE: usize = 13;
+
+/// Largest payload accepted from a peer
+pub const MAX_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+const CMD_BLOCK: u8 = 1;
+const CMD_TRANSACTION: u8 = 2;
+
+/// Messages exchanged between peers
+///
+/// Payloads use the versioned consensus encoding, so peers running other
+/// implementations see exactly the bytes that are hashed.
+#[derive(Debug, Clone)]
+pub enum NetworkMessage {
+    Block(Block),
+    Transaction(Transaction),
+}
+
+impl NetworkMessage {
+    /// Frame the message for the network identified by `magic`
+    pub fn to_bytes(&self, magic: [u8; 4]) -> Vec<u8> {
+        let (command, payload) = match self {
+            NetworkMessage::Block(block) => (CMD_BLOCK, encoding::serialize_versioned(block)),
+            NetworkMessage::Transaction(tx) => (CMD_TRANSACTION, encoding::serialize_versioned(tx)),
+        };
+
+        let mut bytes = Vec::with_capacity(MESSAGE_HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(&magic);
+        bytes.push(command);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&sha256d(&payload).0[..4]);
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Parse a complete frame, checking magic, length and checksum
+    pub fn from_bytes(magic: [u8; 4], bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < MESSAGE_HEADER_SIZE {
+            return Err(Error::NetworkError("Truncated message header".to_string()));
+        }
+        if bytes[0..4] != magic {
+            return Err(Error::NetworkError("Wrong network magic".to_string()));
+        }
+
+        let command = bytes[4];
+        let len = u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) as usize;
+        if len > MAX_PAYLOAD_SIZE || bytes.len() - MESSAGE_HEADER_SIZE != len {
+            return Err(Error::NetworkError("Invalid payload length".to_string()));
+        }
+
+        let payload = &bytes[MESSAGE_HEADER_SIZE..];
+        if sha256d(payload).0[..4] != bytes[9..13] {
+            return Err(Error::NetworkError("Payload checksum mismatch".to_string()));
+        }
+
+        match command {
+            CMD_BLOCK => Ok(NetworkMessage::Block(encoding::deserialize_versioned(payload)?)),
+            CMD_TRANSACTION => Ok(NetworkMessage::Transaction(encoding::deserialize_versioned(payload)?)),
+            other => Err(Error::NetworkError(format!("Unknown command {}", other))),
+        }
+    }
+}
 
 /// P2P Network Node
 pub struct Node {
+    magic: [u8; 4],
     // TODO: Add network configuration
 }
 
 impl Node {
     pub fn new(bind_addr: &str, bind_port: u16) -> Result<Self> {
         // TODO: Initialize P2P node
-        Ok(Node {})
+        Ok(Node {
+            magic: ChainParams::mainnet().magic_bytes,
+        })
     }
 
     pub fn connect(&self, peer_addr: &str) -> Result<()> {
@@ -19,12 +91,14 @@ impl Node {
         Ok(())
     }
 
-    pub fn broadcast_block(&self, _block: &crate::block::Block) -> Result<()> {
+    pub fn broadcast_block(&self, block: &Block) -> Result<()> {
+        let _frame = NetworkMessage::Block(block.clone()).to_bytes(self.magic);
         // TODO: Broadcast block to peers
         Ok(())
     }
 
-    pub fn broadcast_transaction(&self, _tx: &crate::transaction::Transaction) -> Result<()> {
+    pub fn broadcast_transaction(&self, tx: &Transaction) -> Result<()> {
+        let _frame = NetworkMessage::Transaction(tx.clone()).to_bytes(self.magic);
         // TODO: Broadcast transaction to peers
         Ok(())
     }
@@ -61,4 +135,30 @@ mod tests {
     fn test_network_creation() {
         let _network = P2PNetwork::new();
     }
+
+    #[test]
+    fn test_message_round_trip() {
+        let magic = ChainParams::mainnet().magic_bytes;
+        let coinbase = crate::block::test_coinbase(5, 50);
+        let block = Block::new(1, crate::types::BlockHash::zero(), vec![coinbase], 1704067200, 0x1d00ffff, 5);
+
+        let bytes = NetworkMessage::Block(block.clone()).to_bytes(magic);
+        match NetworkMessage::from_bytes(magic, &bytes).unwrap() {
+            NetworkMessage::Block(decoded) => assert_eq!(decoded.hash(), block.hash()),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_message_rejects_corruption() {
+        let magic = ChainParams::mainnet().magic_bytes;
+        let tx = crate::block::test_coinbase(5, 50);
+        let mut bytes = NetworkMessage::Transaction(tx).to_bytes(magic);
+
+        assert!(NetworkMessage::from_bytes(ChainParams::testnet().magic_bytes, &bytes).is_err());
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(NetworkMessage::from_bytes(magic, &bytes).is_err());
+    }
 }
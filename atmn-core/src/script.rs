@@ -0,0 +1,480 @@
+// atmn-core/src/script.rs
+// Script interpreter and standard script templates
+//
+// Outputs are locked with pay-to-pubkey-hash scripts:
+//   script_pubkey: OP_DUP OP_HASH160 <20-byte pubkey hash> OP_EQUALVERIFY OP_CHECKSIG
+//   script_sig:    <DER signature + sighash byte> <public key>
+//
+// The interpreter runs the (push-only) script_sig, then the script_pubkey on the
+// resulting stack. Spending succeeds if it finishes with a true value on top.
+
+use ripemd::Ripemd160;
+use secp256k1::{ecdsa, Message, PublicKey, SECP256K1};
+use sha2::{Digest, Sha256};
+use crate::chain_params::ChainParams;
+use crate::consensus::{sha256, sha256d};
+use crate::error::{Error, Result};
+use crate::sighash;
+use crate::transaction::Transaction;
+use crate::types::Address;
+
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_PUSHDATA4: u8 = 0x4e;
+pub const OP_1NEGATE: u8 = 0x4f;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_NOP: u8 = 0x61;
+pub const OP_VERIFY: u8 = 0x69;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_DROP: u8 = 0x75;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_SHA256: u8 = 0xa8;
+pub const OP_HASH160: u8 = 0xa9;
+pub const OP_HASH256: u8 = 0xaa;
+pub const OP_CHECKSIG: u8 = 0xac;
+pub const OP_CHECKSIGVERIFY: u8 = 0xad;
+
+/// Maximum script size in bytes
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Maximum size of a single stack element
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// Maximum number of stack elements
+pub const MAX_STACK_SIZE: usize = 1000;
+
+/// Length of a P2PKH script_pubkey
+pub const P2PKH_SCRIPT_SIZE: usize = 25;
+
+/// Prefix of a pay-to-pubkey-hash address
+pub const ADDRESS_PREFIX: &str = "ATMN_";
+
+/// RIPEMD-160 of SHA-256, used for public key hashes
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    let mut hasher = Ripemd160::new();
+    hasher.update(Sha256::digest(data));
+    hasher.finalize().into()
+}
+
+/// Build a P2PKH locking script for a public key hash
+pub fn p2pkh_script_pubkey(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+    let mut script = Vec::with_capacity(P2PKH_SCRIPT_SIZE);
+    script.push(OP_DUP);
+    script.push(OP_HASH160);
+    push_data(&mut script, pubkey_hash);
+    script.push(OP_EQUALVERIFY);
+    script.push(OP_CHECKSIG);
+    script
+}
+
+/// Build the unlocking script for a P2PKH output
+pub fn p2pkh_script_sig(signature: &[u8], pubkey: &[u8]) -> Vec<u8> {
+    let mut script = Vec::with_capacity(signature.len() + pubkey.len() + 2);
+    push_data(&mut script, signature);
+    push_data(&mut script, pubkey);
+    script
+}
+
+/// Extract the public key hash from a P2PKH script_pubkey
+pub fn extract_p2pkh(script: &[u8]) -> Option<[u8; 20]> {
+    if script.len() == P2PKH_SCRIPT_SIZE
+        && script[0] == OP_DUP
+        && script[1] == OP_HASH160
+        && script[2] == 20
+        && script[23] == OP_EQUALVERIFY
+        && script[24] == OP_CHECKSIG
+    {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&script[3..23]);
+        Some(hash)
+    } else {
+        None
+    }
+}
+
+/// Format a public key hash as an address (`ATMN_<40 hex>`)
+pub fn pubkey_hash_to_address(pubkey_hash: &[u8; 20]) -> String {
+    format!("{}{}", ADDRESS_PREFIX, hex::encode(pubkey_hash))
+}
+
+/// Parse an `ATMN_<40 hex>` address into its public key hash
+pub fn address_to_pubkey_hash(address: &str) -> Option<[u8; 20]> {
+    let hex_part = address.strip_prefix(ADDRESS_PREFIX)?;
+    let bytes = hex::decode(hex_part).ok()?;
+    bytes.try_into().ok()
+}
+
+/// Locking script paying to an address on `params`' network
+///
+/// Legacy `ATMN_<40 hex>` addresses are still accepted.
+pub fn address_to_script_pubkey(address: &str, params: &ChainParams) -> Option<Vec<u8>> {
+    match Address::parse(address, params) {
+        Ok(address) => Some(address.script_pubkey()),
+        Err(_) => address_to_pubkey_hash(address).map(|hash| p2pkh_script_pubkey(&hash)),
+    }
+}
+
+/// Address a script pays to on `params`' network, if it is a standard template
+pub fn script_pubkey_to_address(script: &[u8], params: &ChainParams) -> Option<String> {
+    Address::from_script_pubkey(script).map(|address| address.encode(params))
+}
+
+/// Append a minimal data push
+pub fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    match data.len() {
+        len @ 0..=0x4b => script.push(len as u8),
+        len @ 0x4c..=0xff => {
+            script.push(OP_PUSHDATA1);
+            script.push(len as u8);
+        }
+        len @ 0x100..=0xffff => {
+            script.push(OP_PUSHDATA2);
+            script.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        len => {
+            script.push(OP_PUSHDATA4);
+            script.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+    }
+    script.extend_from_slice(data);
+}
+
+/// Checks signatures found by OP_CHECKSIG
+pub trait SignatureChecker {
+    /// `signature` is DER with a trailing sighash byte; `script_code` is the
+    /// script being executed
+    fn check_sig(&self, signature: &[u8], pubkey: &[u8], script_code: &[u8]) -> bool;
+}
+
+/// Verifies signatures over a specific transaction input on one network
+pub struct TransactionSignatureChecker<'a> {
+    tx: &'a Transaction,
+    input_index: usize,
+    network_id: u32,
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+    pub fn new(tx: &'a Transaction, input_index: usize, network_id: u32) -> Self {
+        Self { tx, input_index, network_id }
+    }
+}
+
+impl SignatureChecker for TransactionSignatureChecker<'_> {
+    fn check_sig(&self, signature: &[u8], pubkey: &[u8], script_code: &[u8]) -> bool {
+        let Some((&hash_type, der)) = signature.split_last() else {
+            return false;
+        };
+        match sighash::signature_hash(self.tx, self.input_index, script_code, hash_type, self.network_id) {
+            Ok(digest) => verify_ecdsa(der, pubkey, &digest),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Verify a DER signature, rejecting high-S (malleable) encodings
+pub fn verify_ecdsa(der: &[u8], pubkey: &[u8], digest: &[u8; 32]) -> bool {
+    let Ok(sig) = ecdsa::Signature::from_der(der) else {
+        return false;
+    };
+    let mut normalized = sig;
+    normalized.normalize_s();
+    if normalized != sig {
+        return false;
+    }
+    let Ok(key) = PublicKey::from_slice(pubkey) else {
+        return false;
+    };
+
+    let msg = Message::from_digest(*digest);
+    SECP256K1.verify_ecdsa(&msg, &sig, &key).is_ok()
+}
+
+/// Run `script_sig` followed by `script_pubkey`
+pub fn verify_script(script_sig: &[u8], script_pubkey: &[u8], checker: &dyn SignatureChecker) -> Result<()> {
+    if !is_push_only(script_sig) {
+        return Err(Error::ScriptError("script_sig is not push-only".to_string()));
+    }
+
+    let mut stack = Vec::new();
+    execute(script_sig, &mut stack, checker)?;
+    execute(script_pubkey, &mut stack, checker)?;
+
+    match stack.last() {
+        Some(top) if cast_to_bool(top) => Ok(()),
+        _ => Err(Error::ScriptError("script evaluated to false".to_string())),
+    }
+}
+
+/// True if the script only pushes data
+pub fn is_push_only(script: &[u8]) -> bool {
+    let mut pc = 0;
+    while pc < script.len() {
+        let opcode = script[pc];
+        pc += 1;
+        if opcode > OP_16 {
+            return false;
+        }
+        match read_push(script, &mut pc, opcode) {
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// Decode the data pushed by `opcode`, advancing `pc` past it
+fn read_push<'a>(script: &'a [u8], pc: &mut usize, opcode: u8) -> Result<Option<&'a [u8]>> {
+    let len = match opcode {
+        0x01..=0x4b => opcode as usize,
+        OP_PUSHDATA1 => read_le(script, pc, 1)?,
+        OP_PUSHDATA2 => read_le(script, pc, 2)?,
+        OP_PUSHDATA4 => read_le(script, pc, 4)?,
+        _ => return Ok(None),
+    };
+    if *pc + len > script.len() {
+        return Err(Error::ScriptError("push past end of script".to_string()));
+    }
+    let data = &script[*pc..*pc + len];
+    *pc += len;
+    Ok(Some(data))
+}
+
+fn read_le(script: &[u8], pc: &mut usize, width: usize) -> Result<usize> {
+    if *pc + width > script.len() {
+        return Err(Error::ScriptError("truncated push length".to_string()));
+    }
+    let len = script[*pc..*pc + width]
+        .iter()
+        .rev()
+        .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    *pc += width;
+    Ok(len)
+}
+
+fn execute(script: &[u8], stack: &mut Vec<Vec<u8>>, checker: &dyn SignatureChecker) -> Result<()> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(Error::ScriptError("script too large".to_string()));
+    }
+
+    let mut pc = 0;
+    while pc < script.len() {
+        let opcode = script[pc];
+        pc += 1;
+
+        if let Some(data) = read_push(script, &mut pc, opcode)? {
+            if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(Error::ScriptError("push exceeds element size".to_string()));
+            }
+            stack.push(data.to_vec());
+        } else {
+            match opcode {
+                OP_0 => stack.push(Vec::new()),
+                OP_1NEGATE => stack.push(vec![0x81]),
+                OP_1..=OP_16 => stack.push(vec![opcode - OP_1 + 1]),
+                OP_NOP => {}
+                OP_VERIFY => {
+                    if !cast_to_bool(&pop(stack)?) {
+                        return Err(Error::ScriptError("OP_VERIFY failed".to_string()));
+                    }
+                }
+                OP_RETURN => return Err(Error::ScriptError("OP_RETURN".to_string())),
+                OP_DROP => {
+                    pop(stack)?;
+                }
+                OP_DUP => {
+                    let top = stack.last().cloned()
+                        .ok_or_else(|| Error::ScriptError("stack underflow".to_string()))?;
+                    stack.push(top);
+                }
+                OP_EQUAL | OP_EQUALVERIFY => {
+                    let a = pop(stack)?;
+                    let b = pop(stack)?;
+                    if opcode == OP_EQUALVERIFY {
+                        if a != b {
+                            return Err(Error::ScriptError("OP_EQUALVERIFY failed".to_string()));
+                        }
+                    } else {
+                        stack.push(bool_to_stack(a == b));
+                    }
+                }
+                OP_SHA256 => {
+                    let data = pop(stack)?;
+                    stack.push(sha256(&data).to_vec());
+                }
+                OP_HASH160 => {
+                    let data = pop(stack)?;
+                    stack.push(hash160(&data).to_vec());
+                }
+                OP_HASH256 => {
+                    let data = pop(stack)?;
+                    stack.push(sha256d(&data).0.to_vec());
+                }
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                    let pubkey = pop(stack)?;
+                    let signature = pop(stack)?;
+                    let valid = checker.check_sig(&signature, &pubkey, script);
+                    if opcode == OP_CHECKSIGVERIFY {
+                        if !valid {
+                            return Err(Error::InvalidSignature);
+                        }
+                    } else {
+                        stack.push(bool_to_stack(valid));
+                    }
+                }
+                other => {
+                    return Err(Error::ScriptError(format!("unsupported opcode 0x{:02x}", other)));
+                }
+            }
+        }
+
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(Error::ScriptError("stack overflow".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>> {
+    stack.pop().ok_or_else(|| Error::ScriptError("stack underflow".to_string()))
+}
+
+fn bool_to_stack(value: bool) -> Vec<u8> {
+    if value { vec![1] } else { Vec::new() }
+}
+
+/// Any non-zero byte is true, except a lone sign bit (negative zero)
+fn cast_to_bool(data: &[u8]) -> bool {
+    for (i, byte) in data.iter().enumerate() {
+        if *byte != 0 {
+            return !(i == data.len() - 1 && *byte == 0x80);
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{TxInput, TxOutput};
+    use crate::types::TxHash;
+    use secp256k1::SecretKey;
+
+    fn test_key() -> (SecretKey, Vec<u8>) {
+        let secret = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(SECP256K1, &secret).serialize().to_vec();
+        (secret, pubkey)
+    }
+
+    fn spending_tx() -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TxInput {
+                prev_tx_hash: TxHash::from_bytes([9u8; 32]),
+                prev_tx_index: 0,
+                script: vec![],
+                sequence: 0xFFFFFFFF,
+            }],
+            outputs: vec![TxOutput { amount: 1000, script_pubkey: vec![OP_1] }],
+            locktime: 0,
+        }
+    }
+
+    fn sign(tx: &mut Transaction, secret: &SecretKey, pubkey: &[u8], script_pubkey: &[u8]) {
+        let digest = sighash::signature_hash(tx, 0, script_pubkey, sighash::SIGHASH_ALL, ChainParams::mainnet().network_id).unwrap();
+        let sig = SECP256K1.sign_ecdsa(&Message::from_digest(digest), secret);
+        let mut sig_bytes = sig.serialize_der().to_vec();
+        sig_bytes.push(sighash::SIGHASH_ALL);
+        tx.inputs[0].script = p2pkh_script_sig(&sig_bytes, pubkey);
+    }
+
+    #[test]
+    fn test_p2pkh_template() {
+        let (_, pubkey) = test_key();
+        let hash = hash160(&pubkey);
+        let script = p2pkh_script_pubkey(&hash);
+        assert_eq!(script.len(), P2PKH_SCRIPT_SIZE);
+        assert_eq!(extract_p2pkh(&script), Some(hash));
+
+        let params = ChainParams::mainnet();
+        let address = Address::from_bytes(hash).encode(&params);
+        assert_eq!(address_to_script_pubkey(&address, &params), Some(script.clone()));
+        assert_eq!(script_pubkey_to_address(&script, &params), Some(address.clone()));
+        assert_eq!(address_to_script_pubkey(&pubkey_hash_to_address(&hash), &params), Some(script.clone()));
+        assert_eq!(address_to_script_pubkey(&address, &ChainParams::testnet()), None);
+        assert_eq!(address_to_script_pubkey("ATMN_recipient123", &params), None);
+    }
+
+    #[test]
+    fn test_p2pkh_spend() {
+        let (secret, pubkey) = test_key();
+        let script_pubkey = p2pkh_script_pubkey(&hash160(&pubkey));
+        let mut tx = spending_tx();
+        sign(&mut tx, &secret, &pubkey, &script_pubkey);
+
+        let checker = TransactionSignatureChecker::new(&tx, 0, ChainParams::mainnet().network_id);
+        assert!(verify_script(&tx.inputs[0].script, &script_pubkey, &checker).is_ok());
+
+        // The same signature is not valid on another network
+        let checker = TransactionSignatureChecker::new(&tx, 0, ChainParams::testnet().network_id);
+        assert!(verify_script(&tx.inputs[0].script, &script_pubkey, &checker).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_rejected() {
+        let (_, pubkey) = test_key();
+        let script_pubkey = p2pkh_script_pubkey(&hash160(&pubkey));
+
+        // Signed by a different key whose hash does not match
+        let other = SecretKey::from_slice(&[0x43; 32]).unwrap();
+        let other_pub = PublicKey::from_secret_key(SECP256K1, &other).serialize().to_vec();
+        let mut tx = spending_tx();
+        sign(&mut tx, &other, &other_pub, &script_pubkey);
+
+        let checker = TransactionSignatureChecker::new(&tx, 0, ChainParams::mainnet().network_id);
+        assert!(verify_script(&tx.inputs[0].script, &script_pubkey, &checker).is_err());
+    }
+
+    #[test]
+    fn test_tampered_transaction_rejected() {
+        let (secret, pubkey) = test_key();
+        let script_pubkey = p2pkh_script_pubkey(&hash160(&pubkey));
+        let mut tx = spending_tx();
+        sign(&mut tx, &secret, &pubkey, &script_pubkey);
+
+        tx.outputs[0].amount += 1;
+        let checker = TransactionSignatureChecker::new(&tx, 0, ChainParams::mainnet().network_id);
+        assert!(verify_script(&tx.inputs[0].script, &script_pubkey, &checker).is_err());
+    }
+
+    #[test]
+    fn test_address_as_signature_rejected() {
+        // The old placeholder: sender address bytes in the input script
+        let (_, pubkey) = test_key();
+        let script_pubkey = p2pkh_script_pubkey(&hash160(&pubkey));
+        let mut tx = spending_tx();
+        tx.inputs[0].script = pubkey_hash_to_address(&hash160(&pubkey)).into_bytes();
+
+        let checker = TransactionSignatureChecker::new(&tx, 0, ChainParams::mainnet().network_id);
+        assert!(verify_script(&tx.inputs[0].script, &script_pubkey, &checker).is_err());
+    }
+
+    #[test]
+    fn test_push_only() {
+        assert!(is_push_only(&p2pkh_script_sig(&[1; 71], &[2; 33])));
+        assert!(!is_push_only(&[OP_DUP]));
+        assert!(!is_push_only(&[0x05, 0x01]));
+    }
+
+    #[test]
+    fn test_op_return_fails() {
+        let tx = spending_tx();
+        let checker = TransactionSignatureChecker::new(&tx, 0, ChainParams::mainnet().network_id);
+        assert!(verify_script(&[OP_1], &[OP_RETURN], &checker).is_err());
+        assert!(verify_script(&[], &[OP_1], &checker).is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+// atmn-core/src/sighash.rs
+// Signature hash algorithm
+//
+// The digest signed for an input is
+//   sha256d(network_id as u32 LE | modified transaction | hash_type as u32 LE)
+// where the modified transaction has every input script blanked except the
+// one being signed, which carries the script_pubkey of the output it spends.
+// Prefixing the network id keeps mainnet, testnet and regtest signatures
+// from being replayed on each other.
+//
+// hash_type selects which parts of the transaction are committed to:
+// - SIGHASH_ALL:    all inputs and outputs
+// - SIGHASH_NONE:   all inputs, no outputs (other inputs' sequences are free)
+// - SIGHASH_SINGLE: all inputs, only the output at the same index
+// - SIGHASH_ANYONECANPAY (flag): only the input being signed
+
+use crate::consensus::sha256d;
+use crate::error::{Error, Result};
+use crate::transaction::{Transaction, TxOutput};
+
+pub const SIGHASH_ALL: u8 = 0x01;
+pub const SIGHASH_NONE: u8 = 0x02;
+pub const SIGHASH_SINGLE: u8 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// Base mode of a hash type, without the ANYONECANPAY flag
+pub fn base_type(hash_type: u8) -> u8 {
+    hash_type & !SIGHASH_ANYONECANPAY
+}
+
+/// True for ALL, NONE or SINGLE, optionally combined with ANYONECANPAY
+pub fn is_defined_hash_type(hash_type: u8) -> bool {
+    matches!(base_type(hash_type), SIGHASH_ALL | SIGHASH_NONE | SIGHASH_SINGLE)
+}
+
+/// Digest signed by `input_index` spending an output locked by `script_code`
+pub fn signature_hash(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &[u8],
+    hash_type: u8,
+    network_id: u32,
+) -> Result<[u8; 32]> {
+    if input_index >= tx.inputs.len() {
+        return Err(Error::ScriptError("input index out of range".to_string()));
+    }
+    if !is_defined_hash_type(hash_type) {
+        return Err(Error::ScriptError(format!("undefined sighash type 0x{:02x}", hash_type)));
+    }
+
+    let mut copy = tx.clone();
+    for (i, input) in copy.inputs.iter_mut().enumerate() {
+        input.script = if i == input_index { script_code.to_vec() } else { Vec::new() };
+    }
+
+    match base_type(hash_type) {
+        SIGHASH_NONE => {
+            copy.outputs.clear();
+            zero_other_sequences(&mut copy, input_index);
+        }
+        SIGHASH_SINGLE => {
+            if input_index >= copy.outputs.len() {
+                return Err(Error::ScriptError(
+                    "SIGHASH_SINGLE input has no matching output".to_string(),
+                ));
+            }
+            copy.outputs.truncate(input_index + 1);
+            for output in &mut copy.outputs[..input_index] {
+                *output = TxOutput { amount: u64::MAX, script_pubkey: Vec::new() };
+            }
+            zero_other_sequences(&mut copy, input_index);
+        }
+        _ => {}
+    }
+
+    if hash_type & SIGHASH_ANYONECANPAY != 0 {
+        copy.inputs = vec![copy.inputs.swap_remove(input_index)];
+    }
+
+    let mut data = Vec::with_capacity(4 + copy.size() + 4);
+    data.extend_from_slice(&network_id.to_le_bytes());
+    data.extend_from_slice(&copy.serialize());
+    data.extend_from_slice(&(hash_type as u32).to_le_bytes());
+    Ok(sha256d(&data).0)
+}
+
+/// Other inputs may be replaced when their outputs are not committed to
+fn zero_other_sequences(tx: &mut Transaction, input_index: usize) {
+    for (i, input) in tx.inputs.iter_mut().enumerate() {
+        if i != input_index {
+            input.sequence = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_params::ChainParams;
+    use crate::transaction::TxInput;
+    use crate::types::TxHash;
+
+    fn sample_tx() -> Transaction {
+        let input = |n: u8| TxInput {
+            prev_tx_hash: TxHash::from_bytes([n; 32]),
+            prev_tx_index: 0,
+            script: vec![0xaa],
+            sequence: 0xFFFFFFFF,
+        };
+        let output = |amount| TxOutput { amount, script_pubkey: vec![0x51] };
+        Transaction {
+            version: 1,
+            inputs: vec![input(1), input(2)],
+            outputs: vec![output(100), output(200)],
+            locktime: 0,
+        }
+    }
+
+    fn digest(tx: &Transaction, index: usize, hash_type: u8) -> [u8; 32] {
+        signature_hash(tx, index, &[0x51], hash_type, ChainParams::mainnet().network_id).unwrap()
+    }
+
+    #[test]
+    fn test_commits_to_network() {
+        let tx = sample_tx();
+        let mainnet = signature_hash(&tx, 0, &[0x51], SIGHASH_ALL, ChainParams::mainnet().network_id).unwrap();
+        let testnet = signature_hash(&tx, 0, &[0x51], SIGHASH_ALL, ChainParams::testnet().network_id).unwrap();
+        assert_ne!(mainnet, testnet);
+    }
+
+    #[test]
+    fn test_all_commits_to_every_output() {
+        let tx = sample_tx();
+        let mut changed = tx.clone();
+        changed.outputs[1].amount += 1;
+        assert_ne!(digest(&tx, 0, SIGHASH_ALL), digest(&changed, 0, SIGHASH_ALL));
+        assert_ne!(digest(&tx, 0, SIGHASH_ALL), digest(&tx, 0, SIGHASH_NONE));
+    }
+
+    #[test]
+    fn test_none_ignores_outputs() {
+        let tx = sample_tx();
+        let mut changed = tx.clone();
+        changed.outputs.push(TxOutput { amount: 5, script_pubkey: vec![] });
+        changed.inputs[1].sequence = 7;
+        assert_eq!(digest(&tx, 0, SIGHASH_NONE), digest(&changed, 0, SIGHASH_NONE));
+    }
+
+    #[test]
+    fn test_single_commits_to_matching_output() {
+        let tx = sample_tx();
+
+        let mut other_output = tx.clone();
+        other_output.outputs[1].amount += 1;
+        assert_eq!(digest(&tx, 0, SIGHASH_SINGLE), digest(&other_output, 0, SIGHASH_SINGLE));
+
+        let mut own_output = tx.clone();
+        own_output.outputs[0].amount += 1;
+        assert_ne!(digest(&tx, 0, SIGHASH_SINGLE), digest(&own_output, 0, SIGHASH_SINGLE));
+
+        let mut no_match = tx.clone();
+        no_match.outputs.truncate(1);
+        assert!(signature_hash(&no_match, 1, &[0x51], SIGHASH_SINGLE, ChainParams::mainnet().network_id).is_err());
+    }
+
+    #[test]
+    fn test_anyonecanpay_ignores_other_inputs() {
+        let tx = sample_tx();
+        let hash_type = SIGHASH_ALL | SIGHASH_ANYONECANPAY;
+        let mut changed = tx.clone();
+        changed.inputs.remove(1);
+        assert_eq!(digest(&tx, 0, hash_type), digest(&changed, 0, hash_type));
+        assert_ne!(digest(&tx, 0, SIGHASH_ALL), digest(&changed, 0, SIGHASH_ALL));
+    }
+
+    #[test]
+    fn test_undefined_hash_type_rejected() {
+        let tx = sample_tx();
+        assert!(signature_hash(&tx, 0, &[], 0x00, ChainParams::mainnet().network_id).is_err());
+        assert!(signature_hash(&tx, 0, &[], 0x41, ChainParams::mainnet().network_id).is_err());
+        assert!(signature_hash(&tx, 5, &[], SIGHASH_ALL, ChainParams::mainnet().network_id).is_err());
+    }
+}
@@ -1,9 +1,17 @@
 // Storage layer using RocksDB
 use crate::{Block, Transaction};
-use crate::types::{BlockHash, BlockHeight, TxHash};
+use crate::block_filter::{BlockFilter, MAX_FILTER_HEADER_RANGE, MAX_FILTER_RANGE};
+use crate::chain_params::ChainParams;
+use crate::types::{Address, BlockHash, BlockHeight, OutPoint, Timestamp, TxHash};
+use crate::consensus;
+use crate::encoding::{self, Decodable, Encodable, Reader};
 use crate::error::{Error, Result};
-use rocksdb::{DB, Options, IteratorMode};
+use crate::merkle::{MerkleProof, TransactionProof};
+use crate::script;
+use crate::uint::U256;
+use rocksdb::{ColumnFamily, DB, Options, IteratorMode, WriteBatch};
 use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 
@@ -14,6 +22,17 @@ const CF_TRANSACTIONS: &str = "transactions";
 const CF_UTXOS: &str = "utxos";
 const CF_ADDRESS_INDEX: &str = "address_index";  // address -> list of UTXO keys
 const CF_METADATA: &str = "metadata";
+const CF_CHAIN_INDEX: &str = "chain_index";  // hash -> BlockIndexEntry, every known block
+const CF_SIDE_BLOCKS: &str = "side_blocks";  // hash -> block, blocks not on the active chain
+const CF_UNDO: &str = "undo";  // hash -> BlockUndo, for connected blocks
+const CF_BLOCK_FILTERS: &str = "block_filters";  // height -> FilterEntry, for connected blocks
+
+/// Metadata key holding the active tip height
+const BEST_HEIGHT_KEY: &[u8] = b"best_height";
+
+/// Metadata key holding the tip of an unversioned database being rebuilt,
+/// empty if it had none
+const REBUILD_TIP_KEY: &[u8] = b"rebuild_tip";
 
 /// Storage manager for blockchain data
 #[derive(Clone)]
@@ -22,13 +41,75 @@ pub struct Storage {
 }
 
 /// UTXO entry for tracking unspent outputs
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UtxoEntry {
     pub tx_hash: TxHash,
     pub output_index: u32,
     pub amount: u64,
     pub script_pubkey: Vec<u8>,
     pub block_height: BlockHeight,
+    pub is_coinbase: bool,
+}
+
+/// Read access to unspent outputs and the chain they belong to, used by validation
+pub trait UtxoView {
+    fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>>;
+
+    /// Median-time-past of the active-chain block at `height`, for time-based relative locks
+    fn median_time_past(&self, height: BlockHeight) -> Result<Timestamp>;
+}
+
+/// Outputs spent by a connected block, needed to disconnect it again
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockUndo {
+    /// Spent UTXOs in input order across the block
+    pub spent: Vec<UtxoEntry>,
+}
+
+/// Block index entry, kept for every known block including side branches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockIndexEntry {
+    pub hash: BlockHash,
+    pub prev_hash: BlockHash,
+    pub height: BlockHeight,
+    pub bits: u32,
+    pub timestamp: Timestamp,
+    /// Total work of the chain ending at this block
+    pub chainwork: U256,
+    /// Set once the block has failed contextual validation
+    pub invalid: bool,
+}
+
+/// Repair made by the startup consistency check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsistencyRepair {
+    /// `best_height` pointed past stored block data and was rewound
+    RewoundTip { from: BlockHeight, to: Option<BlockHeight> },
+    /// A block left half-written above the tip was rolled back
+    RolledBackTornBlock { height: BlockHeight, hash: BlockHash },
+    /// Block index entries were missing for active-chain blocks
+    RebuiltChainIndex { blocks: u64 },
+    /// Compact filters were missing for active-chain blocks
+    RebuiltBlockFilters { blocks: u64 },
+    /// A database from before the versioned encoding was rebuilt from its blocks
+    RebuiltUnversioned { blocks: u64 },
+}
+
+/// Stored compact filter of an active-chain block with its filter header
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FilterEntry {
+    filter: BlockFilter,
+    header: BlockHash,
+}
+
+impl UtxoView for Storage {
+    fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
+        Storage::get_utxo(self, &outpoint.tx_hash, outpoint.index)
+    }
+
+    fn median_time_past(&self, height: BlockHeight) -> Result<Timestamp> {
+        Storage::median_time_past(self, height)
+    }
 }
 
 impl Storage {
@@ -39,43 +120,55 @@ impl Storage {
         opts.create_missing_column_families(true);
         
         // Define column families
-        let cfs = vec![CF_BLOCKS, CF_BLOCK_INDEX, CF_TRANSACTIONS, CF_UTXOS, CF_ADDRESS_INDEX, CF_METADATA];
+        let cfs = vec![
+            CF_BLOCKS, CF_BLOCK_INDEX, CF_TRANSACTIONS, CF_UTXOS, CF_ADDRESS_INDEX, CF_METADATA,
+            CF_CHAIN_INDEX, CF_SIDE_BLOCKS, CF_UNDO, CF_BLOCK_FILTERS,
+        ];
         
         let db = DB::open_cf(&opts, path, cfs)
             .map_err(|e| Error::DatabaseError(e.to_string()))?;
         
-        Ok(Self {
+        let storage = Self {
             db: Arc::new(db),
-        })
+        };
+        for repair in storage.check_consistency()? {
+            log::warn!("Repaired database: {:?}", repair);
+        }
+        
+        Ok(storage)
     }
 
-    /// Store a block
+    /// Connect a block to the active chain at `height`
+    ///
+    /// Every write for the block, including the new tip, is committed in one batch.
     pub fn put_block(&self, height: BlockHeight, block: &Block) -> Result<()> {
-        let cf_blocks = self.db.cf_handle(CF_BLOCKS)
-            .ok_or_else(|| Error::DatabaseError("CF_BLOCKS not found".to_string()))?;
-        let cf_index = self.db.cf_handle(CF_BLOCK_INDEX)
-            .ok_or_else(|| Error::DatabaseError("CF_BLOCK_INDEX not found".to_string()))?;
-        
-        // Serialize block
-        let block_data = bincode::serialize(block)
-            .map_err(|e| Error::DatabaseError(format!("Serialization error: {}", e)))?;
         let hash = block.hash();
+        let mut batch = BlockBatch::new(&self.db);
         
         // Store by height
-        self.db.put_cf(cf_blocks, height.to_le_bytes(), block_data.clone())
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        batch.put(CF_BLOCKS, &height.to_le_bytes(), encoding::serialize_versioned(block))?;
         
         // Store hash -> height index
-        self.db.put_cf(cf_index, hash.as_bytes(), height.to_le_bytes())
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        batch.put(CF_BLOCK_INDEX, hash.as_bytes(), height.to_le_bytes().to_vec())?;
         
         // Store transactions
-        self.store_block_transactions(height, block)?;
+        let undo = self.store_block_transactions(&mut batch, height, block)?;
+        
+        // Filter over created and spent scripts, chained to the parent's
+        let spent = undo.spent.iter().map(|utxo| utxo.script_pubkey.as_slice());
+        let filter = BlockFilter::build(block, spent);
+        self.put_block_filter(&mut batch, height, filter)?;
+        
+        // Blocks connected directly (genesis, tools) still need an index entry
+        if self.get_block_index(&hash)?.is_none() {
+            let entry = self.index_entry_for(block)?;
+            batch.put(CF_CHAIN_INDEX, hash.as_bytes(), encoding::serialize_versioned(&entry))?;
+        }
         
         // Update best block height
-        self.update_best_height(height)?;
+        batch.put(CF_METADATA, BEST_HEIGHT_KEY, height.to_le_bytes().to_vec())?;
         
-        Ok(())
+        batch.commit()
     }
 
     /// Get block by height
@@ -86,7 +179,7 @@ impl Storage {
         match self.db.get_cf(cf_blocks, height.to_le_bytes())
             .map_err(|e| Error::DatabaseError(e.to_string()))? {
             Some(data) => {
-                let block: Block = bincode::deserialize(&data)
+                let block: Block = encoding::deserialize_versioned(&data)
                     .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
                 Ok(Some(block))
             }
@@ -94,6 +187,17 @@ impl Storage {
         }
     }
 
+    /// Median timestamp of the block at `height` and up to ten ancestors
+    pub fn median_time_past(&self, height: BlockHeight) -> Result<Timestamp> {
+        let first = height.saturating_sub(consensus::MEDIAN_TIME_SPAN as u64 - 1);
+        let mut timestamps = Vec::with_capacity(consensus::MEDIAN_TIME_SPAN);
+        for h in first..=height {
+            let block = self.get_block(h)?.ok_or(Error::InvalidBlockHeight(h))?;
+            timestamps.push(block.header.timestamp);
+        }
+        Ok(consensus::median_time_past(&timestamps))
+    }
+
     /// Get block by hash
     pub fn get_block_by_hash(&self, hash: &BlockHash) -> Result<Option<Block>> {
         let cf_index = self.db.cf_handle(CF_BLOCK_INDEX)
@@ -113,14 +217,485 @@ impl Storage {
         }
     }
 
-    /// Store transactions from a block
-    fn store_block_transactions(&self, height: BlockHeight, block: &Block) -> Result<()> {
-        let cf_txs = self.db.cf_handle(CF_TRANSACTIONS)
-            .ok_or_else(|| Error::DatabaseError("CF_TRANSACTIONS not found".to_string()))?;
+    /// Index entry for a block whose parent (if any) is already indexed
+    pub fn index_entry_for(&self, block: &Block) -> Result<BlockIndexEntry> {
+        let parent_work = match self.get_block_index(&block.header.prev_block_hash)? {
+            Some(parent) => parent.chainwork,
+            None => U256::ZERO,
+        };
+        
+        Ok(BlockIndexEntry {
+            hash: block.hash(),
+            prev_hash: block.header.prev_block_hash,
+            height: block.height,
+            bits: block.header.bits,
+            timestamp: block.header.timestamp,
+            chainwork: parent_work.saturating_add(consensus::block_work(block.header.bits)),
+            invalid: false,
+        })
+    }
+
+    /// Store a block index entry
+    pub fn put_block_index(&self, entry: &BlockIndexEntry) -> Result<()> {
+        let cf_chain = self.db.cf_handle(CF_CHAIN_INDEX)
+            .ok_or_else(|| Error::DatabaseError("CF_CHAIN_INDEX not found".to_string()))?;
+        
+        self.db.put_cf(cf_chain, entry.hash.as_bytes(), encoding::serialize_versioned(entry))
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Get a block index entry by hash
+    pub fn get_block_index(&self, hash: &BlockHash) -> Result<Option<BlockIndexEntry>> {
+        let cf_chain = self.db.cf_handle(CF_CHAIN_INDEX)
+            .ok_or_else(|| Error::DatabaseError("CF_CHAIN_INDEX not found".to_string()))?;
+        
+        match self.db.get_cf(cf_chain, hash.as_bytes())
+            .map_err(|e| Error::DatabaseError(e.to_string()))? {
+            Some(data) => {
+                let entry: BlockIndexEntry = encoding::deserialize_versioned(&data)
+                    .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Store a block that is not (or no longer) on the active chain
+    pub fn put_side_block(&self, block: &Block) -> Result<()> {
+        let cf_side = self.db.cf_handle(CF_SIDE_BLOCKS)
+            .ok_or_else(|| Error::DatabaseError("CF_SIDE_BLOCKS not found".to_string()))?;
+        
+        self.db.put_cf(cf_side, block.hash().as_bytes(), encoding::serialize_versioned(block))
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Get a side-branch block by hash
+    pub fn get_side_block(&self, hash: &BlockHash) -> Result<Option<Block>> {
+        let cf_side = self.db.cf_handle(CF_SIDE_BLOCKS)
+            .ok_or_else(|| Error::DatabaseError("CF_SIDE_BLOCKS not found".to_string()))?;
+        
+        match self.db.get_cf(cf_side, hash.as_bytes())
+            .map_err(|e| Error::DatabaseError(e.to_string()))? {
+            Some(data) => {
+                let block: Block = encoding::deserialize_versioned(&data)
+                    .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+                Ok(Some(block))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a side-branch block once it joins the active chain
+    pub fn delete_side_block(&self, hash: &BlockHash) -> Result<()> {
+        let cf_side = self.db.cf_handle(CF_SIDE_BLOCKS)
+            .ok_or_else(|| Error::DatabaseError("CF_SIDE_BLOCKS not found".to_string()))?;
+        
+        self.db.delete_cf(cf_side, hash.as_bytes())
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Hash of the active-chain block at `height`
+    pub fn get_block_hash(&self, height: BlockHeight) -> Result<Option<BlockHash>> {
+        Ok(self.get_block(height)?.map(|block| block.hash()))
+    }
+
+    /// Disconnect the tip of the active chain, undoing its UTXO changes
+    ///
+    /// Outputs the block created are removed, the outputs it spent are
+    /// restored from its undo record and `best_height` is rewound, all in
+    /// one batch.
+    pub fn disconnect_block(&self) -> Result<Block> {
+        let height = self.get_best_height()?.ok_or(Error::ChainNotInitialized)?;
+        let block = self.get_block(height)?.ok_or(Error::InvalidBlockHeight(height))?;
+        let hash = block.hash();
+        let undo = self.get_block_undo(&hash)?
+            .ok_or_else(|| Error::DatabaseError(format!("Missing undo data for block {}", hash)))?;
+        
+        let mut batch = BlockBatch::new(&self.db);
+        
+        // Remove everything the block created
+        let mut created = HashSet::new();
+        for tx in &block.transactions {
+            let tx_hash = tx.hash();
+            created.insert(tx_hash);
+            remove_outputs(&mut batch, &tx_hash, tx)?;
+            batch.delete(CF_TRANSACTIONS, tx_hash.as_bytes())?;
+        }
+        
+        // Restore spent outputs, except those created and spent within the block
+        for utxo in undo.spent.iter().filter(|utxo| !created.contains(&utxo.tx_hash)) {
+            restore_utxo(&mut batch, utxo)?;
+        }
+        
+        batch.delete(CF_UNDO, hash.as_bytes())?;
+        batch.delete(CF_BLOCK_FILTERS, &height.to_le_bytes())?;
+        batch.delete(CF_BLOCKS, &height.to_le_bytes())?;
+        batch.delete(CF_BLOCK_INDEX, hash.as_bytes())?;
+        
+        // Rewind the tip; disconnecting genesis leaves an empty chain
+        if height == 0 {
+            batch.delete(CF_METADATA, BEST_HEIGHT_KEY)?;
+        } else {
+            batch.put(CF_METADATA, BEST_HEIGHT_KEY, (height - 1).to_le_bytes().to_vec())?;
+        }
+        
+        batch.commit()?;
+        Ok(block)
+    }
+
+    /// Detect and repair state left behind by an interrupted write
+    ///
+    /// Versions before batched commits wrote a block piece by piece and
+    /// updated `best_height` last, so a crash could leave a partly applied
+    /// block above the tip, or a tip whose block data is missing. Bincode
+    /// databases are rebuilt from their blocks up to the tip, which drops
+    /// such leftovers; versioned ones are repaired in place. Runs on every
+    /// open; returns the repairs made.
+    pub fn check_consistency(&self) -> Result<Vec<ConsistencyRepair>> {
+        let mut repairs = Vec::new();
+        
+        // Databases from before the versioned encoding hold bincode records
+        if let Some(blocks) = self.rebuild_unversioned()? {
+            repairs.push(ConsistencyRepair::RebuiltUnversioned { blocks });
+        }
+        
+        // The tip must point at stored block data
+        if let Some(best) = self.get_best_height()? {
+            if self.get_block(best)?.is_none() {
+                let mut to = None;
+                for height in (0..best).rev() {
+                    if self.get_block(height)?.is_some() {
+                        to = Some(height);
+                        break;
+                    }
+                }
+                
+                let mut batch = BlockBatch::new(&self.db);
+                match to {
+                    Some(height) => batch.put(CF_METADATA, BEST_HEIGHT_KEY, height.to_le_bytes().to_vec())?,
+                    None => batch.delete(CF_METADATA, BEST_HEIGHT_KEY)?,
+                }
+                batch.commit()?;
+                repairs.push(ConsistencyRepair::RewoundTip { from: best, to });
+            }
+        }
+        
+        // A block stored above the tip never finished connecting
+        let next = self.get_best_height()?.map_or(0, |height| height + 1);
+        if let Some(block) = self.get_block(next)? {
+            self.roll_back_torn_block(next, &block)?;
+            repairs.push(ConsistencyRepair::RolledBackTornBlock { height: next, hash: block.hash() });
+        }
+        
+        // Databases created before the block index have no entries for the active chain
+        if let Some(best) = self.get_best_height()? {
+            let tip_hash = self.get_block_hash(best)?.ok_or(Error::InvalidBlockHeight(best))?;
+            if self.get_block_index(&tip_hash)?.is_none() {
+                let mut rebuilt = 0;
+                for height in 0..=best {
+                    let Some(block) = self.get_block(height)? else { continue };
+                    if self.get_block_index(&block.hash())?.is_none() {
+                        self.put_block_index(&self.index_entry_for(&block)?)?;
+                        rebuilt += 1;
+                    }
+                }
+                repairs.push(ConsistencyRepair::RebuiltChainIndex { blocks: rebuilt });
+            }
+        }
+        
+        // Databases created before compact filters have none for the active chain
+        if let Some(best) = self.get_best_height()? {
+            if self.get_filter_entry(best)?.is_none() {
+                let mut rebuilt = 0;
+                for height in 0..=best {
+                    if self.get_filter_entry(height)?.is_some() {
+                        continue;
+                    }
+                    let block = self.get_block(height)?.ok_or(Error::InvalidBlockHeight(height))?;
+                    let spent = self.spent_scripts(&block)?;
+                    let filter = BlockFilter::build(&block, spent.iter().map(Vec::as_slice));
+                    let mut batch = BlockBatch::new(&self.db);
+                    self.put_block_filter(&mut batch, height, filter)?;
+                    batch.commit()?;
+                    rebuilt += 1;
+                }
+                repairs.push(ConsistencyRepair::RebuiltBlockFilters { blocks: rebuilt });
+            }
+        }
+        
+        Ok(repairs)
+    }
+
+    /// Reconnect the blocks of a database written before the versioned
+    /// encoding, returning how many were kept
+    ///
+    /// Such databases hold bincode records, and their transaction index and
+    /// UTXO set were keyed by placeholder transaction hashes, so only the
+    /// blocks up to the recorded tip are kept and everything else is derived
+    /// from them again. An interrupted rebuild resumes on the next open.
+    fn rebuild_unversioned(&self) -> Result<Option<u64>> {
+        let cf_meta = self.db.cf_handle(CF_METADATA)
+            .ok_or_else(|| Error::DatabaseError("CF_METADATA not found".to_string()))?;
+        
+        let tip = match self.db.get_cf(cf_meta, REBUILD_TIP_KEY)
+            .map_err(|e| Error::DatabaseError(e.to_string()))? {
+            Some(data) if data.is_empty() => None,
+            Some(data) => Some(BlockHeight::from_le_bytes(
+                data.as_slice().try_into()
+                    .map_err(|e| Error::DatabaseError(format!("Invalid height bytes: {:?}", e)))?
+            )),
+            None => {
+                if !self.is_unversioned()? {
+                    return Ok(None);
+                }
+                let tip = self.get_best_height()?;
+                let data = tip.map(|height| height.to_le_bytes().to_vec()).unwrap_or_default();
+                self.db.put_cf(cf_meta, REBUILD_TIP_KEY, data)
+                    .map_err(|e| Error::DatabaseError(e.to_string()))?;
+                tip
+            }
+        };
+        
+        // Only the blocks are kept; a rebuild cut short has re-encoded some
+        for name in [CF_BLOCK_INDEX, CF_TRANSACTIONS, CF_UTXOS, CF_ADDRESS_INDEX, CF_CHAIN_INDEX, CF_UNDO, CF_BLOCK_FILTERS] {
+            self.clear_column_family(name)?;
+        }
+        self.db.delete_cf(cf_meta, BEST_HEIGHT_KEY)
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+        
+        let mut kept = 0;
+        if let Some(tip) = tip {
+            while kept <= tip {
+                let Some(block) = self.get_stored_block(kept)? else { break };
+                self.put_block(kept, &block)?;
+                kept += 1;
+            }
+        }
+        
+        // Blocks past a gap or above the tip never finished connecting
+        let cf_blocks = self.db.cf_handle(CF_BLOCKS)
+            .ok_or_else(|| Error::DatabaseError("CF_BLOCKS not found".to_string()))?;
+        let mut batch = BlockBatch::new(&self.db);
+        for item in self.db.iterator_cf(cf_blocks, IteratorMode::Start) {
+            let (key, _) = item.map_err(|e| Error::DatabaseError(e.to_string()))?;
+            if stored_height(&key)? >= kept {
+                batch.delete(CF_BLOCKS, &key)?;
+            }
+        }
+        batch.delete(CF_METADATA, REBUILD_TIP_KEY)?;
+        batch.commit()?;
+        Ok(Some(kept))
+    }
+    
+    /// Whether stored blocks use bincode rather than the versioned encoding
+    fn is_unversioned(&self) -> Result<bool> {
+        let cf_blocks = self.db.cf_handle(CF_BLOCKS)
+            .ok_or_else(|| Error::DatabaseError("CF_BLOCKS not found".to_string()))?;
+        
+        let Some(item) = self.db.iterator_cf(cf_blocks, IteratorMode::Start).next() else {
+            return Ok(false);
+        };
+        let (key, data) = item.map_err(|e| Error::DatabaseError(e.to_string()))?;
+        let height = stored_height(&key)?;
+        if decode_versioned_block(&data, height).is_some() {
+            return Ok(false);
+        }
+        if decode_bincode_block(&data, height).is_some() {
+            return Ok(true);
+        }
+        Err(Error::DatabaseError(format!(
+            "Block at height {} is in neither the versioned nor the bincode encoding",
+            height
+        )))
+    }
+    
+    /// Block at `height` in either the versioned or the bincode encoding
+    fn get_stored_block(&self, height: BlockHeight) -> Result<Option<Block>> {
+        let cf_blocks = self.db.cf_handle(CF_BLOCKS)
+            .ok_or_else(|| Error::DatabaseError("CF_BLOCKS not found".to_string()))?;
         
+        match self.db.get_cf(cf_blocks, height.to_le_bytes())
+            .map_err(|e| Error::DatabaseError(e.to_string()))? {
+            Some(data) => decode_versioned_block(&data, height)
+                .or_else(|| decode_bincode_block(&data, height))
+                .map(Some)
+                .ok_or_else(|| Error::DatabaseError(format!("Unreadable block at height {}", height))),
+            None => Ok(None),
+        }
+    }
+    
+    /// Delete every record in a column family
+    fn clear_column_family(&self, name: &'static str) -> Result<()> {
+        let cf = self.db.cf_handle(name)
+            .ok_or_else(|| Error::DatabaseError(format!("Column family {} not found", name)))?;
+        
+        let mut batch = BlockBatch::new(&self.db);
+        for item in self.db.iterator_cf(cf, IteratorMode::Start) {
+            let (key, _) = item.map_err(|e| Error::DatabaseError(e.to_string()))?;
+            batch.delete(name, &key)?;
+        }
+        batch.commit()
+    }
+
+    /// Undo whatever part of an unbatched block write reached the database
+    ///
+    /// Transactions were stored with their metadata before their UTXO
+    /// changes, so a transaction without metadata was never applied. Spent
+    /// outputs are rebuilt from the transaction index since versions writing
+    /// unbatched kept no undo data.
+    fn roll_back_torn_block(&self, height: BlockHeight, block: &Block) -> Result<()> {
+        let hash = block.hash();
+        let created: HashSet<TxHash> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        let mut batch = BlockBatch::new(&self.db);
+        
+        for tx in block.transactions.iter().rev() {
+            let tx_hash = tx.hash();
+            match self.get_transaction(&tx_hash)? {
+                Some(meta) if meta.block_hash == hash => {}
+                _ => continue,
+            }
+            
+            remove_outputs(&mut batch, &tx_hash, tx)?;
+            batch.delete(CF_TRANSACTIONS, tx_hash.as_bytes())?;
+            
+            if tx.is_coinbase() {
+                continue;
+            }
+            for input in tx.inputs.iter().filter(|input| !created.contains(&input.prev_tx_hash)) {
+                let prev = self.get_transaction(&input.prev_tx_hash)?
+                    .ok_or_else(|| Error::DatabaseError(format!("Missing spent transaction {}", input.prev_tx_hash)))?;
+                let output = prev.transaction.outputs.get(input.prev_tx_index as usize)
+                    .ok_or_else(|| Error::DatabaseError(format!("Missing spent output {}", input.outpoint())))?;
+                
+                restore_utxo(&mut batch, &UtxoEntry {
+                    tx_hash: input.prev_tx_hash,
+                    output_index: input.prev_tx_index,
+                    amount: output.amount,
+                    script_pubkey: output.script_pubkey.clone(),
+                    block_height: prev.block_height,
+                    is_coinbase: prev.transaction.is_coinbase(),
+                })?;
+            }
+        }
+        
+        batch.delete(CF_BLOCK_FILTERS, &height.to_le_bytes())?;
+        batch.delete(CF_BLOCKS, &height.to_le_bytes())?;
+        batch.delete(CF_BLOCK_INDEX, hash.as_bytes())?;
+        batch.commit()
+    }
+
+    /// Get the undo record of a connected block
+    pub fn get_block_undo(&self, hash: &BlockHash) -> Result<Option<BlockUndo>> {
+        let cf_undo = self.db.cf_handle(CF_UNDO)
+            .ok_or_else(|| Error::DatabaseError("CF_UNDO not found".to_string()))?;
+        
+        match self.db.get_cf(cf_undo, hash.as_bytes())
+            .map_err(|e| Error::DatabaseError(e.to_string()))? {
+            Some(data) => {
+                let undo: BlockUndo = encoding::deserialize_versioned(&data)
+                    .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+                Ok(Some(undo))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Scripts of the outputs a connected block spends
+    ///
+    /// Read from the block's undo record, or from the transaction index for
+    /// blocks connected before undo records were kept.
+    fn spent_scripts(&self, block: &Block) -> Result<Vec<Vec<u8>>> {
+        if let Some(undo) = self.get_block_undo(&block.hash())? {
+            return Ok(undo.spent.into_iter().map(|utxo| utxo.script_pubkey).collect());
+        }
+        
+        let mut scripts = Vec::new();
+        for input in block.transactions.iter().filter(|tx| !tx.is_coinbase()).flat_map(|tx| &tx.inputs) {
+            let prev = self.get_transaction(&input.prev_tx_hash)?
+                .ok_or_else(|| Error::DatabaseError(format!("Missing spent transaction {}", input.prev_tx_hash)))?;
+            let output = prev.transaction.outputs.get(input.prev_tx_index as usize)
+                .ok_or_else(|| Error::DatabaseError(format!("Missing spent output {}", input.outpoint())))?;
+            scripts.push(output.script_pubkey.clone());
+        }
+        Ok(scripts)
+    }
+
+    /// Queue the filter of the block at `height`, chaining its header to the parent's
+    fn put_block_filter(&self, batch: &mut BlockBatch<'_>, height: BlockHeight, filter: BlockFilter) -> Result<()> {
+        let prev_header = match height {
+            0 => BlockHash::zero(),
+            _ => self.get_filter_header(height - 1)?
+                .ok_or_else(|| Error::DatabaseError(format!("Missing filter header at height {}", height - 1)))?,
+        };
+        let entry = FilterEntry { header: filter.header(&prev_header), filter };
+        batch.put(CF_BLOCK_FILTERS, &height.to_le_bytes(), encoding::serialize_versioned(&entry))
+    }
+
+    fn get_filter_entry(&self, height: BlockHeight) -> Result<Option<FilterEntry>> {
+        let cf_filters = self.db.cf_handle(CF_BLOCK_FILTERS)
+            .ok_or_else(|| Error::DatabaseError("CF_BLOCK_FILTERS not found".to_string()))?;
+        
+        match self.db.get_cf(cf_filters, height.to_le_bytes())
+            .map_err(|e| Error::DatabaseError(e.to_string()))? {
+            Some(data) => {
+                let entry: FilterEntry = encoding::deserialize_versioned(&data)
+                    .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Compact filter of the active-chain block at `height`
+    pub fn get_block_filter(&self, height: BlockHeight) -> Result<Option<BlockFilter>> {
+        Ok(self.get_filter_entry(height)?.map(|entry| entry.filter))
+    }
+
+    /// Filter header of the active-chain block at `height`
+    pub fn get_filter_header(&self, height: BlockHeight) -> Result<Option<BlockHash>> {
+        Ok(self.get_filter_entry(height)?.map(|entry| entry.header))
+    }
+
+    /// Filters of the active-chain blocks `start..=end`, at most `MAX_FILTER_RANGE`
+    pub fn get_block_filters(&self, start: BlockHeight, end: BlockHeight) -> Result<Vec<BlockFilter>> {
+        Ok(self.get_filter_range(start, end, MAX_FILTER_RANGE)?
+            .into_iter()
+            .map(|entry| entry.filter)
+            .collect())
+    }
+
+    /// Filter headers of the active-chain blocks `start..=end`, at most `MAX_FILTER_HEADER_RANGE`
+    pub fn get_filter_headers(&self, start: BlockHeight, end: BlockHeight) -> Result<Vec<BlockHash>> {
+        Ok(self.get_filter_range(start, end, MAX_FILTER_HEADER_RANGE)?
+            .into_iter()
+            .map(|entry| entry.header)
+            .collect())
+    }
+
+    fn get_filter_range(&self, start: BlockHeight, end: BlockHeight, max: u64) -> Result<Vec<FilterEntry>> {
+        let best = self.get_best_height()?.ok_or(Error::ChainNotInitialized)?;
+        if start > end || end > best {
+            return Err(Error::InvalidBlockHeight(end));
+        }
+        if end - start >= max {
+            return Err(Error::Other(format!("Requested {} filters, at most {} allowed", end - start + 1, max)));
+        }
+        
+        (start..=end)
+            .map(|height| self.get_filter_entry(height)?
+                .ok_or_else(|| Error::DatabaseError(format!("Missing filter at height {}", height))))
+            .collect()
+    }
+
+    /// Store transactions from a block, recording what they spend
+    fn store_block_transactions(&self, batch: &mut BlockBatch<'_>, height: BlockHeight, block: &Block) -> Result<BlockUndo> {
+        let mut undo = BlockUndo::default();
         for (idx, tx) in block.transactions.iter().enumerate() {
             // Calculate transaction hash
-            let tx_hash = self.calculate_tx_hash(tx);
+            let tx_hash = tx.hash();
             
             // Store tx with metadata
             let tx_meta = TransactionMetadata {
@@ -130,16 +705,14 @@ impl Storage {
                 tx_index: idx as u32,
             };
             
-            let tx_data = bincode::serialize(&tx_meta)
-                .map_err(|e| Error::DatabaseError(format!("Serialization error: {}", e)))?;
-            self.db.put_cf(cf_txs, tx_hash.as_bytes(), tx_data)
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            batch.put(CF_TRANSACTIONS, tx_hash.as_bytes(), encoding::serialize_versioned(&tx_meta))?;
             
             // Update UTXO set
-            self.update_utxos(height, &tx_hash, tx)?;
+            undo.spent.extend(update_utxos(batch, height, &tx_hash, tx)?);
         }
         
-        Ok(())
+        batch.put(CF_UNDO, block.hash().as_bytes(), encoding::serialize_versioned(&undo))?;
+        Ok(undo)
     }
 
     /// Get transaction by hash
@@ -150,7 +723,7 @@ impl Storage {
         match self.db.get_cf(cf_txs, tx_hash.as_bytes())
             .map_err(|e| Error::DatabaseError(e.to_string()))? {
             Some(data) => {
-                let tx_meta: TransactionMetadata = bincode::deserialize(&data)
+                let tx_meta: TransactionMetadata = encoding::deserialize_versioned(&data)
                     .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
                 Ok(Some(tx_meta))
             }
@@ -158,74 +731,45 @@ impl Storage {
         }
     }
 
-    /// Update UTXO set
-    fn update_utxos(&self, height: BlockHeight, tx_hash: &TxHash, tx: &Transaction) -> Result<()> {
+    /// Inclusion proof for a transaction on the active chain, with the
+    /// header of the block holding it
+    pub fn get_transaction_proof(&self, tx_hash: &TxHash) -> Result<Option<TransactionProof>> {
+        let Some(tx_meta) = self.get_transaction(tx_hash)? else {
+            return Ok(None);
+        };
+        let block = self.get_block(tx_meta.block_height)?
+            .ok_or(Error::InvalidBlockHeight(tx_meta.block_height))?;
+
+        let proof = MerkleProof::new(&block.transactions, tx_meta.tx_index as usize)
+            .filter(|proof| proof.txid == *tx_hash)
+            .ok_or_else(|| Error::DatabaseError(format!(
+                "Transaction {} not at index {} of block {}",
+                tx_hash, tx_meta.tx_index, tx_meta.block_hash
+            )))?;
+        Ok(Some(TransactionProof { header: block.header, height: block.height, proof }))
+    }
+
+    /// Get an unspent output by outpoint
+    pub fn get_utxo(&self, tx_hash: &TxHash, output_index: u32) -> Result<Option<UtxoEntry>> {
         let cf_utxos = self.db.cf_handle(CF_UTXOS)
             .ok_or_else(|| Error::DatabaseError("CF_UTXOS not found".to_string()))?;
-        let cf_addr_idx = self.db.cf_handle(CF_ADDRESS_INDEX)
-            .ok_or_else(|| Error::DatabaseError("CF_ADDRESS_INDEX not found".to_string()))?;
-        
-        // Remove spent UTXOs (inputs)
-        for input in &tx.inputs {
-            let utxo_key = format!("{}:{}", input.prev_tx_hash, input.prev_tx_index);
-            self.db.delete_cf(cf_utxos, utxo_key.as_bytes())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
-        }
-        
-        // Add new UTXOs (outputs)
-        for (output_index, output) in tx.outputs.iter().enumerate() {
-            let utxo_entry = UtxoEntry {
-                tx_hash: tx_hash.clone(),
-                output_index: output_index as u32,
-                amount: output.amount,
-                script_pubkey: output.script_pubkey.clone(),
-                block_height: height,
-            };
-            
-            let utxo_key = format!("{}:{}", tx_hash, output_index);
-            let utxo_data = bincode::serialize(&utxo_entry)
-                .map_err(|e| Error::DatabaseError(format!("Serialization error: {}", e)))?;
-            self.db.put_cf(cf_utxos, utxo_key.as_bytes(), utxo_data.clone())
-                .map_err(|e| Error::DatabaseError(e.to_string()))?;
-            
-            // Extract address from script_pubkey (it's encoded as address bytes)
-            let address = String::from_utf8_lossy(&output.script_pubkey).to_string();
-            
-            // Add to address index
-            self.add_to_address_index(&cf_addr_idx, &address, &utxo_key)?;
-        }
-        
-        Ok(())
-    }
 
-    /// Add UTXO key to address index
-    fn add_to_address_index(&self, cf_addr_idx: &rocksdb::ColumnFamily, address: &str, utxo_key: &str) -> Result<()> {
-        // Get existing UTXO list for address
-        let mut utxo_list = match self.db.get_cf(cf_addr_idx, address.as_bytes())
+        let utxo_key = format!("{}:{}", tx_hash, output_index);
+        match self.db.get_cf(cf_utxos, utxo_key.as_bytes())
             .map_err(|e| Error::DatabaseError(e.to_string()))? {
             Some(data) => {
-                bincode::deserialize::<Vec<String>>(&data)
-                    .unwrap_or_default()
+                let utxo: UtxoEntry = encoding::deserialize_versioned(&data)
+                    .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+                Ok(Some(utxo))
             }
-            None => Vec::new(),
-        };
-        
-        // Add new UTXO key if not present
-        if !utxo_list.contains(&utxo_key.to_string()) {
-            utxo_list.push(utxo_key.to_string());
+            None => Ok(None),
         }
-        
-        // Save updated list
-        let data = bincode::serialize(&utxo_list)
-            .map_err(|e| Error::DatabaseError(format!("Serialization error: {}", e)))?;
-        self.db.put_cf(cf_addr_idx, address.as_bytes(), data)
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
-        
-        Ok(())
     }
 
-    /// Get UTXOs for an address (now uses address index for efficiency)
-    pub fn get_utxos_for_address(&self, address: &str) -> Result<Vec<UtxoEntry>> {
+    /// Get UTXOs for an address on `params`' network (now uses address index for efficiency)
+    ///
+    /// Checksummed and legacy `ATMN_<40 hex>` forms find the same outputs.
+    pub fn get_utxos_for_address(&self, address: &str, params: &ChainParams) -> Result<Vec<UtxoEntry>> {
         let cf_addr_idx = self.db.cf_handle(CF_ADDRESS_INDEX)
             .ok_or_else(|| Error::DatabaseError("CF_ADDRESS_INDEX not found".to_string()))?;
         let cf_utxos = self.db.cf_handle(CF_UTXOS)
@@ -234,7 +778,9 @@ impl Storage {
         let mut utxos = Vec::new();
         
         // Get UTXO keys for this address from index
-        if let Some(data) = self.db.get_cf(cf_addr_idx, address.as_bytes())
+        let index_key = script::address_to_script_pubkey(address, params)
+            .map_or_else(|| address.to_string(), |script_pubkey| output_address(&script_pubkey));
+        if let Some(data) = self.db.get_cf(cf_addr_idx, index_key.as_bytes())
             .map_err(|e| Error::DatabaseError(e.to_string()))? {
             let utxo_keys: Vec<String> = bincode::deserialize(&data)
                 .unwrap_or_default();
@@ -243,7 +789,7 @@ impl Storage {
             for utxo_key in utxo_keys {
                 if let Some(utxo_data) = self.db.get_cf(cf_utxos, utxo_key.as_bytes())
                     .map_err(|e| Error::DatabaseError(e.to_string()))? {
-                    let utxo: UtxoEntry = bincode::deserialize(&utxo_data)
+                    let utxo: UtxoEntry = encoding::deserialize_versioned(&utxo_data)
                         .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
                     utxos.push(utxo);
                 }
@@ -253,9 +799,9 @@ impl Storage {
         Ok(utxos)
     }
 
-    /// Calculate balance for an address
-    pub fn get_balance(&self, address: &str) -> Result<u64> {
-        let utxos = self.get_utxos_for_address(address)?;
+    /// Calculate balance for an address on `params`' network
+    pub fn get_balance(&self, address: &str, params: &ChainParams) -> Result<u64> {
+        let utxos = self.get_utxos_for_address(address, params)?;
         Ok(utxos.iter().map(|u| u.amount).sum())
     }
 
@@ -264,7 +810,7 @@ impl Storage {
         let cf_meta = self.db.cf_handle(CF_METADATA)
             .ok_or_else(|| Error::DatabaseError("CF_METADATA not found".to_string()))?;
         
-        match self.db.get_cf(cf_meta, b"best_height")
+        match self.db.get_cf(cf_meta, BEST_HEIGHT_KEY)
             .map_err(|e| Error::DatabaseError(e.to_string()))? {
             Some(data) => {
                 let height = BlockHeight::from_le_bytes(
@@ -277,32 +823,29 @@ impl Storage {
         }
     }
 
-    /// Update best block height
-    fn update_best_height(&self, height: BlockHeight) -> Result<()> {
-        let cf_meta = self.db.cf_handle(CF_METADATA)
-            .ok_or_else(|| Error::DatabaseError("CF_METADATA not found".to_string()))?;
-        
-        self.db.put_cf(cf_meta, b"best_height", height.to_le_bytes())
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
-        Ok(())
-    }
+    /// Sum the UTXO set and compare it with the issuance `params` allows at the tip
+    pub fn audit_supply(&self, params: &ChainParams) -> Result<SupplyAudit> {
+        let height = self.get_best_height()?.ok_or(Error::ChainNotInitialized)?;
+        let cf_utxos = self.db.cf_handle(CF_UTXOS)
+            .ok_or_else(|| Error::DatabaseError("CF_UTXOS not found".to_string()))?;
 
-    /// Calculate transaction hash (simplified)
-    fn calculate_tx_hash(&self, tx: &Transaction) -> TxHash {
-        use sha2::{Sha256, Digest};
-        let tx_data = bincode::serialize(tx).unwrap_or_default();
-        let hash = Sha256::digest(&tx_data);
-        TxHash::from_bytes(hash.into())
-    }
+        let mut utxo_count = 0u64;
+        let mut utxo_total = 0u64;
+        for item in self.db.iterator_cf(cf_utxos, IteratorMode::Start) {
+            let (_, data) = item.map_err(|e| Error::DatabaseError(e.to_string()))?;
+            let utxo: UtxoEntry = encoding::deserialize_versioned(&data)
+                .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+            utxo_count += 1;
+            utxo_total = utxo_total.checked_add(utxo.amount)
+                .ok_or_else(|| Error::DatabaseError("UTXO set total overflows".to_string()))?;
+        }
 
-    /// Delete block (for reorg handling)
-    pub fn delete_block(&self, height: BlockHeight) -> Result<()> {
-        let cf_blocks = self.db.cf_handle(CF_BLOCKS)
-            .ok_or_else(|| Error::DatabaseError("CF_BLOCKS not found".to_string()))?;
-        
-        self.db.delete_cf(cf_blocks, height.to_le_bytes())
-            .map_err(|e| Error::DatabaseError(e.to_string()))?;
-        Ok(())
+        Ok(SupplyAudit {
+            height,
+            utxo_count,
+            utxo_total,
+            expected_issuance: params.issued_supply(height),
+        })
     }
 
     /// Get database statistics
@@ -316,6 +859,176 @@ impl Storage {
     }
 }
 
+/// Writes for one block, committed atomically
+///
+/// Reads check the pending writes first, so later transactions in a block
+/// see the outputs and address index entries written by earlier ones.
+struct BlockBatch<'a> {
+    db: &'a DB,
+    batch: WriteBatch,
+    pending: HashMap<(&'static str, Vec<u8>), Option<Vec<u8>>>,
+}
+
+impl<'a> BlockBatch<'a> {
+    fn new(db: &'a DB) -> Self {
+        Self {
+            db,
+            batch: WriteBatch::default(),
+            pending: HashMap::new(),
+        }
+    }
+
+    fn cf(&self, name: &str) -> Result<&'a ColumnFamily> {
+        self.db.cf_handle(name)
+            .ok_or_else(|| Error::DatabaseError(format!("Column family {} not found", name)))
+    }
+
+    fn get(&self, cf: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.pending.get(&(cf, key.to_vec())) {
+            return Ok(value.clone());
+        }
+        self.db.get_cf(self.cf(cf)?, key)
+            .map_err(|e| Error::DatabaseError(e.to_string()))
+    }
+
+    fn put(&mut self, cf: &'static str, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.batch.put_cf(self.cf(cf)?, key, &value);
+        self.pending.insert((cf, key.to_vec()), Some(value));
+        Ok(())
+    }
+
+    fn delete(&mut self, cf: &'static str, key: &[u8]) -> Result<()> {
+        self.batch.delete_cf(self.cf(cf)?, key);
+        self.pending.insert((cf, key.to_vec()), None);
+        Ok(())
+    }
+
+    fn commit(self) -> Result<()> {
+        self.db.write(self.batch)
+            .map_err(|e| Error::DatabaseError(e.to_string()))
+    }
+
+    /// Add UTXO key to address index
+    fn add_to_address_index(&mut self, address: &str, utxo_key: &str) -> Result<()> {
+        // Get existing UTXO list for address
+        let mut utxo_list = match self.get(CF_ADDRESS_INDEX, address.as_bytes())? {
+            Some(data) => {
+                bincode::deserialize::<Vec<String>>(&data)
+                    .unwrap_or_default()
+            }
+            None => Vec::new(),
+        };
+        
+        // Add new UTXO key if not present
+        if !utxo_list.contains(&utxo_key.to_string()) {
+            utxo_list.push(utxo_key.to_string());
+        }
+        
+        // Save updated list
+        let data = bincode::serialize(&utxo_list)
+            .map_err(|e| Error::DatabaseError(format!("Serialization error: {}", e)))?;
+        self.put(CF_ADDRESS_INDEX, address.as_bytes(), data)
+    }
+
+    /// Remove UTXO key from address index
+    fn remove_from_address_index(&mut self, address: &str, utxo_key: &str) -> Result<()> {
+        let Some(data) = self.get(CF_ADDRESS_INDEX, address.as_bytes())? else {
+            return Ok(());
+        };
+        
+        let mut utxo_list = bincode::deserialize::<Vec<String>>(&data).unwrap_or_default();
+        utxo_list.retain(|key| key != utxo_key);
+        
+        if utxo_list.is_empty() {
+            self.delete(CF_ADDRESS_INDEX, address.as_bytes())
+        } else {
+            let data = bincode::serialize(&utxo_list)
+                .map_err(|e| Error::DatabaseError(format!("Serialization error: {}", e)))?;
+            self.put(CF_ADDRESS_INDEX, address.as_bytes(), data)
+        }
+    }
+}
+
+/// Update UTXO set, returning the entries spent
+fn update_utxos(batch: &mut BlockBatch<'_>, height: BlockHeight, tx_hash: &TxHash, tx: &Transaction) -> Result<Vec<UtxoEntry>> {
+    // Remove spent UTXOs (inputs)
+    let mut spent = Vec::new();
+    for input in &tx.inputs {
+        let utxo_key = format!("{}:{}", input.prev_tx_hash, input.prev_tx_index);
+        if let Some(data) = batch.get(CF_UTXOS, utxo_key.as_bytes())? {
+            let utxo: UtxoEntry = encoding::deserialize_versioned(&data)
+                .map_err(|e| Error::DatabaseError(format!("Deserialization error: {}", e)))?;
+            spent.push(utxo);
+        }
+        batch.delete(CF_UTXOS, utxo_key.as_bytes())?;
+    }
+    
+    // Add new UTXOs (outputs)
+    for (output_index, output) in tx.outputs.iter().enumerate() {
+        let utxo_entry = UtxoEntry {
+            tx_hash: *tx_hash,
+            output_index: output_index as u32,
+            amount: output.amount,
+            script_pubkey: output.script_pubkey.clone(),
+            block_height: height,
+            is_coinbase: tx.is_coinbase(),
+        };
+        restore_utxo(batch, &utxo_entry)?;
+    }
+    
+    Ok(spent)
+}
+
+/// Write an unspent output and index it by address
+fn restore_utxo(batch: &mut BlockBatch<'_>, utxo: &UtxoEntry) -> Result<()> {
+    let utxo_key = format!("{}:{}", utxo.tx_hash, utxo.output_index);
+    batch.put(CF_UTXOS, utxo_key.as_bytes(), encoding::serialize_versioned(utxo))?;
+    batch.add_to_address_index(&output_address(&utxo.script_pubkey), &utxo_key)
+}
+
+/// Remove a transaction's outputs and their address index entries
+fn remove_outputs(batch: &mut BlockBatch<'_>, tx_hash: &TxHash, tx: &Transaction) -> Result<()> {
+    for (output_index, output) in tx.outputs.iter().enumerate() {
+        let utxo_key = format!("{}:{}", tx_hash, output_index);
+        batch.delete(CF_UTXOS, utxo_key.as_bytes())?;
+        batch.remove_from_address_index(&output_address(&output.script_pubkey), &utxo_key)?;
+    }
+    Ok(())
+}
+
+/// Height a `CF_BLOCKS` key encodes
+fn stored_height(key: &[u8]) -> Result<BlockHeight> {
+    key.try_into()
+        .map(BlockHeight::from_le_bytes)
+        .map_err(|e| Error::DatabaseError(format!("Invalid height bytes: {:?}", e)))
+}
+
+/// Decode a versioned block record, if it is one for `height`
+fn decode_versioned_block(data: &[u8], height: BlockHeight) -> Option<Block> {
+    encoding::deserialize_versioned::<Block>(data)
+        .ok()
+        .filter(|block| block.height == height)
+}
+
+/// Decode a bincode block record from before the versioned encoding, if it
+/// is one for `height` and uses every byte
+fn decode_bincode_block(data: &[u8], height: BlockHeight) -> Option<Block> {
+    bincode::deserialize::<Block>(data)
+        .ok()
+        .filter(|block| block.height == height)
+        .filter(|block| bincode::serialized_size(block).ok() == Some(data.len() as u64))
+}
+
+/// Address an output is indexed under
+///
+/// Standard outputs map to their legacy P2PKH address, whatever the
+/// network; legacy outputs stored the address bytes directly.
+fn output_address(script_pubkey: &[u8]) -> String {
+    Address::from_script_pubkey(script_pubkey)
+        .map(|address| address.to_legacy())
+        .unwrap_or_else(|| String::from_utf8_lossy(script_pubkey).to_string())
+}
+
 /// Transaction metadata with block info
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionMetadata {
@@ -325,6 +1038,137 @@ pub struct TransactionMetadata {
     pub tx_index: u32,
 }
 
+impl Encodable for UtxoEntry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.tx_hash.encode(out);
+        out.extend_from_slice(&self.output_index.to_le_bytes());
+        out.extend_from_slice(&self.amount.to_le_bytes());
+        encoding::write_var_bytes(out, &self.script_pubkey);
+        out.extend_from_slice(&self.block_height.to_le_bytes());
+        out.push(self.is_coinbase as u8);
+    }
+}
+
+impl Decodable for UtxoEntry {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(UtxoEntry {
+            tx_hash: TxHash::decode(reader)?,
+            output_index: reader.read_u32()?,
+            amount: reader.read_u64()?,
+            script_pubkey: reader.read_var_bytes()?,
+            block_height: reader.read_u64()?,
+            is_coinbase: reader.read_u8()? != 0,
+        })
+    }
+}
+
+impl Encodable for TransactionMetadata {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.transaction.encode(out);
+        out.extend_from_slice(&self.block_height.to_le_bytes());
+        self.block_hash.encode(out);
+        out.extend_from_slice(&self.tx_index.to_le_bytes());
+    }
+}
+
+impl Decodable for TransactionMetadata {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TransactionMetadata {
+            transaction: Transaction::decode(reader)?,
+            block_height: reader.read_u64()?,
+            block_hash: BlockHash::decode(reader)?,
+            tx_index: reader.read_u32()?,
+        })
+    }
+}
+
+impl Encodable for BlockIndexEntry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.hash.encode(out);
+        self.prev_hash.encode(out);
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.extend_from_slice(&self.bits.to_le_bytes());
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&self.chainwork.to_be_bytes());
+        out.push(self.invalid as u8);
+    }
+}
+
+impl Decodable for BlockIndexEntry {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(BlockIndexEntry {
+            hash: BlockHash::decode(reader)?,
+            prev_hash: BlockHash::decode(reader)?,
+            height: reader.read_u64()?,
+            bits: reader.read_u32()?,
+            timestamp: reader.read_u32()?,
+            chainwork: U256::from_be_bytes(reader.read_array()?),
+            invalid: reader.read_u8()? != 0,
+        })
+    }
+}
+
+impl Encodable for BlockUndo {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encoding::write_varint(out, self.spent.len() as u64);
+        for utxo in &self.spent {
+            utxo.encode(out);
+        }
+    }
+}
+
+impl Decodable for BlockUndo {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        // tx hash, index, amount, empty script, height, coinbase flag
+        let count = reader.read_count(32 + 4 + 8 + 1 + 8 + 1)?;
+        let mut spent = Vec::with_capacity(count);
+        for _ in 0..count {
+            spent.push(UtxoEntry::decode(reader)?);
+        }
+        Ok(BlockUndo { spent })
+    }
+}
+
+impl Encodable for FilterEntry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.filter.block_hash.encode(out);
+        self.header.encode(out);
+        encoding::write_var_bytes(out, &self.filter.content);
+    }
+}
+
+impl Decodable for FilterEntry {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+        let block_hash = BlockHash::decode(reader)?;
+        let header = BlockHash::decode(reader)?;
+        let content = reader.read_var_bytes()?;
+        Ok(FilterEntry { filter: BlockFilter { block_hash, content }, header })
+    }
+}
+
+/// UTXO set total against the schedule's issuance at the tip
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupplyAudit {
+    pub height: BlockHeight,
+    pub utxo_count: u64,
+    /// Sum of every unspent output
+    pub utxo_total: u64,
+    /// Total subsidy of blocks `0..=height`
+    pub expected_issuance: u64,
+}
+
+impl SupplyAudit {
+    /// More coins exist than the schedule has issued
+    pub fn is_inflated(&self) -> bool {
+        self.utxo_total > self.expected_issuance
+    }
+
+    /// Issued coins missing from the UTXO set: unclaimed subsidy and fees
+    pub fn unclaimed(&self) -> u64 {
+        self.expected_issuance.saturating_sub(self.utxo_total)
+    }
+}
+
 /// Storage statistics
 #[derive(Debug, Clone)]
 pub struct StorageStats {
@@ -335,9 +1179,14 @@ pub struct StorageStats {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::block::BlockHeader;
+    use crate::block::{BlockHeader, TEST_MINER_ADDRESS};
+    use crate::types::Amount;
     use tempfile::TempDir;
 
+    fn coinbase_tx(height: BlockHeight, address: &str, amount: Amount) -> Transaction {
+        Block::create_coinbase_tx(height, address, amount, &ChainParams::mainnet()).unwrap()
+    }
+
     fn create_test_storage() -> (Storage, TempDir) {
         let temp_dir = TempDir::new().unwrap();
         let storage = Storage::new(temp_dir.path()).unwrap();
@@ -359,6 +1208,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_transaction_proof_lookup() {
+        let (storage, _temp_dir) = create_test_storage();
+        let txs: Vec<Transaction> = (0..3).map(|i| coinbase_tx(i, TEST_MINER_ADDRESS, 1)).collect();
+        let block = Block::new(1, BlockHash::zero(), txs.clone(), 1701657600, 0x207fffff, 0);
+        storage.put_block(0, &block).unwrap();
+
+        let proof = storage.get_transaction_proof(&txs[2].hash()).unwrap().unwrap();
+        assert_eq!(proof.header, block.header);
+        assert_eq!(proof.height, 0);
+        assert_eq!(proof.proof.index, 2);
+        assert!(proof.verify());
+
+        assert!(storage.get_transaction_proof(&TxHash::from_bytes([9u8; 32])).unwrap().is_none());
+    }
+
     #[test]
     fn test_storage_creation() {
         let (_storage, _temp_dir) = create_test_storage();
@@ -389,6 +1254,47 @@ mod tests {
         assert_eq!(retrieved.unwrap().height, 0);
     }
 
+    #[test]
+    fn test_transaction_round_trip() {
+        let (storage, _temp_dir) = create_test_storage();
+        let coinbase = coinbase_tx(0, TEST_MINER_ADDRESS, 50);
+        let tx_hash = coinbase.hash();
+        let block = Block::new(1, BlockHash::zero(), vec![coinbase], 1701657600, 0x1d00ffff, 0);
+
+        storage.put_block(0, &block).unwrap();
+
+        let stored = storage.get_block(0).unwrap().unwrap();
+        assert_eq!(stored.hash(), block.hash());
+        assert_eq!(stored.transactions[0].hash(), tx_hash);
+
+        let meta = storage.get_transaction(&tx_hash).unwrap().unwrap();
+        assert_eq!(meta.transaction.hash(), tx_hash);
+        assert_eq!(meta.block_hash, block.hash());
+        assert_eq!(meta.tx_index, 0);
+
+        let utxos = storage.get_utxos_for_address(TEST_MINER_ADDRESS, &ChainParams::mainnet()).unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].amount, 50);
+
+        let utxo = storage.get_utxo(&tx_hash, 0).unwrap().unwrap();
+        assert_eq!(utxo.amount, 50);
+        assert!(utxo.is_coinbase);
+        assert!(storage.get_utxo(&tx_hash, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_p2pkh_outputs_indexed_by_address() {
+        let (storage, _temp_dir) = create_test_storage();
+        let address = script::pubkey_hash_to_address(&[0x5a; 20]);
+        let coinbase = coinbase_tx(0, &address, 75);
+        assert!(script::extract_p2pkh(&coinbase.outputs[0].script_pubkey).is_some());
+
+        let block = Block::new(1, BlockHash::zero(), vec![coinbase], 1701657600, 0x1d00ffff, 0);
+        storage.put_block(0, &block).unwrap();
+
+        assert_eq!(storage.get_balance(&address, &ChainParams::mainnet()).unwrap(), 75);
+    }
+
     #[test]
     fn test_best_height() {
         let (storage, _temp_dir) = create_test_storage();
@@ -413,4 +1319,407 @@ mod tests {
         assert_eq!(stats.best_height, 1);
         assert_eq!(stats.total_blocks, 2);
     }
+
+    #[test]
+    fn test_audit_supply() {
+        let (storage, _temp_dir) = create_test_storage();
+        let params = ChainParams::regtest();
+        assert!(matches!(storage.audit_supply(&params), Err(Error::ChainNotInitialized)));
+
+        let genesis = coinbase_tx(0, TEST_MINER_ADDRESS, params.get_block_reward(0));
+        storage.put_block(0, &Block::new(1, BlockHash::zero(), vec![genesis], 1701657600, 0x207fffff, 0)).unwrap();
+        // Block 1 leaves part of its subsidy unclaimed
+        let coinbase = coinbase_tx(1, TEST_MINER_ADDRESS, params.get_block_reward(1) - 10);
+        storage.put_block(1, &Block::new(1, BlockHash::zero(), vec![coinbase], 1701657601, 0x207fffff, 1)).unwrap();
+
+        let audit = storage.audit_supply(&params).unwrap();
+        assert_eq!(audit.height, 1);
+        assert_eq!(audit.utxo_count, 2);
+        assert_eq!(audit.expected_issuance, params.issued_supply(1));
+        assert_eq!(audit.unclaimed(), 10);
+        assert!(!audit.is_inflated());
+
+        // A coinbase paying more than the schedule shows up as inflation
+        let coinbase = coinbase_tx(2, TEST_MINER_ADDRESS, params.get_block_reward(2) + 20);
+        storage.put_block(2, &Block::new(1, BlockHash::zero(), vec![coinbase], 1701657602, 0x207fffff, 2)).unwrap();
+        let audit = storage.audit_supply(&params).unwrap();
+        assert_eq!(audit.utxo_total, audit.expected_issuance + 10);
+        assert!(audit.is_inflated());
+    }
+
+    /// Every key/value in a column family, for before/after comparisons
+    fn dump_cf(storage: &Storage, name: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let cf = storage.db.cf_handle(name).unwrap();
+        storage.db.iterator_cf(cf, IteratorMode::Start)
+            .map(|item| {
+                let (key, value) = item.unwrap();
+                (key.to_vec(), value.to_vec())
+            })
+            .collect()
+    }
+
+    fn spend(prev: &Transaction, index: u32, outputs: Vec<crate::transaction::TxOutput>) -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![crate::transaction::TxInput {
+                prev_tx_hash: prev.hash(),
+                prev_tx_index: index,
+                script: Vec::new(),
+                sequence: 0xFFFFFFFF,
+            }],
+            outputs,
+            locktime: 0,
+        }
+    }
+
+    #[test]
+    fn test_connect_then_disconnect_restores_utxo_set() {
+        use crate::transaction::TxOutput;
+
+        let (storage, _temp_dir) = create_test_storage();
+        let owner = script::pubkey_hash_to_address(&[0x11; 20]);
+        let payee = script::pubkey_hash_to_address(&[0x22; 20]);
+        let output = |amount, address: &str| TxOutput {
+            amount,
+            script_pubkey: script::address_to_script_pubkey(address, &ChainParams::mainnet()).unwrap(),
+        };
+
+        let coinbase = coinbase_tx(0, &owner, 1_000);
+        let genesis = Block::new(1, BlockHash::zero(), vec![coinbase.clone()], 1701657600, 0x1d00ffff, 0);
+        storage.put_block(0, &genesis).unwrap();
+
+        let utxos_before = dump_cf(&storage, CF_UTXOS);
+        let addresses_before = dump_cf(&storage, CF_ADDRESS_INDEX);
+        let txs_before = dump_cf(&storage, CF_TRANSACTIONS);
+
+        // Spend the genesis output, then spend the result again in the same block
+        let tx1 = spend(&coinbase, 0, vec![output(600, &payee), output(400, &owner)]);
+        let tx2 = spend(&tx1, 0, vec![output(600, &owner)]);
+        let block = Block::new(
+            1,
+            genesis.hash(),
+            vec![coinbase_tx(1, &payee, 50), tx1.clone(), tx2.clone()],
+            1701657601,
+            0x1d00ffff,
+            1,
+        );
+        storage.put_block(1, &block).unwrap();
+
+        assert!(storage.get_utxo(&coinbase.hash(), 0).unwrap().is_none());
+        assert_eq!(storage.get_balance(&owner, &ChainParams::mainnet()).unwrap(), 1_000);
+        let undo = storage.get_block_undo(&block.hash()).unwrap().unwrap();
+        assert_eq!(undo.spent.len(), 2);
+        assert_eq!(undo.spent[0].tx_hash, coinbase.hash());
+
+        let disconnected = storage.disconnect_block().unwrap();
+        assert_eq!(disconnected.hash(), block.hash());
+
+        assert_eq!(dump_cf(&storage, CF_UTXOS), utxos_before);
+        assert_eq!(dump_cf(&storage, CF_ADDRESS_INDEX), addresses_before);
+        assert_eq!(dump_cf(&storage, CF_TRANSACTIONS), txs_before);
+        assert_eq!(storage.get_best_height().unwrap(), Some(0));
+        assert!(storage.get_block(1).unwrap().is_none());
+        assert!(storage.get_block_by_hash(&block.hash()).unwrap().is_none());
+        assert!(storage.get_block_undo(&block.hash()).unwrap().is_none());
+        assert_eq!(storage.get_balance(&payee, &ChainParams::mainnet()).unwrap(), 0);
+
+        // Disconnecting genesis empties the chain
+        storage.disconnect_block().unwrap();
+        assert_eq!(storage.get_best_height().unwrap(), None);
+        assert!(dump_cf(&storage, CF_UTXOS).is_empty());
+        assert!(dump_cf(&storage, CF_ADDRESS_INDEX).is_empty());
+    }
+
+    #[test]
+    fn test_block_undo_round_trip() {
+        let undo = BlockUndo {
+            spent: vec![UtxoEntry {
+                tx_hash: TxHash::from_bytes([3; 32]),
+                output_index: 1,
+                amount: 42,
+                script_pubkey: vec![0x51],
+                block_height: 7,
+                is_coinbase: true,
+            }],
+        };
+        let bytes = encoding::serialize_versioned(&undo);
+        assert_eq!(encoding::deserialize_versioned::<BlockUndo>(&bytes).unwrap(), undo);
+    }
+
+    /// Replay a versioned but unbatched write that stopped after `applied` transactions
+    fn torn_write(storage: &Storage, height: BlockHeight, block: &Block, applied: usize) {
+        let mut batch = BlockBatch::new(&storage.db);
+        batch.put(CF_BLOCKS, &height.to_le_bytes(), encoding::serialize_versioned(block)).unwrap();
+        batch.put(CF_BLOCK_INDEX, block.hash().as_bytes(), height.to_le_bytes().to_vec()).unwrap();
+        for (idx, tx) in block.transactions.iter().enumerate().take(applied) {
+            let meta = TransactionMetadata {
+                transaction: tx.clone(),
+                block_height: height,
+                block_hash: block.hash(),
+                tx_index: idx as u32,
+            };
+            batch.put(CF_TRANSACTIONS, tx.hash().as_bytes(), encoding::serialize_versioned(&meta)).unwrap();
+            update_utxos(&mut batch, height, &tx.hash(), tx).unwrap();
+        }
+        batch.commit().unwrap();
+    }
+
+    #[test]
+    fn test_torn_block_write_rolled_back_on_open() {
+        use crate::transaction::TxOutput;
+
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let owner = script::pubkey_hash_to_address(&[0x33; 20]);
+        let payee = script::pubkey_hash_to_address(&[0x44; 20]);
+        let output = |amount, address: &str| TxOutput {
+            amount,
+            script_pubkey: script::address_to_script_pubkey(address, &ChainParams::mainnet()).unwrap(),
+        };
+
+        let coinbase = coinbase_tx(0, &owner, 1_000);
+        let genesis = Block::new(1, BlockHash::zero(), vec![coinbase.clone()], 1701657600, 0x1d00ffff, 0);
+        storage.put_block(0, &genesis).unwrap();
+        assert!(storage.check_consistency().unwrap().is_empty());
+
+        let utxos_before = dump_cf(&storage, CF_UTXOS);
+        let addresses_before = dump_cf(&storage, CF_ADDRESS_INDEX);
+        let txs_before = dump_cf(&storage, CF_TRANSACTIONS);
+
+        let tx1 = spend(&coinbase, 0, vec![output(600, &payee), output(400, &owner)]);
+        let tx2 = spend(&tx1, 0, vec![output(600, &owner)]);
+        let block = Block::new(
+            1,
+            genesis.hash(),
+            vec![coinbase_tx(1, &payee, 50), tx1, tx2],
+            1701657601,
+            0x1d00ffff,
+            1,
+        );
+
+        // Crash after two of three transactions, before the tip moved
+        torn_write(&storage, 1, &block, 2);
+        assert_eq!(storage.get_best_height().unwrap(), Some(0));
+        assert!(storage.get_utxo(&coinbase.hash(), 0).unwrap().is_none());
+        drop(storage);
+
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        assert!(storage.get_block(1).unwrap().is_none());
+        assert!(storage.get_block_by_hash(&block.hash()).unwrap().is_none());
+        assert_eq!(dump_cf(&storage, CF_UTXOS), utxos_before);
+        assert_eq!(dump_cf(&storage, CF_ADDRESS_INDEX), addresses_before);
+        assert_eq!(dump_cf(&storage, CF_TRANSACTIONS), txs_before);
+
+        // The block connects cleanly afterwards
+        storage.put_block(1, &block).unwrap();
+        assert_eq!(storage.get_balance(&owner, &ChainParams::mainnet()).unwrap(), 1_000);
+        assert!(storage.check_consistency().unwrap().is_empty());
+    }
+
+    /// Write `blocks` the way versions before the versioned encoding did:
+    /// bincode records, with transactions and outputs keyed by the
+    /// placeholder zero hash those versions computed
+    fn write_unversioned(storage: &Storage, blocks: &[Block], best_height: Option<BlockHeight>) {
+        let put = |name, key: &[u8], value: Vec<u8>| {
+            storage.db.put_cf(storage.db.cf_handle(name).unwrap(), key, value).unwrap();
+        };
+        let placeholder = TxHash::from_bytes([0u8; 32]);
+        for block in blocks {
+            put(CF_BLOCKS, &block.height.to_le_bytes(), bincode::serialize(block).unwrap());
+            put(CF_BLOCK_INDEX, block.hash().as_bytes(), block.height.to_le_bytes().to_vec());
+            for (idx, tx) in block.transactions.iter().enumerate() {
+                let meta = TransactionMetadata {
+                    transaction: tx.clone(),
+                    block_height: block.height,
+                    block_hash: block.hash(),
+                    tx_index: idx as u32,
+                };
+                put(CF_TRANSACTIONS, placeholder.as_bytes(), bincode::serialize(&meta).unwrap());
+                for (index, output) in tx.outputs.iter().enumerate() {
+                    let utxo = (placeholder, index as u32, output.amount, &output.script_pubkey, block.height);
+                    put(CF_UTXOS, format!("{}:{}", placeholder, index).as_bytes(), bincode::serialize(&utxo).unwrap());
+                }
+            }
+        }
+        if let Some(height) = best_height {
+            put(CF_METADATA, BEST_HEIGHT_KEY, height.to_le_bytes().to_vec());
+        }
+    }
+
+    #[test]
+    fn test_unversioned_database_rebuilt_on_open() {
+        let owner = script::pubkey_hash_to_address(&[0x33; 20]);
+        let genesis = Block::new(
+            1,
+            BlockHash::zero(),
+            vec![coinbase_tx(0, &owner, 1_000)],
+            1701657600,
+            0x1d00ffff,
+            0,
+        );
+        let next = Block::new(
+            1,
+            genesis.hash(),
+            vec![coinbase_tx(1, &owner, 500)],
+            1701657601,
+            0x1d00ffff,
+            1,
+        );
+        let (expected, _expected_dir) = create_test_storage();
+        expected.put_block(0, &genesis).unwrap();
+        expected.put_block(1, &next).unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        write_unversioned(&storage, &[genesis, next.clone()], Some(1));
+        assert!(storage.get_block(0).is_err());
+        drop(storage);
+
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        assert_eq!(storage.get_best_height().unwrap(), Some(1));
+        assert_eq!(storage.get_block(1).unwrap().unwrap().hash(), next.hash());
+        assert_eq!(storage.get_balance(&owner, &ChainParams::mainnet()).unwrap(), 1_500);
+        for name in [
+            CF_BLOCKS, CF_BLOCK_INDEX, CF_TRANSACTIONS, CF_UTXOS, CF_ADDRESS_INDEX, CF_METADATA,
+            CF_CHAIN_INDEX, CF_UNDO, CF_BLOCK_FILTERS,
+        ] {
+            assert_eq!(dump_cf(&storage, name), dump_cf(&expected, name), "{}", name);
+        }
+        assert!(storage.check_consistency().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_torn_unversioned_block_dropped_on_open() {
+        let owner = script::pubkey_hash_to_address(&[0x33; 20]);
+        let coinbase = coinbase_tx(0, &owner, 1_000);
+        let genesis = Block::new(1, BlockHash::zero(), vec![coinbase.clone()], 1701657600, 0x1d00ffff, 0);
+        let block = Block::new(
+            1,
+            genesis.hash(),
+            vec![coinbase_tx(1, &owner, 50), spend(&coinbase, 0, coinbase.outputs.clone())],
+            1701657601,
+            0x1d00ffff,
+            1,
+        );
+        let (expected, _expected_dir) = create_test_storage();
+        expected.put_block(0, &genesis).unwrap();
+
+        // Crash after the block and its transactions were written, before the tip moved
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        write_unversioned(&storage, &[genesis, block.clone()], Some(0));
+        drop(storage);
+
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        assert_eq!(storage.get_best_height().unwrap(), Some(0));
+        assert!(storage.get_block(1).unwrap().is_none());
+        assert!(storage.get_block_by_hash(&block.hash()).unwrap().is_none());
+        for name in [CF_BLOCKS, CF_TRANSACTIONS, CF_UTXOS, CF_ADDRESS_INDEX, CF_UNDO, CF_BLOCK_FILTERS] {
+            assert_eq!(dump_cf(&storage, name), dump_cf(&expected, name), "{}", name);
+        }
+
+        // The block connects cleanly afterwards
+        storage.put_block(1, &block).unwrap();
+        assert_eq!(storage.get_balance(&owner, &ChainParams::mainnet()).unwrap(), 1_050);
+        assert!(storage.check_consistency().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_consistency_check_rewinds_tip_and_rebuilds_index() {
+        let (storage, _temp_dir) = create_test_storage();
+        for height in 0..3 {
+            storage.put_block(height, &create_test_block(height)).unwrap();
+        }
+
+        // Tip points at block data that never made it to disk
+        let cf_blocks = storage.db.cf_handle(CF_BLOCKS).unwrap();
+        storage.db.delete_cf(cf_blocks, 2u64.to_le_bytes()).unwrap();
+        // Databases from before the block index
+        let cf_chain = storage.db.cf_handle(CF_CHAIN_INDEX).unwrap();
+        for height in 0..2 {
+            storage.db.delete_cf(cf_chain, create_test_block(height).hash().as_bytes()).unwrap();
+        }
+
+        assert_eq!(
+            storage.check_consistency().unwrap(),
+            vec![
+                ConsistencyRepair::RewoundTip { from: 2, to: Some(1) },
+                ConsistencyRepair::RebuiltChainIndex { blocks: 2 },
+            ]
+        );
+        assert_eq!(storage.get_best_height().unwrap(), Some(1));
+        let tip = storage.get_block_index(&create_test_block(1).hash()).unwrap().unwrap();
+        assert_eq!(tip.height, 1);
+        assert!(storage.check_consistency().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_block_filters_follow_connect_and_disconnect() {
+        use crate::transaction::TxOutput;
+
+        let (storage, _temp_dir) = create_test_storage();
+        let owner = script::pubkey_hash_to_address(&[0x55; 20]);
+        let payee = script::pubkey_hash_to_address(&[0x66; 20]);
+        let owner_script = script::address_to_script_pubkey(&owner, &ChainParams::mainnet()).unwrap();
+        let payee_script = script::address_to_script_pubkey(&payee, &ChainParams::mainnet()).unwrap();
+
+        let coinbase = coinbase_tx(0, &owner, 1_000);
+        let genesis = Block::new(1, BlockHash::zero(), vec![coinbase.clone()], 1701657600, 0x1d00ffff, 0);
+        storage.put_block(0, &genesis).unwrap();
+
+        let tx = spend(&coinbase, 0, vec![TxOutput { amount: 1_000, script_pubkey: payee_script.clone() }]);
+        let miner_coinbase = coinbase_tx(1, TEST_MINER_ADDRESS, 50);
+        let block = Block::new(1, genesis.hash(), vec![miner_coinbase.clone(), tx], 1701657601, 0x1d00ffff, 1);
+        storage.put_block(1, &block).unwrap();
+
+        // Created and spent scripts both match
+        let filter = storage.get_block_filter(1).unwrap().unwrap();
+        assert_eq!(filter.block_hash, block.hash());
+        assert_eq!(filter.element_count().unwrap(), 3);
+        assert!(filter.match_any(&[&payee_script]).unwrap());
+        assert!(filter.match_any(&[&owner_script]).unwrap());
+        assert!(filter.match_any(&[&miner_coinbase.outputs[0].script_pubkey]).unwrap());
+
+        // Headers chain from the zero hash
+        let genesis_header = storage.get_filter_header(0).unwrap().unwrap();
+        let genesis_filter = storage.get_block_filter(0).unwrap().unwrap();
+        assert_eq!(genesis_header, genesis_filter.header(&BlockHash::zero()));
+        assert_eq!(storage.get_filter_header(1).unwrap().unwrap(), filter.header(&genesis_header));
+
+        assert_eq!(storage.get_block_filters(0, 1).unwrap(), vec![genesis_filter, filter.clone()]);
+        assert_eq!(storage.get_filter_headers(1, 1).unwrap(), vec![filter.header(&genesis_header)]);
+        assert!(storage.get_block_filters(1, 2).is_err());
+        assert!(storage.get_filter_headers(1, 0).is_err());
+
+        storage.disconnect_block().unwrap();
+        assert!(storage.get_block_filter(1).unwrap().is_none());
+        assert!(storage.get_filter_header(1).unwrap().is_none());
+        assert_eq!(storage.get_filter_header(0).unwrap(), Some(genesis_header));
+
+        // Reconnecting restores the same chain
+        storage.put_block(1, &block).unwrap();
+        assert_eq!(storage.get_block_filter(1).unwrap(), Some(filter));
+    }
+
+    #[test]
+    fn test_consistency_check_rebuilds_block_filters() {
+        let (storage, _temp_dir) = create_test_storage();
+        for height in 0..3 {
+            storage.put_block(height, &create_test_block(height)).unwrap();
+        }
+        let headers = storage.get_filter_headers(0, 2).unwrap();
+
+        // Databases from before compact filters
+        let cf_filters = storage.db.cf_handle(CF_BLOCK_FILTERS).unwrap();
+        for height in 0..3u64 {
+            storage.db.delete_cf(cf_filters, height.to_le_bytes()).unwrap();
+        }
+
+        assert_eq!(
+            storage.check_consistency().unwrap(),
+            vec![ConsistencyRepair::RebuiltBlockFilters { blocks: 3 }]
+        );
+        assert_eq!(storage.get_filter_headers(0, 2).unwrap(), headers);
+        assert!(storage.check_consistency().unwrap().is_empty());
+    }
 }
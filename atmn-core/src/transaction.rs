@@ -2,8 +2,13 @@
 // Transaction structure and validation
 
 use serde::{Deserialize, Serialize};
-use crate::types::{TxHash, Amount};
-use crate::error::Result;
+use std::collections::HashSet;
+use crate::chain_params::ChainParams;
+use crate::consensus::sha256d;
+use crate::encoding::{self, Encodable};
+use crate::script::{self, TransactionSignatureChecker};
+use crate::types::{TxHash, Amount, BlockHeight, OutPoint, Timestamp};
+use crate::error::{Error, Result};
 
 /// Transaction Input
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +19,26 @@ pub struct TxInput {
     pub sequence: u32,
 }
 
+impl TxInput {
+    /// The output this input spends
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.prev_tx_hash, self.prev_tx_index)
+    }
+
+    /// BIP68 relative lock encoded in `sequence`, if any
+    pub fn relative_lock(&self) -> Option<RelativeLock> {
+        if self.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return None;
+        }
+        let value = self.sequence & SEQUENCE_LOCKTIME_MASK;
+        if self.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            Some(RelativeLock::Seconds(value << SEQUENCE_LOCKTIME_GRANULARITY))
+        } else {
+            Some(RelativeLock::Blocks(value))
+        }
+    }
+}
+
 /// Transaction Output
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TxOutput {
@@ -30,10 +55,54 @@ pub struct Transaction {
     pub locktime: u32,
 }
 
+/// Minimum coinbase script length in bytes
+pub const MIN_COINBASE_SCRIPT_SIZE: usize = 2;
+
+/// Maximum coinbase script length in bytes
+pub const MAX_COINBASE_SCRIPT_SIZE: usize = 100;
+
+/// Locktimes below this are block heights, at or above it Unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Input sequence that opts out of locktime and relative locks
+pub const SEQUENCE_FINAL: u32 = 0xFFFF_FFFF;
+
+/// Sequence bit that disables the input's relative lock (BIP68)
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// Sequence bit selecting a time-based rather than height-based relative lock
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Sequence bits holding the relative lock value
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_FFFF;
+
+/// Time-based relative locks count in units of 2^9 = 512 seconds
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
+/// Highest input sequence that marks a transaction as replaceable (BIP125)
+pub const MAX_BIP125_RBF_SEQUENCE: u32 = 0xFFFF_FFFD;
+
+/// Lowest transaction version that enforces relative locks
+pub const RELATIVE_LOCK_MIN_VERSION: u32 = 2;
+
+/// Relative lock of one input, from its sequence number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeLock {
+    /// Spendable this many blocks after the spent output confirmed
+    Blocks(u32),
+    /// Spendable this many seconds (median-time-past) after the spent output confirmed
+    Seconds(u32),
+}
+
 impl Transaction {
+    /// Canonical transaction id: double SHA-256 of the serialized transaction
     pub fn hash(&self) -> TxHash {
-        // Double SHA256 of serialized tx
-        TxHash::from_bytes([0u8; 32])  // TODO: Implement hashing
+        TxHash::from_bytes(sha256d(&self.serialize()).0)
+    }
+
+    /// Consensus serialization (see `encoding` for the layout)
+    pub fn serialize(&self) -> Vec<u8> {
+        encoding::serialize(self)
     }
 
     pub fn is_coinbase(&self) -> bool {
@@ -41,23 +110,109 @@ impl Transaction {
         TxHash::from_bytes([0u8; 32]) == self.inputs[0].prev_tx_hash
     }
 
-    pub fn total_input_amount(&self) -> Amount {
-        // TODO: Sum input values
-        0
+    /// Whether the absolute locktime allows inclusion in a block at `height`
+    /// whose previous block has median-time-past `median_time_past`
+    ///
+    /// Locktime 0 or every input at `SEQUENCE_FINAL` means no lock.
+    pub fn is_final(&self, height: BlockHeight, median_time_past: Timestamp) -> bool {
+        if self.locktime == 0 || self.inputs.iter().all(|i| i.sequence == SEQUENCE_FINAL) {
+            return true;
+        }
+        if self.locktime < LOCKTIME_THRESHOLD {
+            (self.locktime as BlockHeight) < height
+        } else {
+            self.locktime < median_time_past
+        }
+    }
+
+    /// Relative locks of each input (BIP68), empty for versions that don't enforce them
+    pub fn relative_locks(&self) -> Vec<Option<RelativeLock>> {
+        if self.version < RELATIVE_LOCK_MIN_VERSION || self.is_coinbase() {
+            return Vec::new();
+        }
+        self.inputs.iter().map(TxInput::relative_lock).collect()
+    }
+
+    /// Whether the sender allows this transaction to be replaced in the mempool
+    pub fn signals_rbf(&self) -> bool {
+        self.inputs.iter().any(|input| input.sequence <= MAX_BIP125_RBF_SEQUENCE)
     }
 
     pub fn total_output_amount(&self) -> Amount {
         self.outputs.iter().map(|o| o.amount).sum()
     }
 
-    pub fn is_valid(&self) -> Result<()> {
-        // TODO: Implement transaction validation
+    /// Context-free structural checks (no chain parameters needed)
+    pub fn check_structure(&self) -> Result<()> {
+        if self.inputs.is_empty() || self.outputs.is_empty() {
+            return Err(Error::InvalidTransaction);
+        }
+
+        // Output values must not overflow
+        let mut total: Amount = 0;
+        for output in &self.outputs {
+            total = total.checked_add(output.amount).ok_or(Error::InvalidAmount)?;
+        }
+
+        // Each outpoint may only be spent once
+        let mut seen = HashSet::with_capacity(self.inputs.len());
+        for input in &self.inputs {
+            if !seen.insert((input.prev_tx_hash, input.prev_tx_index)) {
+                return Err(Error::DuplicateInput);
+            }
+        }
+
+        if self.is_coinbase() {
+            let script_len = self.inputs[0].script.len();
+            if !(MIN_COINBASE_SCRIPT_SIZE..=MAX_COINBASE_SCRIPT_SIZE).contains(&script_len) {
+                return Err(Error::InvalidTransaction);
+            }
+        } else {
+            // Only the coinbase may reference the null outpoint
+            let null_hash = TxHash::from_bytes([0u8; 32]);
+            if self.inputs.iter().any(|i| i.prev_tx_hash == null_hash) {
+                return Err(Error::InvalidTransaction);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Context-free validation against consensus limits
+    pub fn is_valid(&self, params: &ChainParams) -> Result<()> {
+        if self.size() > params.max_tx_size as usize {
+            return Err(Error::TransactionTooLarge);
+        }
+
+        self.check_structure()?;
+
+        if self.total_output_amount() > params.total_supply {
+            return Err(Error::InvalidAmount);
+        }
+
+        Ok(())
+    }
+
+    /// Verify every input script against the output it spends
+    ///
+    /// `spent_outputs[i]` must be the output referenced by `inputs[i]`.
+    /// Signatures are checked against the network in `params`.
+    pub fn verify_scripts(&self, spent_outputs: &[TxOutput], params: &ChainParams) -> Result<()> {
+        if spent_outputs.len() != self.inputs.len() {
+            return Err(Error::InvalidTransaction);
+        }
+
+        for (index, (input, spent)) in self.inputs.iter().zip(spent_outputs).enumerate() {
+            let checker = TransactionSignatureChecker::new(self, index, params.network_id);
+            script::verify_script(&input.script, &spent.script_pubkey, &checker)?;
+        }
+
         Ok(())
     }
 
+    /// Serialized size in bytes
     pub fn size(&self) -> usize {
-        // TODO: Calculate serialized size
-        0
+        self.encoded_len()
     }
 }
 
@@ -65,6 +220,23 @@ impl Transaction {
 mod tests {
     use super::*;
 
+    fn create_test_transaction() -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TxInput {
+                prev_tx_hash: TxHash::from_bytes([1u8; 32]),
+                prev_tx_index: 0,
+                script: vec![0xab; 10],
+                sequence: 0xFFFFFFFF,
+            }],
+            outputs: vec![TxOutput {
+                amount: 1_000_000,
+                script_pubkey: vec![0xcd; 25],
+            }],
+            locktime: 0,
+        }
+    }
+
     #[test]
     fn test_transaction_creation() {
         let tx = Transaction {
@@ -75,4 +247,121 @@ mod tests {
         };
         assert!(tx.is_coinbase() || !tx.is_coinbase());
     }
+
+    #[test]
+    fn test_hash_is_double_sha256_of_serialization() {
+        let tx = create_test_transaction();
+        assert_eq!(tx.hash().as_bytes(), &sha256d(&tx.serialize()).0);
+        assert_ne!(tx.hash(), TxHash::from_bytes([0u8; 32]));
+
+        let mut other = tx.clone();
+        other.outputs[0].amount += 1;
+        assert_ne!(tx.hash(), other.hash());
+    }
+
+    #[test]
+    fn test_size_matches_serialization() {
+        let mut tx = create_test_transaction();
+        assert_eq!(tx.size(), tx.serialize().len());
+        // 4 + 1 + (32 + 4 + 1 + 10 + 4) + 1 + (8 + 1 + 25) + 4
+        assert_eq!(tx.size(), 95);
+
+        tx.inputs[0].script = vec![0u8; 300];
+        assert_eq!(tx.size(), tx.serialize().len());
+    }
+
+    #[test]
+    fn test_valid_transaction() {
+        let tx = create_test_transaction();
+        assert!(tx.is_valid(&ChainParams::mainnet()).is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_inputs_rejected() {
+        let mut tx = create_test_transaction();
+        tx.inputs.push(tx.inputs[0].clone());
+        assert!(matches!(tx.check_structure(), Err(Error::DuplicateInput)));
+    }
+
+    #[test]
+    fn test_output_overflow_rejected() {
+        let mut tx = create_test_transaction();
+        tx.outputs.push(TxOutput { amount: u64::MAX, script_pubkey: vec![] });
+        assert!(matches!(tx.check_structure(), Err(Error::InvalidAmount)));
+    }
+
+    #[test]
+    fn test_verify_scripts_requires_owner_key() {
+        use crate::sighash;
+        use secp256k1::{Message, PublicKey, SecretKey, SECP256K1};
+
+        let secret = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(SECP256K1, &secret).serialize();
+        let spent = TxOutput {
+            amount: 2_000_000,
+            script_pubkey: script::p2pkh_script_pubkey(&script::hash160(&pubkey)),
+        };
+
+        // Unsigned input cannot spend
+        let mut tx = create_test_transaction();
+        tx.inputs[0].script.clear();
+        let params = ChainParams::mainnet();
+        assert!(tx.verify_scripts(&[spent.clone()], &params).is_err());
+
+        let digest = sighash::signature_hash(&tx, 0, &spent.script_pubkey, sighash::SIGHASH_ALL, params.network_id)
+            .unwrap();
+        let mut sig = SECP256K1.sign_ecdsa(&Message::from_digest(digest), &secret)
+            .serialize_der()
+            .to_vec();
+        sig.push(sighash::SIGHASH_ALL);
+        tx.inputs[0].script = script::p2pkh_script_sig(&sig, &pubkey);
+
+        assert!(tx.verify_scripts(&[spent.clone()], &params).is_ok());
+        assert!(tx.verify_scripts(&[spent.clone()], &ChainParams::regtest()).is_err());
+        assert!(tx.verify_scripts(&[], &params).is_err());
+    }
+
+    #[test]
+    fn test_oversized_transaction_rejected() {
+        let mut params = ChainParams::mainnet();
+        params.max_tx_size = 50;
+        let tx = create_test_transaction();
+        assert!(matches!(tx.is_valid(&params), Err(Error::TransactionTooLarge)));
+    }
+
+    #[test]
+    fn test_locktime_finality() {
+        let mut tx = create_test_transaction();
+        tx.locktime = 100;
+        // Every input final: locktime ignored
+        assert!(tx.is_final(1, 0));
+
+        tx.inputs[0].sequence = SEQUENCE_FINAL - 1;
+        assert!(!tx.is_final(100, 0));
+        assert!(tx.is_final(101, 0));
+
+        // Time locks compare against median-time-past
+        tx.locktime = LOCKTIME_THRESHOLD + 1_000;
+        assert!(!tx.is_final(1_000_000, LOCKTIME_THRESHOLD + 1_000));
+        assert!(tx.is_final(1, LOCKTIME_THRESHOLD + 1_001));
+    }
+
+    #[test]
+    fn test_relative_lock_decoding() {
+        let mut tx = create_test_transaction();
+        tx.inputs[0].sequence = 10;
+        // Version 1 never enforces relative locks
+        assert!(tx.relative_locks().is_empty());
+
+        tx.version = RELATIVE_LOCK_MIN_VERSION;
+        assert_eq!(tx.relative_locks(), vec![Some(RelativeLock::Blocks(10))]);
+
+        tx.inputs[0].sequence = SEQUENCE_LOCKTIME_TYPE_FLAG | 3;
+        assert_eq!(tx.relative_locks(), vec![Some(RelativeLock::Seconds(3 * 512))]);
+
+        tx.inputs[0].sequence = SEQUENCE_LOCKTIME_DISABLE_FLAG | 10;
+        assert_eq!(tx.relative_locks(), vec![None]);
+        tx.inputs[0].sequence = SEQUENCE_FINAL;
+        assert_eq!(tx.relative_locks(), vec![None]);
+    }
 }
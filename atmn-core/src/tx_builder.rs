@@ -1,19 +1,33 @@
 // atmn-core/src/tx_builder.rs
 // Transaction builder for creating and signing transactions
 
-use crate::{Transaction, Storage};
+use secp256k1::{Message, PublicKey, SecretKey, SECP256K1};
+use crate::{ChainParams, Transaction, Storage};
 use crate::transaction::{TxInput, TxOutput};
-use crate::types::{TxHash, Amount};
+use crate::types::{Address, Amount};
 use crate::error::{Error, Result};
+use crate::script;
+use crate::sighash::{self, SIGHASH_ALL};
 use crate::storage::UtxoEntry;
 
 pub struct TransactionBuilder {
     storage: Storage,
+    chain_params: ChainParams,
 }
 
 impl TransactionBuilder {
+    /// Builder for mainnet
     pub fn new(storage: Storage) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            chain_params: ChainParams::mainnet(),
+        }
+    }
+    
+    /// Sign and validate for another network
+    pub fn with_chain_params(mut self, chain_params: ChainParams) -> Self {
+        self.chain_params = chain_params;
+        self
     }
     
     /// Create a simple payment transaction
@@ -26,7 +40,7 @@ impl TransactionBuilder {
         fee: Amount,
     ) -> Result<Transaction> {
         // Get UTXOs for sender
-        let utxos = self.storage.get_utxos_for_address(from_address)?;
+        let utxos = self.storage.get_utxos_for_address(from_address, &self.chain_params)?;
         
         if utxos.is_empty() {
             return Err(Error::InvalidTransaction);
@@ -50,13 +64,18 @@ impl TransactionBuilder {
             return Err(Error::InsufficientFunds);
         }
         
-        // Create inputs
+        let to_script = script::address_to_script_pubkey(to_address, &self.chain_params)
+            .ok_or(Error::InvalidAddress)?;
+        let change_script = script::address_to_script_pubkey(from_address, &self.chain_params)
+            .ok_or(Error::InvalidAddress)?;
+        
+        // Create inputs (unsigned; scripts are filled in when signing)
         let inputs: Vec<TxInput> = selected_utxos
             .iter()
             .map(|utxo| TxInput {
                 prev_tx_hash: utxo.tx_hash.clone(),
                 prev_tx_index: utxo.output_index,
-                script: from_address.as_bytes().to_vec(), // Signature placeholder
+                script: Vec::new(),
                 sequence: 0xFFFFFFFF,
             })
             .collect();
@@ -67,7 +86,7 @@ impl TransactionBuilder {
         // Payment output
         outputs.push(TxOutput {
             amount,
-            script_pubkey: to_address.as_bytes().to_vec(),
+            script_pubkey: to_script,
         });
         
         // Change output (if any)
@@ -75,7 +94,7 @@ impl TransactionBuilder {
         if change > 0 {
             outputs.push(TxOutput {
                 amount: change,
-                script_pubkey: from_address.as_bytes().to_vec(),
+                script_pubkey: change_script,
             });
         }
         
@@ -87,45 +106,239 @@ impl TransactionBuilder {
         })
     }
     
-    /// Validate a transaction
-    pub fn validate_transaction(&self, tx: &Transaction) -> Result<()> {
-        // Check not empty
-        if tx.inputs.is_empty() || tx.outputs.is_empty() {
-            return Err(Error::InvalidTransaction);
+    /// Sign one input with `secret_key`, filling in its P2PKH unlocking script
+    pub fn sign_input(
+        &self,
+        tx: &mut Transaction,
+        input_index: usize,
+        secret_key: &SecretKey,
+        hash_type: u8,
+    ) -> Result<()> {
+        let input = tx.inputs.get(input_index).ok_or(Error::InvalidTransaction)?;
+        let utxo = self.spent_utxo(input)?;
+        
+        // The key must own the output being spent
+        let pubkey = PublicKey::from_secret_key(SECP256K1, secret_key).serialize();
+        if script::extract_p2pkh(&utxo.script_pubkey) != Some(script::hash160(&pubkey)) {
+            return Err(Error::InvalidSignature);
+        }
+        
+        let digest = sighash::signature_hash(
+            tx,
+            input_index,
+            &utxo.script_pubkey,
+            hash_type,
+            self.chain_params.network_id,
+        )?;
+        let mut signature = SECP256K1
+            .sign_ecdsa(&Message::from_digest(digest), secret_key)
+            .serialize_der()
+            .to_vec();
+        signature.push(hash_type);
+        
+        tx.inputs[input_index].script = script::p2pkh_script_sig(&signature, &pubkey);
+        Ok(())
+    }
+    
+    /// Sign every input with SIGHASH_ALL, using whichever key owns its output
+    pub fn sign_all(&self, tx: &mut Transaction, secret_keys: &[SecretKey]) -> Result<()> {
+        let owners: Vec<([u8; 20], &SecretKey)> = secret_keys
+            .iter()
+            .map(|key| {
+                let pubkey = PublicKey::from_secret_key(SECP256K1, key).serialize();
+                (script::hash160(&pubkey), key)
+            })
+            .collect();
+        
+        for index in 0..tx.inputs.len() {
+            let utxo = self.spent_utxo(&tx.inputs[index])?;
+            let owner = script::extract_p2pkh(&utxo.script_pubkey)
+                .ok_or(Error::InvalidTransaction)?;
+            let key = owners
+                .iter()
+                .find(|(hash, _)| *hash == owner)
+                .map(|(_, key)| *key)
+                .ok_or(Error::InvalidSignature)?;
+            self.sign_input(tx, index, key, SIGHASH_ALL)?;
         }
         
+        Ok(())
+    }
+    
+    /// Unspent output referenced by an input
+    fn spent_utxo(&self, input: &TxInput) -> Result<UtxoEntry> {
+        self.storage
+            .get_utxo(&input.prev_tx_hash, input.prev_tx_index)?
+            .ok_or(Error::InvalidTransaction)
+    }
+    
+    /// Validate a transaction against the UTXO set, including input scripts
+    pub fn validate_transaction(&self, tx: &Transaction) -> Result<()> {
+        tx.check_structure()?;
+        
         // Check not coinbase (coinbase can only be in block, not submitted)
         if tx.is_coinbase() {
             return Err(Error::InvalidTransaction);
         }
         
         // Verify inputs exist and are unspent
-        let mut total_input = 0u64;
+        let mut total_input: Amount = 0;
+        let mut spent_outputs = Vec::with_capacity(tx.inputs.len());
         for input in &tx.inputs {
-            let utxo_key = format!("{}:{}", input.prev_tx_hash, input.prev_tx_index);
-            // TODO: Check UTXO exists in storage
-            // For now, just check non-zero
-            total_input += 1_000_000_000; // Placeholder
+            let utxo = self.spent_utxo(input)?;
+            total_input = total_input.checked_add(utxo.amount).ok_or(Error::InvalidAmount)?;
+            spent_outputs.push(TxOutput {
+                amount: utxo.amount,
+                script_pubkey: utxo.script_pubkey,
+            });
         }
         
-        let total_output: Amount = tx.outputs.iter().map(|o| o.amount).sum();
-        
         // Verify inputs >= outputs (the difference is the fee)
-        if total_input < total_output {
+        if total_input < tx.total_output_amount() {
             return Err(Error::InsufficientFunds);
         }
         
-        Ok(())
+        // Each input must be signed by the owner of the output it spends
+        tx.verify_scripts(&spent_outputs, &self.chain_params)
     }
 }
 
+/// Parse a hex-encoded secp256k1 secret key
+pub fn parse_secret_key(hex_key: &str) -> Result<SecretKey> {
+    let bytes = hex::decode(hex_key.trim()).map_err(|_| Error::InvalidSignature)?;
+    SecretKey::from_slice(&bytes).map_err(|_| Error::InvalidSignature)
+}
+
+/// P2PKH address controlled by a secret key
+pub fn secret_key_address(secret_key: &SecretKey) -> String {
+    Address::from_secret_key(secret_key).to_legacy()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Block;
+    use crate::types::BlockHash;
+    use tempfile::TempDir;
+    
+    fn funded_builder(secret: &SecretKey, amounts: &[Amount]) -> (TransactionBuilder, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let from = secret_key_address(secret);
+        
+        for (height, amount) in amounts.iter().enumerate() {
+            let coinbase = Block::create_coinbase_tx(height as u64, &from, *amount, &ChainParams::mainnet()).unwrap();
+            let block = Block::new(1, BlockHash::zero(), vec![coinbase], 1701657600, 0x1d00ffff, height as u64);
+            storage.put_block(height as u64, &block).unwrap();
+        }
+        
+        (TransactionBuilder::new(storage), temp_dir)
+    }
     
     #[test]
     fn test_tx_builder() {
-        // Test will be implemented when storage is available
-        assert!(true);
+        let secret = SecretKey::from_slice(&[0x21; 32]).unwrap();
+        let (builder, _temp_dir) = funded_builder(&secret, &[10_000]);
+        let from = secret_key_address(&secret);
+        let to = script::pubkey_hash_to_address(&[0x33; 20]);
+        
+        let mut tx = builder.create_payment(&from, &to, 6_000, 100).unwrap();
+        assert_eq!(tx.outputs[1].amount, 3_900);
+        assert!(builder.create_payment(&from, "ATMN_recipient123", 6_000, 100).is_err());
+        
+        // Unsigned spend is rejected
+        assert!(builder.validate_transaction(&tx).is_err());
+        
+        builder.sign_all(&mut tx, &[secret]).unwrap();
+        assert!(builder.validate_transaction(&tx).is_ok());
+    }
+    
+    #[test]
+    fn test_sign_all_multiple_inputs() {
+        let secret = SecretKey::from_slice(&[0x22; 32]).unwrap();
+        let (builder, _temp_dir) = funded_builder(&secret, &[4_000, 4_000]);
+        let from = secret_key_address(&secret);
+        let to = script::pubkey_hash_to_address(&[0x44; 20]);
+        
+        let mut tx = builder.create_payment(&from, &to, 7_000, 100).unwrap();
+        assert_eq!(tx.inputs.len(), 2);
+        
+        // A key that owns nothing cannot sign
+        let stranger = SecretKey::from_slice(&[0x23; 32]).unwrap();
+        assert!(builder.sign_all(&mut tx.clone(), &[stranger]).is_err());
+        assert!(builder.sign_input(&mut tx.clone(), 0, &stranger, SIGHASH_ALL).is_err());
+        
+        builder.sign_all(&mut tx, &[stranger, secret]).unwrap();
+        assert!(builder.validate_transaction(&tx).is_ok());
+        
+        // Signatures do not carry over to another network
+        let regtest = TransactionBuilder::new(builder.storage.clone())
+            .with_chain_params(ChainParams::regtest());
+        assert!(regtest.validate_transaction(&tx).is_err());
+    }
+    
+    #[test]
+    fn test_anyonecanpay_allows_extra_inputs() {
+        let secret = SecretKey::from_slice(&[0x24; 32]).unwrap();
+        let (builder, _temp_dir) = funded_builder(&secret, &[5_000, 5_000]);
+        let from = secret_key_address(&secret);
+        let to = script::pubkey_hash_to_address(&[0x55; 20]);
+        
+        let mut tx = builder.create_payment(&from, &to, 4_000, 100).unwrap();
+        assert_eq!(tx.inputs.len(), 1);
+        builder.sign_input(&mut tx, 0, &secret, SIGHASH_ALL | sighash::SIGHASH_ANYONECANPAY).unwrap();
+        
+        // Add a second input after signing; the first signature stays valid
+        let other = builder.storage.get_utxos_for_address(&from, &builder.chain_params).unwrap()
+            .into_iter()
+            .find(|u| u.tx_hash != tx.inputs[0].prev_tx_hash)
+            .unwrap();
+        tx.inputs.push(TxInput {
+            prev_tx_hash: other.tx_hash,
+            prev_tx_index: other.output_index,
+            script: Vec::new(),
+            sequence: 0xFFFFFFFF,
+        });
+        builder.sign_input(&mut tx, 1, &secret, SIGHASH_ALL).unwrap();
+        assert!(builder.validate_transaction(&tx).is_ok());
+    }
+    
+    #[test]
+    fn test_pay_and_spend_checksummed_address() {
+        let params = ChainParams::mainnet();
+        let secret = SecretKey::from_slice(&[0x25; 32]).unwrap();
+        let owner = Address::from_secret_key(&secret);
+        let from = owner.encode(&params);
+        let to = Address::from_bytes([0x66; 20]).encode(&params);
+        
+        // A coinbase pays the checksummed address a standard output
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path()).unwrap();
+        let coinbase = Block::create_coinbase_tx(0, &from, 10_000, &params).unwrap();
+        assert_eq!(coinbase.outputs[0].script_pubkey, owner.script_pubkey());
+        storage.put_block(0, &Block::new(1, BlockHash::zero(), vec![coinbase], 1701657600, 0x1d00ffff, 0)).unwrap();
+        assert_eq!(storage.get_balance(&from, &params).unwrap(), 10_000);
+        assert_eq!(storage.get_balance(&owner.to_legacy(), &params).unwrap(), 10_000);
+        assert_eq!(storage.get_balance(&owner.encode(&ChainParams::testnet()), &params).unwrap(), 0);
+        
+        // And it can be spent to another checksummed address
+        let builder = TransactionBuilder::new(storage.clone());
+        let mut tx = builder.create_payment(&from, &to, 6_000, 100).unwrap();
+        builder.sign_all(&mut tx, &[secret]).unwrap();
+        builder.validate_transaction(&tx).unwrap();
+        
+        let coinbase = Block::create_coinbase_tx(1, &from, 100, &params).unwrap();
+        let parent = storage.get_block(0).unwrap().unwrap().hash();
+        storage.put_block(1, &Block::new(1, parent, vec![coinbase, tx], 1701657601, 0x1d00ffff, 1)).unwrap();
+        assert_eq!(storage.get_balance(&to, &params).unwrap(), 6_000);
+        assert_eq!(storage.get_balance(&from, &params).unwrap(), 4_000);
+    }
+    
+    #[test]
+    fn test_parse_secret_key() {
+        let secret = parse_secret_key(&"21".repeat(32)).unwrap();
+        assert_eq!(secret, SecretKey::from_slice(&[0x21; 32]).unwrap());
+        assert!(parse_secret_key("not hex").is_err());
+        assert!(parse_secret_key(&"00".repeat(32)).is_err());
     }
 }
@@ -48,6 +48,25 @@ impl fmt::Display for TxHash {
     }
 }
 
+/// Reference to a transaction output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub tx_hash: TxHash,
+    pub index: u32,
+}
+
+impl OutPoint {
+    pub fn new(tx_hash: TxHash, index: u32) -> Self {
+        OutPoint { tx_hash, index }
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.tx_hash, self.index)
+    }
+}
+
 /// Block Height (block number)
 pub type BlockHeight = u64;
 
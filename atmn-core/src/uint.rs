@@ -0,0 +1,361 @@
+// atmn-core/src/uint.rs
+// 256-bit unsigned integer for targets and chainwork
+//
+// Just enough arithmetic for proof-of-work: comparison, add/sub, shifts,
+// multiplication by u64, full division and the compact ("bits") encoding.
+// Limbs are little-endian; byte conversions are big-endian to match how
+// targets and block hashes are compared elsewhere.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Not, Shl, Shr, Sub};
+
+/// Unsigned 256-bit integer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(n: u64) -> Self {
+        U256([n, 0, 0, 0])
+    }
+
+    pub fn from_u128(n: u128) -> Self {
+        U256([n as u64, (n >> 64) as u64, 0, 0])
+    }
+
+    /// Parse from 32 big-endian bytes
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 32 - 8 * (i + 1);
+            *limb = u64::from_be_bytes(bytes[start..start + 8].try_into().unwrap());
+        }
+        U256(limbs)
+    }
+
+    /// Serialize as 32 big-endian bytes
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 32 - 8 * (i + 1);
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    /// Number of significant bits
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + (64 - self.0[i].leading_zeros());
+            }
+        }
+        0
+    }
+
+    /// Lowest 64 bits
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// Value as u64, saturating
+    pub fn saturating_to_u64(&self) -> u64 {
+        if self.bits() > 64 { u64::MAX } else { self.0[0] }
+    }
+
+    pub fn overflowing_add(self, other: U256) -> (U256, bool) {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for i in 0..4 {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            result[i] = sum;
+            carry = c1 || c2;
+        }
+        (U256(result), carry)
+    }
+
+    pub fn checked_add(self, other: U256) -> Option<U256> {
+        match self.overflowing_add(other) {
+            (sum, false) => Some(sum),
+            _ => None,
+        }
+    }
+
+    pub fn saturating_add(self, other: U256) -> U256 {
+        self.checked_add(other).unwrap_or(U256::MAX)
+    }
+
+    pub fn overflowing_sub(self, other: U256) -> (U256, bool) {
+        let mut result = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            result[i] = diff;
+            borrow = b1 || b2;
+        }
+        (U256(result), borrow)
+    }
+
+    pub fn checked_sub(self, other: U256) -> Option<U256> {
+        match self.overflowing_sub(other) {
+            (diff, false) => Some(diff),
+            _ => None,
+        }
+    }
+
+    /// Multiply by a u64, or None on overflow
+    pub fn checked_mul_u64(self, other: u64) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut carry = 0u128;
+        for i in 0..4 {
+            let product = self.0[i] as u128 * other as u128 + carry;
+            result[i] = product as u64;
+            carry = product >> 64;
+        }
+        if carry == 0 { Some(U256(result)) } else { None }
+    }
+
+    /// Quotient and remainder, or None when dividing by zero
+    pub fn checked_div_rem(self, divisor: U256) -> Option<(U256, U256)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        if self < divisor {
+            return Some((U256::ZERO, self));
+        }
+
+        // Shift-subtract long division
+        let shift = self.bits() - divisor.bits();
+        let mut remainder = self;
+        let mut quotient = U256::ZERO;
+        let mut denominator = divisor << shift;
+        for i in (0..=shift).rev() {
+            if remainder >= denominator {
+                remainder = remainder - denominator;
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+            denominator = denominator >> 1;
+        }
+        Some((quotient, remainder))
+    }
+
+    /// Decode compact bits; returns (value, negative, overflow) like Bitcoin's SetCompact
+    pub fn from_compact(bits: u32) -> (U256, bool, bool) {
+        let size = bits >> 24;
+        let mut word = bits & 0x007fffff;
+        let value = if size <= 3 {
+            word >>= 8 * (3 - size);
+            U256::from_u64(word as u64)
+        } else {
+            U256::from_u64(word as u64) << (8 * (size - 3))
+        };
+
+        let negative = word != 0 && (bits & 0x00800000) != 0;
+        let overflow = word != 0
+            && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+        (value, negative, overflow)
+    }
+
+    /// Encode as compact bits, like Bitcoin's GetCompact
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (*self >> (8 * (size - 3))).low_u64() as u32
+        };
+
+        // The 0x00800000 bit is the sign; keep the mantissa positive
+        if compact & 0x00800000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+        compact | (size << 24)
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for U256 {
+    type Output = U256;
+
+    fn add(self, other: U256) -> U256 {
+        self.checked_add(other).expect("U256 addition overflow")
+    }
+}
+
+impl Sub for U256 {
+    type Output = U256;
+
+    fn sub(self, other: U256) -> U256 {
+        self.checked_sub(other).expect("U256 subtraction underflow")
+    }
+}
+
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, other: U256) -> U256 {
+        self.checked_div_rem(other).expect("U256 division by zero").0
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256(self.0.map(|limb| !limb))
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for i in limbs..4 {
+            result[i] = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                result[i] |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for i in 0..4usize.saturating_sub(limbs) {
+            result[i] = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                result[i] |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_be_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_round_trip() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0x80;
+        bytes[31] = 0x01;
+        let n = U256::from_be_bytes(bytes);
+        assert_eq!(n.to_be_bytes(), bytes);
+        assert_eq!(n.bits(), 256);
+        assert_eq!(n.low_u64(), 1);
+    }
+
+    #[test]
+    fn test_add_sub_carry() {
+        let a = U256::from_u64(u64::MAX);
+        let b = a + U256::ONE;
+        assert_eq!(b, U256::ONE << 64);
+        assert_eq!(b - U256::ONE, a);
+        assert!(U256::MAX.checked_add(U256::ONE).is_none());
+        assert!(U256::ZERO.checked_sub(U256::ONE).is_none());
+        assert_eq!(U256::MAX.saturating_add(U256::ONE), U256::MAX);
+    }
+
+    #[test]
+    fn test_shifts() {
+        let one = U256::ONE;
+        assert_eq!((one << 255) >> 255, one);
+        assert_eq!((one << 100).bits(), 101);
+        assert_eq!((one << 255) >> 200, one << 55);
+        assert_eq!(U256::MAX >> 192, U256::from_u64(u64::MAX));
+    }
+
+    #[test]
+    fn test_mul_div() {
+        let n = U256::from_u128(0x1234_5678_9abc_def0_1122_3344_5566_7788);
+        let product = n.checked_mul_u64(1_000_000).unwrap();
+        let (quotient, remainder) = product.checked_div_rem(U256::from_u64(1_000_000)).unwrap();
+        assert_eq!(quotient, n);
+        assert!(remainder.is_zero());
+
+        assert_eq!(U256::MAX / U256::MAX, U256::ONE);
+        assert_eq!((U256::ONE << 200) / (U256::ONE << 100), U256::ONE << 100);
+        assert!(U256::MAX.checked_mul_u64(2).is_none());
+        assert!(U256::ONE.checked_div_rem(U256::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_compact_vectors() {
+        // Bitcoin Core arith_uint256 SetCompact/GetCompact vectors
+        for bits in [0x00000000, 0x00123456, 0x01003456, 0x02000056, 0x03000000, 0x04000000,
+                     0x00923456, 0x01803456, 0x02800056, 0x03800000, 0x04800000] {
+            let (value, negative, overflow) = U256::from_compact(bits);
+            assert!(value.is_zero() && !negative && !overflow, "bits {:08x}", bits);
+            assert_eq!(value.to_compact(), 0);
+        }
+
+        let cases = [
+            (0x01123456, U256::from_u64(0x12), 0x01120000),
+            (0x02123456, U256::from_u64(0x1234), 0x02123400),
+            (0x03123456, U256::from_u64(0x123456), 0x03123456),
+            (0x04123456, U256::from_u64(0x12345600), 0x04123456),
+            (0x05009234, U256::from_u64(0x92340000), 0x05009234),
+            (0x20123456, U256::from_u64(0x123456) << 232, 0x20123456),
+        ];
+        for (bits, expected, compact) in cases {
+            let (value, negative, overflow) = U256::from_compact(bits);
+            assert_eq!(value, expected, "bits {:08x}", bits);
+            assert!(!negative && !overflow);
+            assert_eq!(value.to_compact(), compact);
+        }
+
+        let (value, negative, _) = U256::from_compact(0x01fedcba);
+        assert_eq!(value, U256::from_u64(0x7e));
+        assert!(negative);
+        let (value, negative, _) = U256::from_compact(0x04923456);
+        assert_eq!(value, U256::from_u64(0x12345600));
+        assert!(negative);
+
+        let (_, _, overflow) = U256::from_compact(0xff123456);
+        assert!(overflow);
+    }
+}
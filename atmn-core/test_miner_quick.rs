@@ -14,6 +14,7 @@ fn main() {
         difficulty_bits: 0x20ffffff,  // Extremely easy
         version: 1,
         template_time: 1704067200,
+        total_fees: 0,
     };
     
     println!("Starting mine...");
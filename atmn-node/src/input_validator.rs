@@ -1,25 +1,40 @@
 /// Input validation for all user-provided data
 use std::net::IpAddr;
 
+/// Human-readable prefix of mainnet addresses
+const ADDRESS_HRP: &str = "atmn";
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Checksum constant of bech32m (BIP350)
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
 pub struct InputValidator;
 
 impl InputValidator {
-    /// Validate ATMN address format
+    /// Validate an ATMN address: bech32m with the mainnet prefix, version 0
+    /// and a 20-byte public key hash, matching `atmn_core::address`
     pub fn validate_address(addr: &str) -> bool {
-        // ATMN addresses start with "atmn1" and are 42-62 chars
-        if !addr.starts_with("atmn1") {
+        // "atmn1", the version, 32 payload characters and 6 checksum characters
+        if addr.len() != ADDRESS_HRP.len() + 1 + 1 + 32 + 6 {
             return false;
         }
-        
-        let len = addr.len();
-        if len < 42 || len > 62 {
+        let Some(data) = addr.strip_prefix(ADDRESS_HRP).and_then(|rest| rest.strip_prefix('1')) else {
             return false;
+        };
+        
+        let mut values: Vec<u8> = ADDRESS_HRP.bytes().map(|b| b >> 5).collect();
+        values.push(0);
+        values.extend(ADDRESS_HRP.bytes().map(|b| b & 0x1f));
+        for c in data.bytes() {
+            match BECH32_CHARSET.iter().position(|&x| x == c) {
+                Some(value) => values.push(value as u8),
+                None => return false,
+            }
         }
         
-        // Check for valid characters (bech32)
-        addr.chars().all(|c| {
-            c.is_ascii_lowercase() || c.is_ascii_digit()
-        })
+        // 32 payload characters hold exactly 160 bits, so there is no padding to check
+        data.starts_with('q') && bech32m_polymod(&values) == BECH32M_CONST
     }
 
     /// Validate transaction amount
@@ -42,15 +57,35 @@ impl InputValidator {
     }
 }
 
+/// BCH checksum over 5-bit values
+fn bech32m_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for &value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_address_validation() {
-        assert!(InputValidator::validate_address("atmn1qyqszqgpqyqszqgpqyqszqgpqyqszqgpq5n3xd2"));
+        assert!(InputValidator::validate_address("atmn1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5knek8x"));
         assert!(!InputValidator::validate_address("invalid"));
         assert!(!InputValidator::validate_address("btc1qyqszqgpq"));
+        // One mistyped character fails the checksum
+        assert!(!InputValidator::validate_address("atmn1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5knek8z"));
+        // Shaped like an address, but never checksummed
+        assert!(!InputValidator::validate_address("atmn1qyqszqgpqyqszqgpqyqszqgpqyqszqgpq5n3xd2"));
     }
 
     #[test]
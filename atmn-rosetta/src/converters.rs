@@ -1,6 +1,7 @@
 // Converters between atmn-core types and Rosetta types
-use atmn_core::Block;
+use atmn_core::{Block, ChainParams};
 use atmn_core::Transaction as CoreTransaction;
+use atmn_core::script;
 use atmn_core::types::BlockHash;
 use crate::types::*;
 
@@ -44,10 +45,10 @@ pub fn block_to_rosetta(block: &Block, height: u64) -> crate::types::Block {
 /// Convert atmn-core Transaction to Rosetta Transaction
 pub fn transaction_to_rosetta(
     tx: &CoreTransaction,
-    tx_index: usize,
+    _tx_index: usize,
     block_height: u64,
 ) -> Transaction {
-    let tx_hash = format!("{:064x}", tx_index); // Placeholder until we have real tx hashing
+    let tx_hash = tx.hash().to_string();
     
     let mut operations = Vec::new();
     let mut op_index = 0;
@@ -81,11 +82,8 @@ pub fn transaction_to_rosetta(
 
     // Add outputs as positive operations
     for (vout_idx, output) in tx.outputs.iter().enumerate() {
-        let address_bytes = if output.script_pubkey.len() >= 8 {
-            hex::encode(&output.script_pubkey[..8])
-        } else {
-            hex::encode(&output.script_pubkey)
-        };
+        let address = script::script_pubkey_to_address(&output.script_pubkey, &ChainParams::mainnet())
+            .unwrap_or_else(|| String::from_utf8_lossy(&output.script_pubkey).to_string());
         
         operations.push(Operation {
             operation_identifier: OperationIdentifier {
@@ -96,7 +94,7 @@ pub fn transaction_to_rosetta(
             r#type: "TRANSFER".to_string(),
             status: Some("SUCCESS".to_string()),
             account: Some(AccountIdentifier {
-                address: format!("ATMN_{}", address_bytes), // Placeholder
+                address,
                 sub_account: None,
                 metadata: None,
             }),
@@ -4,6 +4,12 @@ use crate::types::*;
 use crate::error::{ApiError, ApiResult};
 use crate::converters::*;
 use crate::AppState;
+use atmn_core::{ChainParams, FeeEstimator, MempoolConfig};
+use atmn_core::fee_estimator::DEFAULT_CONFIRMATION_TARGET;
+
+/// Size assumed for fee suggestions when the caller gives none: a typical
+/// one-input, two-output payment, in bytes
+const DEFAULT_TX_SIZE: usize = 250;
 
 /// Health check endpoint
 pub async fn health() -> &'static str {
@@ -230,7 +236,7 @@ pub async fn account_balance(
     };
 
     // Query UTXO set for this address
-    let utxos = state.storage.get_utxos_for_address(address)
+    let utxos = state.storage.get_utxos_for_address(address, &ChainParams::mainnet())
         .map_err(|e| ApiError::Internal(e.to_string()))?;
 
     // Calculate total balance in satoshis
@@ -297,7 +303,7 @@ pub async fn account_coins(
     };
 
     // Query UTXO set for this address
-    let utxos = state.storage.get_utxos_for_address(address)
+    let utxos = state.storage.get_utxos_for_address(address, &ChainParams::mainnet())
         .map_err(|e| ApiError::Internal(e.to_string()))?;
 
     // Convert UTXOs to Rosetta Coins
@@ -329,10 +335,54 @@ pub async fn construction_preprocess(
 }
 
 /// /construction/metadata - Get dynamic metadata
+///
+/// Suggests a fee from the node's fee estimator. `options` may carry
+/// `confirmation_target` (blocks) and `estimated_size` (bytes).
 pub async fn construction_metadata(
-    Json(_req): Json<serde_json::Value>,
-) -> ApiResult<Json<serde_json::Value>> {
-    Err(ApiError::NotImplemented("construction/metadata".to_string()))
+    State(state): State<AppState>,
+    Json(req): Json<ConstructionMetadataRequest>,
+) -> ApiResult<Json<ConstructionMetadataResponse>> {
+    if !is_mainnet(&req.network_identifier) {
+        return Err(ApiError::NetworkNotFound(req.network_identifier.network));
+    }
+
+    let option = |key: &str| {
+        req.options.as_ref()
+            .and_then(|options| options.get(key))
+            .and_then(|value| value.as_u64())
+    };
+    let target_blocks = option("confirmation_target").map_or(DEFAULT_CONFIRMATION_TARGET, |t| t as usize);
+    let size = option("estimated_size").map_or(DEFAULT_TX_SIZE, |s| s as usize);
+
+    let estimator = FeeEstimator::load(&state.fee_estimates_path)
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(suggest_fee(&estimator, target_blocks, size)))
+}
+
+/// Fee for a `size`-byte transaction to confirm within `target_blocks`,
+/// falling back to the relay minimum while there is too little history
+fn suggest_fee(estimator: &FeeEstimator, target_blocks: usize, size: usize) -> ConstructionMetadataResponse {
+    let (fee_per_kb, metadata) = match estimator.estimate_fee(target_blocks) {
+        Some(estimate) => (estimate.fee_per_kb, serde_json::json!({
+            "fee_per_kb": estimate.fee_per_kb,
+            "confirmation_target": estimate.target_blocks,
+            "confidence": estimate.confidence,
+        })),
+        None => {
+            let min_fee_per_kb = MempoolConfig::default().min_fee_per_byte * 1000;
+            (min_fee_per_kb, serde_json::json!({
+                "fee_per_kb": min_fee_per_kb,
+                "confirmation_target": target_blocks,
+                "confidence": null,
+            }))
+        }
+    };
+    let fee = (fee_per_kb as u128 * size as u128).div_ceil(1000) as i64;
+
+    ConstructionMetadataResponse {
+        metadata,
+        suggested_fee: Some(vec![Amount::new(fee, Currency::atmn())]),
+    }
 }
 
 /// /construction/payloads - Create payloads to sign
@@ -393,6 +443,17 @@ mod tests {
         assert!(result.0.allow.operation_types.contains(&"TRANSFER".to_string()));
     }
 
+    #[test]
+    fn test_suggest_fee_without_history() {
+        let response = suggest_fee(&FeeEstimator::new(), 3, 400);
+        let min_fee_per_kb = MempoolConfig::default().min_fee_per_byte * 1000;
+        assert_eq!(response.metadata["fee_per_kb"], min_fee_per_kb);
+        assert_eq!(response.metadata["confirmation_target"], 3);
+        let fee = &response.suggested_fee.unwrap()[0];
+        assert_eq!(fee.value, (min_fee_per_kb * 400).div_ceil(1000).to_string());
+        assert_eq!(fee.currency, Currency::atmn());
+    }
+
     #[tokio::test]
     async fn test_health() {
         let result = health().await;
@@ -4,10 +4,12 @@ use axum::{
     extract::State,
 };
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use atmn_core::Storage;
+use atmn_core::fee_estimator::FEE_ESTIMATES_FILE_NAME;
 
 mod handlers;
 mod types;
@@ -18,6 +20,8 @@ mod converters;
 #[derive(Clone)]
 pub struct AppState {
     pub storage: Arc<Storage>,
+    /// Fee estimator history saved by the node, read for suggested fees
+    pub fee_estimates_path: PathBuf,
 }
 
 #[tokio::main]
@@ -46,6 +50,7 @@ async fn main() {
     
     let state = AppState {
         storage: Arc::new(storage),
+        fee_estimates_path: PathBuf::from(&storage_path).join(FEE_ESTIMATES_FILE_NAME),
     };
 
     // Build CORS layer